@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS attachments;
+DROP TABLE IF EXISTS note_tags;
+DROP TABLE IF EXISTS tags;
+DROP TABLE IF EXISTS notes;
+DROP TYPE IF EXISTS note_color;
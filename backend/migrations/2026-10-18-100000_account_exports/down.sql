@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS account_exports;
+DROP TYPE IF EXISTS export_status;
+DROP TABLE IF EXISTS login_events;
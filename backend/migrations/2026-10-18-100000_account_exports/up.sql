@@ -0,0 +1,26 @@
+CREATE TABLE login_events (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    success BOOLEAN NOT NULL,
+    ip_address VARCHAR(45),
+    user_agent TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX login_events_user_id_idx ON login_events (user_id, created_at);
+
+CREATE TYPE export_status AS ENUM ('pending', 'ready', 'failed');
+
+CREATE TABLE account_exports (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    status export_status NOT NULL DEFAULT 'pending',
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    storage_key VARCHAR(255),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX account_exports_user_id_idx ON account_exports (user_id);
+CREATE INDEX account_exports_expires_at_idx ON account_exports (expires_at);
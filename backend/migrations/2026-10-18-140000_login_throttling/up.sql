@@ -0,0 +1,11 @@
+ALTER TABLE users
+    ADD COLUMN failed_login_attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN locked_until TIMESTAMP WITH TIME ZONE;
+
+-- Token buckets for the Postgres rate limit store. Only used when several
+-- instances have to share their limits.
+CREATE TABLE rate_limit_buckets (
+    key VARCHAR(320) NOT NULL PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+-- PHC strings grow with stronger argon2 parameters.
+ALTER TABLE users ALTER COLUMN password TYPE VARCHAR(255);
@@ -0,0 +1 @@
+DROP TRIGGER IF EXISTS set_updated_at ON users;
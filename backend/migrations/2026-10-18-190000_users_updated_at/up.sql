@@ -0,0 +1 @@
+SELECT diesel_manage_updated_at('users');
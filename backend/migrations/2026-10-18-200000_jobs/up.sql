@@ -0,0 +1,23 @@
+CREATE TYPE job_status AS ENUM ('pending', 'running', 'completed', 'dead');
+
+CREATE TABLE jobs (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    kind VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    status job_status NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    -- When a pending job becomes due.
+    run_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- A running job whose worker has not finished by then is picked up again.
+    locked_until TIMESTAMP WITH TIME ZONE,
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX jobs_due_idx ON jobs (run_at) WHERE status IN ('pending', 'running');
+CREATE INDEX jobs_status_idx ON jobs (status, kind, created_at);
+
+SELECT diesel_manage_updated_at('jobs');
@@ -0,0 +1,3 @@
+CREATE TYPE locale AS ENUM ('en', 'de');
+
+ALTER TABLE users ADD COLUMN locale locale NOT NULL DEFAULT 'en';
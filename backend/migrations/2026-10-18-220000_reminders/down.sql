@@ -0,0 +1,4 @@
+DROP TABLE notifications;
+DROP TYPE notification_kind;
+DROP TABLE note_reminders;
+ALTER TABLE users DROP COLUMN time_zone;
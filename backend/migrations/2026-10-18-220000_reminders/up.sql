@@ -0,0 +1,36 @@
+-- IANA name, e.g. `Europe/Berlin`. Recurring reminders keep their local
+-- time across daylight saving changes in it.
+ALTER TABLE users ADD COLUMN time_zone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+
+CREATE TABLE note_reminders (
+    note_id UUID NOT NULL PRIMARY KEY REFERENCES notes (id) ON DELETE CASCADE,
+    -- When the note is due; for recurring reminders, the current occurrence.
+    remind_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- Subset of an RFC 5545 RRULE, relative to remind_at.
+    recurrence VARCHAR(255),
+    -- When the reminder goes out: remind_at, or later when snoozed. NULL once
+    -- it has gone out.
+    notify_at TIMESTAMP WITH TIME ZONE,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX note_reminders_notify_at_idx ON note_reminders (notify_at)
+    WHERE notify_at IS NOT NULL;
+SELECT diesel_manage_updated_at('note_reminders');
+
+CREATE TYPE notification_kind AS ENUM ('reminder');
+
+CREATE TABLE notifications (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    kind notification_kind NOT NULL,
+    note_id UUID REFERENCES notes (id) ON DELETE CASCADE,
+    -- What clients need to show the notification; depends on the kind.
+    data JSONB NOT NULL DEFAULT '{}',
+    read_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX notifications_user_id_idx ON notifications (user_id, created_at);
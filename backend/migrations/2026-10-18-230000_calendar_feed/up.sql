@@ -0,0 +1,3 @@
+-- SHA-256 of the secret in the calendar feed URL. Replacing it revokes the
+-- old URL.
+ALTER TABLE users ADD COLUMN calendar_token_hash VARCHAR(64) UNIQUE;
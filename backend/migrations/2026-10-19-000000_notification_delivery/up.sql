@@ -0,0 +1,23 @@
+-- Missing rows mean both in-app and email.
+CREATE TABLE notification_preferences (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    kind notification_kind NOT NULL,
+    in_app BOOLEAN NOT NULL DEFAULT TRUE,
+    email BOOLEAN NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (user_id, kind)
+);
+
+CREATE INDEX notifications_unread_idx ON notifications (user_id) WHERE read_at IS NULL;
+
+-- Tells every backend instance about new notifications once they are
+-- committed, so they can be pushed to the clients connected to it. The
+-- payload is `<user id> <notification id>`.
+CREATE FUNCTION notify_new_notification() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('notifications', NEW.user_id || ' ' || NEW.id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notifications_notify AFTER INSERT ON notifications
+    FOR EACH ROW EXECUTE FUNCTION notify_new_notification();
@@ -0,0 +1,12 @@
+DROP TABLE note_comments;
+
+-- Enum values cannot be dropped, so the type is replaced.
+DELETE FROM notifications WHERE kind = 'mention';
+DELETE FROM notification_preferences WHERE kind = 'mention';
+ALTER TYPE notification_kind RENAME TO notification_kind_old;
+CREATE TYPE notification_kind AS ENUM ('reminder');
+ALTER TABLE notifications
+    ALTER COLUMN kind TYPE notification_kind USING kind::TEXT::notification_kind;
+ALTER TABLE notification_preferences
+    ALTER COLUMN kind TYPE notification_kind USING kind::TEXT::notification_kind;
+DROP TYPE notification_kind_old;
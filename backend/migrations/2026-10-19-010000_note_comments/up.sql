@@ -0,0 +1,23 @@
+ALTER TYPE notification_kind ADD VALUE 'mention';
+
+CREATE TABLE note_comments (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    note_id UUID NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- The first comment of the thread; NULL for that comment itself.
+    parent_id UUID REFERENCES note_comments (id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    -- Part of the note's content that a thread is about, in characters, and
+    -- the text it held when the thread was started.
+    anchor_start INTEGER,
+    anchor_end INTEGER,
+    anchor_text TEXT,
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    resolved_by UUID REFERENCES users (id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX note_comments_note_id_idx ON note_comments (note_id, created_at);
+CREATE INDEX note_comments_parent_id_idx ON note_comments (parent_id);
+SELECT diesel_manage_updated_at('note_comments');
@@ -0,0 +1,2 @@
+DROP TABLE note_shares;
+DROP TYPE share_permission;
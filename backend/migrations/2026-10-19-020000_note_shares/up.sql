@@ -0,0 +1,14 @@
+CREATE TYPE share_permission AS ENUM ('viewer', 'commenter');
+
+-- People other than the owner who may read a note and its comments.
+CREATE TABLE note_shares (
+    note_id UUID NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    permission share_permission NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (note_id, user_id)
+);
+
+CREATE INDEX note_shares_user_id_idx ON note_shares (user_id);
+SELECT diesel_manage_updated_at('note_shares');
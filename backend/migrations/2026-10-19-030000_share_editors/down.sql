@@ -0,0 +1,7 @@
+-- Enum values cannot be dropped, so the type is replaced.
+UPDATE note_shares SET permission = 'commenter' WHERE permission = 'editor';
+ALTER TYPE share_permission RENAME TO share_permission_old;
+CREATE TYPE share_permission AS ENUM ('viewer', 'commenter');
+ALTER TABLE note_shares
+    ALTER COLUMN permission TYPE share_permission USING permission::TEXT::share_permission;
+DROP TYPE share_permission_old;
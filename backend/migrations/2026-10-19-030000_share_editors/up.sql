@@ -0,0 +1 @@
+ALTER TYPE share_permission ADD VALUE 'editor';
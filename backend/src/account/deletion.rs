@@ -0,0 +1,170 @@
+//! Account deletion. Deleting only schedules the account for removal; logging
+//! in during the grace period cancels it, afterwards a periodic task removes
+//! the user together with everything stored for them.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::auth::{password, session};
+use crate::config::SharedNotesPolicy;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::mail::{self, Email};
+use crate::models::share::SharePermission;
+use crate::models::user::User;
+use crate::schema::{account_exports, attachments, note_reminders, note_shares, notes, users};
+use crate::state::AppState;
+
+/// Schedules the account for deletion after the configured grace period.
+/// The password has to be confirmed again.
+pub async fn schedule(
+    state: &AppState,
+    user_id: Uuid,
+    confirm_password: String,
+) -> AppResult<DateTime<Utc>> {
+    let deletion_at = Utc::now() + state.deletion_grace;
+    db::run(&state.pool, move |conn| {
+        let user: User = users::table
+            .find(user_id)
+            .select(User::as_select())
+            .first(conn)?;
+        if !password::verify(&confirm_password, &user.password) {
+            return Err(AppError::Forbidden("password is incorrect".into()));
+        }
+
+        conn.transaction(|conn| {
+            diesel::update(users::table.find(user_id))
+                .set(users::deletion_scheduled_at.eq(deletion_at))
+                .execute(conn)?;
+            // Logging in again is how the deletion gets cancelled.
+            session::revoke_all(conn, user_id, None)?;
+            mail::enqueue(
+                conn,
+                &user.email,
+                user.locale,
+                user.tz(),
+                Email::DeletionScheduled {
+                    name: user.name.clone(),
+                    deletion_at,
+                },
+            )?;
+            Ok::<_, AppError>(())
+        })
+    })
+    .await?;
+
+    Ok(deletion_at)
+}
+
+/// Cancels a pending deletion. Returns whether one was pending.
+pub fn cancel(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<bool> {
+    let updated = diesel::update(users::table.find(user_id))
+        .filter(users::deletion_scheduled_at.is_not_null())
+        .set(users::deletion_scheduled_at.eq(None::<DateTime<Utc>>))
+        .execute(conn)?;
+    Ok(updated > 0)
+}
+
+/// Permanently deletes every account whose grace period has run out.
+pub async fn purge_due(state: AppState) -> AppResult<usize> {
+    let due: Vec<Uuid> = db::run(&state.pool, |conn| {
+        users::table
+            .filter(users::deletion_scheduled_at.le(Utc::now()))
+            .select(users::id)
+            .load(conn)
+    })
+    .await?;
+
+    let mut purged = 0;
+    let shared_notes = state.deletion_shared_notes;
+    for user_id in due {
+        let Some(blob_keys) = db::run(&state.pool, move |conn| {
+            purge_user(conn, user_id, shared_notes)
+        })
+        .await?
+        else {
+            continue;
+        };
+
+        let store = state.store.clone();
+        tokio::task::spawn_blocking(move || {
+            for key in &blob_keys {
+                if let Err(err) = store.delete(key) {
+                    tracing::warn!(%user_id, "failed to delete blob {key}: {err}");
+                }
+            }
+        })
+        .await?;
+        tracing::info!(%user_id, "deleted account");
+        purged += 1;
+    }
+
+    Ok(purged)
+}
+
+/// Deletes the user row, which cascades to notes, tags, attachments, exports
+/// and login history. Returns the blob keys that are no longer referenced,
+/// or `None` if the user was not deleted after all: the deadline is checked
+/// again so a login that raced the purge wins.
+fn purge_user(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    shared_notes: SharedNotesPolicy,
+) -> QueryResult<Option<Vec<String>>> {
+    conn.transaction(|conn| {
+        let still_due = users::table
+            .find(user_id)
+            .filter(users::deletion_scheduled_at.le(Utc::now()))
+            .select(users::id)
+            .for_update()
+            .first::<Uuid>(conn)
+            .optional()?
+            .is_some();
+        if !still_due {
+            return Ok(None);
+        }
+        if shared_notes == SharedNotesPolicy::Transfer {
+            transfer_shared_notes(conn, user_id)?;
+        }
+
+        let mut keys: Vec<String> = attachments::table
+            .inner_join(notes::table)
+            .filter(notes::user_id.eq(user_id))
+            .select(attachments::storage_key)
+            .load(conn)?;
+        keys.extend(
+            account_exports::table
+                .filter(account_exports::user_id.eq(user_id))
+                .select(account_exports::storage_key)
+                .load::<Option<String>>(conn)?
+                .into_iter()
+                .flatten(),
+        );
+
+        diesel::delete(users::table.find(user_id)).execute(conn)?;
+        Ok(Some(keys))
+    })
+}
+
+/// Hands each of `user_id`'s notes that has an editor to the one it was
+/// shared with first, who then owns it instead of sharing it. The reminder
+/// was the old owner's and goes; the tags go with the old owner's account.
+fn transfer_shared_notes(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<()> {
+    let heirs: Vec<(Uuid, Uuid)> = note_shares::table
+        .inner_join(notes::table)
+        .filter(notes::user_id.eq(user_id))
+        .filter(note_shares::permission.eq(SharePermission::Editor))
+        .distinct_on(note_shares::note_id)
+        .order((note_shares::note_id, note_shares::created_at.asc()))
+        .select((note_shares::note_id, note_shares::user_id))
+        .load(conn)?;
+    for (note_id, heir_id) in heirs {
+        diesel::update(notes::table.find(note_id))
+            .set(notes::user_id.eq(heir_id))
+            .execute(conn)?;
+        diesel::delete(note_shares::table.find((note_id, heir_id))).execute(conn)?;
+        diesel::delete(note_reminders::table.find(note_id)).execute(conn)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Changing the email address of an account. The new address only takes
+//! effect once a link sent to it has been opened. The old address is told
+//! about the request and gets a link to cancel it, in case someone else
+//! got hold of the account.
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use uuid::Uuid;
+
+use crate::auth::{password, token};
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::mail::{self, Email};
+use crate::models::email_change::EmailChange;
+use crate::models::user::User;
+use crate::schema::{email_changes, users};
+use crate::state::AppState;
+
+/// Records the requested change, replacing any earlier one, and sends the
+/// confirmation and cancel links. The password has to be confirmed again.
+pub async fn request(
+    state: &AppState,
+    user_id: Uuid,
+    new_email: String,
+    confirm_password: String,
+) -> AppResult<EmailChange> {
+    let confirm_token = token::generate();
+    let cancel_token = token::generate();
+    let change = EmailChange {
+        user_id,
+        new_email,
+        confirm_token_hash: token::hash(&confirm_token),
+        cancel_token_hash: token::hash(&cancel_token),
+        expires_at: Utc::now() + state.email_change_ttl,
+        created_at: Utc::now(),
+    };
+
+    let base_url = state.public_url.trim_end_matches('/');
+    // Pages that post the token back, so mail scanners opening the links
+    // change nothing.
+    let cancel_link = format!("{base_url}/cancel-email-change?token={cancel_token}");
+    let confirm_link = format!("{base_url}/confirm-email?token={confirm_token}");
+    let valid_hours = state.email_change_ttl.num_hours();
+
+    let change = db::run(&state.pool, move |conn| {
+        let user: User = users::table
+            .find(user_id)
+            .select(User::as_select())
+            .first(conn)?;
+        if !password::verify(&confirm_password, &user.password) {
+            return Err(AppError::Forbidden("password is incorrect".into()));
+        }
+        if change.new_email == user.email {
+            return Err(AppError::BadRequest(
+                "this already is your email address".into(),
+            ));
+        }
+        if email_taken(conn, &change.new_email)? {
+            return Err(email_in_use());
+        }
+
+        // Without both emails the change could neither be confirmed nor
+        // noticed, so they are queued together with it.
+        conn.transaction(|conn| {
+            let change = diesel::insert_into(email_changes::table)
+                .values(&change)
+                .on_conflict(email_changes::user_id)
+                .do_update()
+                .set(&change)
+                .returning(EmailChange::as_returning())
+                .get_result(conn)?;
+            mail::enqueue(
+                conn,
+                &user.email,
+                user.locale,
+                user.tz(),
+                Email::EmailChangeNotice {
+                    name: user.name.clone(),
+                    new_email: change.new_email.clone(),
+                    cancel_link,
+                },
+            )?;
+            mail::enqueue(
+                conn,
+                &change.new_email,
+                user.locale,
+                user.tz(),
+                Email::EmailChangeConfirmation {
+                    name: user.name.clone(),
+                    link: confirm_link,
+                    valid_hours,
+                },
+            )?;
+            Ok(change)
+        })
+    })
+    .await?;
+
+    tracing::info!(%user_id, "email change requested");
+    Ok(change)
+}
+
+/// Switches the account to the new address. Returns the updated user.
+pub async fn confirm(state: &AppState, confirm_token: &str) -> AppResult<User> {
+    let token_hash = token::hash(confirm_token);
+    let user = db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let change: EmailChange = email_changes::table
+                .filter(email_changes::confirm_token_hash.eq(&token_hash))
+                .select(EmailChange::as_select())
+                .for_update()
+                .first(conn)?;
+            if change.expires_at <= Utc::now() {
+                return Err(AppError::Gone("this confirmation link has expired".into()));
+            }
+
+            diesel::delete(email_changes::table.find(change.user_id)).execute(conn)?;
+            // The address may have been taken since the change was requested.
+            diesel::update(users::table.find(change.user_id))
+                .set((
+                    users::email.eq(&change.new_email),
+                    users::verified.eq(true),
+                    users::verification_token.eq(None::<String>),
+                    users::token_expires_at.eq(None::<chrono::DateTime<Utc>>),
+                ))
+                .returning(User::as_returning())
+                .get_result(conn)
+                .map_err(|err| match err {
+                    DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                        email_in_use()
+                    }
+                    other => other.into(),
+                })
+        })
+    })
+    .await?;
+
+    tracing::info!(user_id = %user.id, "email address changed");
+    Ok(user)
+}
+
+/// Drops the pending change the cancel link was sent for.
+pub async fn cancel(state: &AppState, cancel_token: &str) -> AppResult<()> {
+    let token_hash = token::hash(cancel_token);
+    let cancelled = db::run(&state.pool, move |conn| {
+        diesel::delete(email_changes::table)
+            .filter(email_changes::cancel_token_hash.eq(token_hash))
+            .returning(email_changes::user_id)
+            .get_result::<Uuid>(conn)
+            .optional()
+    })
+    .await?;
+
+    match cancelled {
+        Some(user_id) => {
+            tracing::info!(%user_id, "email change cancelled");
+            Ok(())
+        }
+        None => Err(AppError::NotFound),
+    }
+}
+
+fn email_taken(conn: &mut PgConnection, email: &str) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        users::table.filter(users::email.eq(email)),
+    ))
+    .get_result(conn)
+}
+
+fn email_in_use() -> AppError {
+    AppError::Conflict("this email address is already in use".into())
+}
@@ -0,0 +1,446 @@
+//! GDPR data export: everything stored about a user, assembled in the
+//! background and handed out through a time-limited, emailed link.
+
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::token;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::jobs::{self, BackgroundJob};
+use crate::mail::{self, Email};
+use crate::models::account_export::{AccountExport, ExportStatus, NewAccountExport};
+use crate::models::api_token::{ApiToken, ApiTokenInfo};
+use crate::models::attachment::Attachment;
+use crate::models::comment::NoteComment;
+use crate::models::email_change::EmailChange;
+use crate::models::login_event::LoginEvent;
+use crate::models::note::Note;
+use crate::models::notification::{Delivery, Notification, NotificationKind};
+use crate::models::reminder::NoteReminder;
+use crate::models::session::{Session, SessionInfo};
+use crate::models::share::NoteShare;
+use crate::models::user::{User, UserProfile};
+use crate::models::user_identity::UserIdentity;
+use crate::schema::{
+    account_exports, api_tokens, attachments, email_changes, login_events, note_comments,
+    note_reminders, note_shares, note_tags, notes, notifications, sessions, tags, user_identities,
+    users,
+};
+use crate::state::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct AccountArchive {
+    pub generated_at: DateTime<Utc>,
+    pub user: UserProfile,
+    pub notes: Vec<NoteArchive>,
+    /// Who the user's notes are shared with.
+    pub shares_granted: Vec<NoteShare>,
+    /// Other people's notes shared with the user.
+    pub shares_received: Vec<NoteShare>,
+    /// The user's comments on notes shared with them.
+    pub comments_on_shared_notes: Vec<NoteComment>,
+    pub notifications: Vec<Notification>,
+    pub notification_preferences: BTreeMap<NotificationKind, Delivery>,
+    pub login_history: Vec<LoginEvent>,
+    pub sessions: Vec<SessionInfo>,
+    pub api_tokens: Vec<ApiTokenInfo>,
+    pub identities: Vec<IdentityArchive>,
+    pub pending_email_change: Option<EmailChangeArchive>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteArchive {
+    #[serde(flatten)]
+    pub note: Note,
+    pub tags: Vec<String>,
+    pub attachments: Vec<AttachmentArchive>,
+    pub reminder: Option<NoteReminder>,
+    /// Only the user's own; the others belong to the people who wrote them.
+    pub comments: Vec<NoteComment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttachmentArchive {
+    pub id: Uuid,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Attachment> for AttachmentArchive {
+    fn from(attachment: Attachment) -> Self {
+        Self {
+            id: attachment.id,
+            file_name: attachment.file_name,
+            mime_type: attachment.mime_type,
+            size_bytes: attachment.size_bytes,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+/// A linked single sign-on account.
+#[derive(Debug, Serialize)]
+pub struct IdentityArchive {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: DateTime<Utc>,
+}
+
+impl From<UserIdentity> for IdentityArchive {
+    fn from(identity: UserIdentity) -> Self {
+        Self {
+            issuer: identity.issuer,
+            subject: identity.subject,
+            email: identity.email,
+            created_at: identity.created_at,
+            last_login_at: identity.last_login_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmailChangeArchive {
+    pub new_email: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<EmailChange> for EmailChangeArchive {
+    fn from(change: EmailChange) -> Self {
+        Self {
+            new_email: change.new_email,
+            expires_at: change.expires_at,
+            created_at: change.created_at,
+        }
+    }
+}
+
+pub fn build_archive(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<AccountArchive> {
+    let user: User = users::table
+        .find(user_id)
+        .select(User::as_select())
+        .first(conn)?;
+
+    let user_notes: Vec<Note> = notes::table
+        .filter(notes::user_id.eq(user_id))
+        .order(notes::created_at.asc())
+        .select(Note::as_select())
+        .load(conn)?;
+    let note_ids = user_notes.iter().map(|note| note.id).collect::<Vec<_>>();
+
+    let mut tags_by_note: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (note_id, name) in note_tags::table
+        .inner_join(tags::table)
+        .filter(note_tags::note_id.eq_any(&note_ids))
+        .order(tags::name.asc())
+        .select((note_tags::note_id, tags::name))
+        .load::<(Uuid, String)>(conn)?
+    {
+        tags_by_note.entry(note_id).or_default().push(name);
+    }
+
+    let mut attachments_by_note: HashMap<Uuid, Vec<AttachmentArchive>> = HashMap::new();
+    for attachment in attachments::table
+        .filter(attachments::note_id.eq_any(&note_ids))
+        .order(attachments::created_at.asc())
+        .select(Attachment::as_select())
+        .load(conn)?
+    {
+        attachments_by_note
+            .entry(attachment.note_id)
+            .or_default()
+            .push(attachment.into());
+    }
+
+    let mut reminders: HashMap<Uuid, NoteReminder> = note_reminders::table
+        .filter(note_reminders::note_id.eq_any(&note_ids))
+        .select(NoteReminder::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|reminder| (reminder.note_id, reminder))
+        .collect();
+
+    let mut comments_by_note: HashMap<Uuid, Vec<NoteComment>> = HashMap::new();
+    let mut comments_on_shared_notes = Vec::new();
+    for comment in note_comments::table
+        .filter(note_comments::user_id.eq(user_id))
+        .order(note_comments::created_at.asc())
+        .select(NoteComment::as_select())
+        .load(conn)?
+    {
+        if note_ids.contains(&comment.note_id) {
+            comments_by_note
+                .entry(comment.note_id)
+                .or_default()
+                .push(comment);
+        } else {
+            comments_on_shared_notes.push(comment);
+        }
+    }
+
+    let shares_granted = note_shares::table
+        .filter(note_shares::note_id.eq_any(&note_ids))
+        .order(note_shares::created_at.asc())
+        .select(NoteShare::as_select())
+        .load(conn)?;
+    let shares_received = note_shares::table
+        .filter(note_shares::user_id.eq(user_id))
+        .order(note_shares::created_at.asc())
+        .select(NoteShare::as_select())
+        .load(conn)?;
+
+    let user_notifications = notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .order(notifications::created_at.asc())
+        .select(Notification::as_select())
+        .load(conn)?;
+    let notification_preferences = crate::notifications::preferences(conn, user_id)?;
+
+    let login_history = login_events::table
+        .filter(login_events::user_id.eq(user_id))
+        .order(login_events::created_at.asc())
+        .select(LoginEvent::as_select())
+        .load(conn)?;
+
+    let user_sessions = sessions::table
+        .filter(sessions::user_id.eq(user_id))
+        .order(sessions::created_at.asc())
+        .select(Session::as_select())
+        .load(conn)?;
+
+    let tokens = api_tokens::table
+        .filter(api_tokens::user_id.eq(user_id))
+        .order(api_tokens::created_at.asc())
+        .select(ApiToken::as_select())
+        .load(conn)?;
+    let identities = user_identities::table
+        .filter(user_identities::user_id.eq(user_id))
+        .order(user_identities::created_at.asc())
+        .select(UserIdentity::as_select())
+        .load(conn)?;
+    let pending_email_change = email_changes::table
+        .find(user_id)
+        .select(EmailChange::as_select())
+        .first(conn)
+        .optional()?;
+
+    Ok(AccountArchive {
+        generated_at: Utc::now(),
+        user: UserProfile::from(&user),
+        notes: user_notes
+            .into_iter()
+            .map(|note| NoteArchive {
+                tags: tags_by_note.remove(&note.id).unwrap_or_default(),
+                attachments: attachments_by_note.remove(&note.id).unwrap_or_default(),
+                reminder: reminders.remove(&note.id),
+                comments: comments_by_note.remove(&note.id).unwrap_or_default(),
+                note,
+            })
+            .collect(),
+        shares_granted,
+        shares_received,
+        comments_on_shared_notes,
+        notifications: user_notifications,
+        notification_preferences,
+        login_history,
+        sessions: user_sessions.into_iter().map(SessionInfo::from).collect(),
+        api_tokens: tokens.into_iter().map(ApiTokenInfo::from).collect(),
+        identities: identities.into_iter().map(IdentityArchive::from).collect(),
+        pending_email_change: pending_email_change.map(EmailChangeArchive::from),
+    })
+}
+
+/// Queues an export for the user, reusing one that is still being assembled.
+pub async fn request(state: &AppState, user_id: Uuid) -> AppResult<AccountExport> {
+    let new_export = NewAccountExport {
+        user_id,
+        // Replaced once the archive is ready, with the hash of the link that
+        // is emailed then.
+        token_hash: token::hash(&token::generate()),
+        expires_at: Utc::now() + state.export_ttl,
+    };
+
+    db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let pending = account_exports::table
+                .filter(account_exports::user_id.eq(user_id))
+                .filter(account_exports::status.eq(ExportStatus::Pending))
+                .filter(account_exports::expires_at.gt(Utc::now()))
+                .select(AccountExport::as_select())
+                .first(conn)
+                .optional()?;
+            if let Some(export) = pending {
+                return Ok(export);
+            }
+
+            let export = diesel::insert_into(account_exports::table)
+                .values(&new_export)
+                .returning(AccountExport::as_returning())
+                .get_result(conn)?;
+            jobs::enqueue(
+                conn,
+                &AssembleExport {
+                    export_id: export.id,
+                },
+            )?;
+            Ok::<_, diesel::result::Error>(export)
+        })
+    })
+    .await
+}
+
+/// Builds the archive of an export and emails the download link.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssembleExport {
+    pub export_id: Uuid,
+}
+
+#[async_trait]
+impl BackgroundJob for AssembleExport {
+    const KIND: &'static str = "account_export";
+
+    async fn run(&self, state: &AppState) -> AppResult<()> {
+        assemble(state, self.export_id).await
+    }
+
+    async fn dead(&self, state: &AppState) {
+        if let Err(err) = mark_failed(state, self.export_id).await {
+            tracing::error!(export_id = %self.export_id, "cannot mark export as failed: {err}");
+        }
+    }
+}
+
+async fn assemble(state: &AppState, export_id: Uuid) -> AppResult<()> {
+    let store = state.store.clone();
+    let link_token = token::generate();
+    let token_hash = token::hash(&link_token);
+    let link = format!(
+        "{}/api/account/export/{export_id}?token={link_token}",
+        state.public_url.trim_end_matches('/')
+    );
+    let valid_hours = state.export_ttl.num_hours();
+    let assembled = db::run(&state.pool, move |conn| {
+        let export: AccountExport = account_exports::table
+            .find(export_id)
+            .select(AccountExport::as_select())
+            .first(conn)?;
+        // Expired and purged, or finished by an earlier attempt.
+        if export.status != ExportStatus::Pending {
+            return Ok(());
+        }
+        let archive = build_archive(conn, export.user_id)?;
+        let json = serde_json::to_vec_pretty(&archive).map_err(AppError::internal)?;
+
+        let storage_key = format!("exports/{export_id}.json");
+        store.put(&storage_key, &json).map_err(AppError::internal)?;
+
+        let user = archive.user;
+        conn.transaction(|conn| {
+            diesel::update(account_exports::table.find(export_id))
+                .set((
+                    account_exports::status.eq(ExportStatus::Ready),
+                    account_exports::token_hash.eq(&token_hash),
+                    account_exports::storage_key.eq(&storage_key),
+                    account_exports::completed_at.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+            mail::enqueue(
+                conn,
+                &user.email,
+                user.locale,
+                user.tz(),
+                Email::ExportReady {
+                    name: user.name.clone(),
+                    link,
+                    valid_hours,
+                },
+            )?;
+            Ok::<_, AppError>(())
+        })
+    })
+    .await;
+    match assembled {
+        Ok(()) | Err(AppError::NotFound) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+async fn mark_failed(state: &AppState, export_id: Uuid) -> AppResult<()> {
+    let store = state.store.clone();
+    db::run(&state.pool, move |conn| {
+        let storage_key = diesel::update(account_exports::table.find(export_id))
+            .set((
+                account_exports::status.eq(ExportStatus::Failed),
+                account_exports::storage_key.eq(None::<String>),
+                account_exports::completed_at.eq(Utc::now()),
+            ))
+            .returning(account_exports::storage_key)
+            .get_result::<Option<String>>(conn)
+            .optional()?;
+        if let Some(key) = storage_key.flatten() {
+            let _ = store.delete(&key);
+        }
+        Ok::<_, AppError>(())
+    })
+    .await
+}
+
+/// Returns the archive contents if the link is valid for this user.
+pub async fn download(
+    state: &AppState,
+    user_id: Uuid,
+    export_id: Uuid,
+    link_token: &str,
+) -> AppResult<Vec<u8>> {
+    let token_hash = token::hash(link_token);
+    let export = db::run(&state.pool, move |conn| {
+        account_exports::table
+            .find(export_id)
+            .filter(account_exports::user_id.eq(user_id))
+            .filter(account_exports::token_hash.eq(token_hash))
+            .select(AccountExport::as_select())
+            .first(conn)
+    })
+    .await?;
+
+    if export.expires_at <= Utc::now() {
+        return Err(AppError::Gone("this export link has expired".into()));
+    }
+    let storage_key = match (export.status, export.storage_key) {
+        (ExportStatus::Ready, Some(key)) => key,
+        _ => return Err(AppError::NotFound),
+    };
+
+    let store = state.store.clone();
+    tokio::task::spawn_blocking(move || store.get(&storage_key))
+        .await?
+        .map_err(AppError::internal)
+}
+
+/// Deletes expired exports together with their archives.
+pub async fn purge_expired(state: AppState) -> AppResult<usize> {
+    let store = state.store.clone();
+    db::run(&state.pool, move |conn| {
+        let expired = diesel::delete(account_exports::table)
+            .filter(account_exports::expires_at.le(Utc::now()))
+            .returning(account_exports::storage_key)
+            .get_results::<Option<String>>(conn)?;
+        for key in expired.iter().flatten() {
+            if let Err(err) = store.delete(key) {
+                tracing::warn!("failed to delete expired export {key}: {err}");
+            }
+        }
+        Ok::<_, AppError>(expired.len())
+    })
+    .await
+}
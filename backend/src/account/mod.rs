@@ -0,0 +1,5 @@
+pub mod deletion;
+pub mod email_change;
+pub mod export;
+pub mod password_reset;
+pub mod registration;
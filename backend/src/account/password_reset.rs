@@ -0,0 +1,128 @@
+//! Resetting a forgotten password through a link sent by email. The link
+//! carries a sealed token bound to the current password hash, so it stops
+//! working once the password has changed, including through the link itself.
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::{session, token};
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::mail::{self, Email};
+use crate::models::user::User;
+use crate::schema::users;
+use crate::state::AppState;
+
+const RESET_PURPOSE: &str = "password_reset";
+const RESET_TTL_MINUTES: i64 = 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResetClaims {
+    sub: Uuid,
+    /// Derived from the password hash the link was issued for.
+    fingerprint: String,
+}
+
+fn fingerprint(password_hash: &str) -> String {
+    token::hash(password_hash)[..16].to_string()
+}
+
+fn invalid_link() -> AppError {
+    AppError::BadRequest("this reset link is invalid or has expired".into())
+}
+
+/// Sends a reset link if an account uses `email`. Whether one does is not
+/// revealed to the caller.
+pub async fn request(state: &AppState, email: String) -> AppResult<()> {
+    let user = db::run(&state.pool, move |conn| {
+        users::table
+            .filter(users::email.eq(&email))
+            .select(User::as_select())
+            .first(conn)
+            .optional()
+    })
+    .await?;
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let reset_token = state
+        .jwt
+        .seal(
+            RESET_PURPOSE,
+            ResetClaims {
+                sub: user.id,
+                fingerprint: fingerprint(&user.password),
+            },
+            Duration::minutes(RESET_TTL_MINUTES),
+        )
+        .map_err(AppError::internal)?;
+    let email = Email::PasswordReset {
+        name: user.name.clone(),
+        link: format!(
+            "{}/reset-password?token={reset_token}",
+            state.public_url.trim_end_matches('/'),
+        ),
+        valid_minutes: RESET_TTL_MINUTES,
+    };
+    db::run(&state.pool, move |conn| {
+        mail::enqueue(conn, &user.email, user.locale, user.tz(), email)
+    })
+    .await?;
+    Ok(())
+}
+
+/// Sets a new password and ends every session of the account.
+pub async fn reset(state: &AppState, reset_token: &str, new_password: String) -> AppResult<()> {
+    let claims: ResetClaims = state
+        .jwt
+        .unseal(RESET_PURPOSE, reset_token)
+        .ok_or_else(invalid_link)?;
+    let user = db::run(&state.pool, move |conn| {
+        users::table
+            .find(claims.sub)
+            .select(User::as_select())
+            .first(conn)
+            .optional()
+    })
+    .await?
+    .filter(|user| fingerprint(&user.password) == claims.fingerprint)
+    .ok_or_else(invalid_link)?;
+
+    let password = state
+        .password_policy
+        .hash_new(
+            state.password_params,
+            new_password,
+            vec![user.name.clone(), user.email.clone()],
+            "password",
+        )
+        .await?;
+    let user_id = user.id;
+    db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            // Guards against two resets racing with the same link.
+            let updated = diesel::update(users::table.find(user_id))
+                .filter(users::password.eq(&user.password))
+                .set((
+                    users::password.eq(password),
+                    // Receiving the link proves the address works.
+                    users::verified.eq(true),
+                    users::failed_login_attempts.eq(0),
+                    users::locked_until.eq(None::<DateTime<Utc>>),
+                ))
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(invalid_link());
+            }
+            session::revoke_all(conn, user_id, None)?;
+            Ok(())
+        })
+    })
+    .await?;
+
+    tracing::info!(%user_id, "password reset");
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Signing up with an email address and password. New accounts stay
+//! unverified until the link sent to their address has been opened.
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use crate::auth::token;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::mail::{self, Email};
+use crate::models::user::{Locale, NewUser, User};
+use crate::schema::users;
+use crate::state::AppState;
+
+const VERIFICATION_TTL_HOURS: i64 = 48;
+
+/// Creates the account and sends the verification link.
+pub async fn register(
+    state: &AppState,
+    name: String,
+    email: String,
+    password: String,
+    locale: Locale,
+    time_zone: String,
+) -> AppResult<User> {
+    let password = state
+        .password_policy
+        .hash_new(
+            state.password_params,
+            password,
+            vec![name.clone(), email.clone()],
+            "password",
+        )
+        .await?;
+    let link_token = token::generate();
+    let new_user = NewUser {
+        name,
+        email,
+        verified: false,
+        password,
+        verification_token: Some(token::hash(&link_token)),
+        token_expires_at: Some(Utc::now() + Duration::hours(VERIFICATION_TTL_HOURS)),
+        locale,
+        time_zone,
+    };
+    let link = format!(
+        "{}/api/auth/verify?token={link_token}",
+        state.public_url.trim_end_matches('/'),
+    );
+
+    let user = db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let user = diesel::insert_into(users::table)
+                .values(&new_user)
+                .returning(User::as_returning())
+                .get_result(conn)
+                .map_err(|err| match err {
+                    DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                        AppError::Conflict(
+                            "an account with this email address already exists".into(),
+                        )
+                    }
+                    other => other.into(),
+                })?;
+            mail::enqueue(
+                conn,
+                &user.email,
+                user.locale,
+                user.tz(),
+                Email::Verification {
+                    name: user.name.clone(),
+                    link,
+                    valid_hours: VERIFICATION_TTL_HOURS,
+                },
+            )?;
+            Ok::<_, AppError>(user)
+        })
+    })
+    .await?;
+    tracing::info!(user_id = %user.id, "account registered");
+
+    Ok(user)
+}
+
+/// Marks the account the link was sent for as verified.
+pub async fn verify(state: &AppState, link_token: &str) -> AppResult<User> {
+    let token_hash = token::hash(link_token);
+    db::run(&state.pool, move |conn| {
+        let user: User = users::table
+            .filter(users::verification_token.eq(&token_hash))
+            .select(User::as_select())
+            .first(conn)?;
+        if user
+            .token_expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(AppError::Gone("this verification link has expired".into()));
+        }
+
+        Ok(diesel::update(users::table.find(user.id))
+            .set((
+                users::verified.eq(true),
+                users::verification_token.eq(None::<String>),
+                users::token_expires_at.eq(None::<chrono::DateTime<Utc>>),
+            ))
+            .returning(User::as_returning())
+            .get_result(conn)?)
+    })
+    .await
+}
@@ -0,0 +1,54 @@
+//! Personal access tokens, sent as `Authorization: Bearer nat_...`.
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+
+use super::token;
+use crate::models::api_token::ApiToken;
+use crate::models::user::UserRole;
+use crate::schema::{api_tokens, users};
+
+/// Tells personal access tokens apart from access JWTs.
+pub const PREFIX: &str = "nat_";
+
+/// How stale `last_used_at` may get before a request refreshes it.
+const LAST_USED_RESOLUTION_MINUTES: i64 = 1;
+
+/// Returns a new token together with the hash to store.
+pub fn generate() -> (String, String) {
+    let token = format!("{PREFIX}{}", token::generate());
+    let hash = token::hash(&token);
+    (token, hash)
+}
+
+/// Looks up an unexpired token and the current role of its owner, recording
+/// that the token was used.
+pub fn authenticate(
+    conn: &mut PgConnection,
+    token: &str,
+) -> QueryResult<Option<(ApiToken, UserRole)>> {
+    let now = Utc::now();
+    let found = api_tokens::table
+        .inner_join(users::table)
+        .filter(api_tokens::token_hash.eq(token::hash(token)))
+        .filter(
+            api_tokens::expires_at
+                .is_null()
+                .or(api_tokens::expires_at.gt(now)),
+        )
+        .select((ApiToken::as_select(), users::role))
+        .first::<(ApiToken, UserRole)>(conn)
+        .optional()?;
+
+    if let Some((api_token, _)) = &found {
+        let stale = api_token.last_used_at.is_none_or(|last_used| {
+            now - last_used > Duration::minutes(LAST_USED_RESOLUTION_MINUTES)
+        });
+        if stale {
+            diesel::update(api_tokens::table.find(api_token.id))
+                .set(api_tokens::last_used_at.eq(Some::<DateTime<Utc>>(now)))
+                .execute(conn)?;
+        }
+    }
+    Ok(found)
+}
@@ -0,0 +1,144 @@
+//! Offline check against passwords known from data breaches, using the
+//! SHA-1 hashes published by Have I Been Pwned. No network access is needed.
+//!
+//! Either format of the downloaded data works:
+//! - a directory of range files as written by the official downloader, one
+//!   `<first 5 hex digits>.txt` file of `<remaining 35 hex digits>:<count>`
+//!   lines per prefix, which is read on demand;
+//! - a single file of `<40 hex digits>:<count>` lines, which is loaded into
+//!   memory and therefore suited to a subset such as the most common hashes.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+const PREFIX_LEN: usize = 5;
+
+#[derive(Debug)]
+pub enum BreachedPasswords {
+    Ranges(PathBuf),
+    Hashes(Vec<[u8; 20]>),
+}
+
+impl BreachedPasswords {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if path.is_dir() {
+            return Ok(Self::Ranges(path.to_path_buf()));
+        }
+
+        let mut hashes = Vec::new();
+        for line in BufReader::new(fs::File::open(path)?).lines() {
+            let line = line?;
+            let Some((hash, count)) = parse_line(&line) else {
+                continue;
+            };
+            let mut digest = [0u8; 20];
+            if count > 0 && hex::decode_to_slice(hash, &mut digest).is_ok() {
+                hashes.push(digest);
+            }
+        }
+        hashes.sort_unstable();
+        hashes.dedup();
+        Ok(Self::Hashes(hashes))
+    }
+
+    /// Whether `password` appears in the breach data.
+    pub fn contains(&self, password: &str) -> io::Result<bool> {
+        let digest: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+        match self {
+            Self::Hashes(hashes) => Ok(hashes.binary_search(&digest).is_ok()),
+            Self::Ranges(dir) => {
+                let hash = hex::encode_upper(digest);
+                let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+                let file = match fs::File::open(dir.join(format!("{prefix}.txt"))) {
+                    Ok(file) => file,
+                    // No file means no breached password with this prefix.
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+                    Err(err) => return Err(err),
+                };
+                for line in BufReader::new(file).lines() {
+                    if let Some((candidate, count)) = parse_line(&line?) {
+                        if count > 0 && candidate.eq_ignore_ascii_case(suffix) {
+                            return Ok(true);
+                        }
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Splits a `HASH:COUNT` line. Downloads with padding contain entries with a
+/// count of zero that do not belong to any real password.
+fn parse_line(line: &str) -> Option<(&str, u64)> {
+    let (hash, count) = line.trim().split_once(':')?;
+    Some((hash, count.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha1_hex(password: &str) -> String {
+        hex::encode_upper(Sha1::digest(password.as_bytes()))
+    }
+
+    /// The same data in both formats: `hunter2` and `letmein` are breached,
+    /// `Summer2024` only appears as padding.
+    fn fixtures() -> [BreachedPasswords; 2] {
+        let dir = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+        let ranges = dir.join("ranges");
+        fs::create_dir_all(&ranges).unwrap();
+        let mut single = String::new();
+        for (password, count) in [("hunter2", 17_043), ("letmein", 3), ("Summer2024", 0)] {
+            let hash = sha1_hex(password);
+            let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+            // The downloader writes upper case and CRLF, some mirrors do not.
+            let range = ranges.join(format!("{prefix}.txt"));
+            let mut lines = fs::read_to_string(&range).unwrap_or_default();
+            lines.push_str(&format!("{}:{count}\r\n", suffix.to_lowercase()));
+            fs::write(range, lines).unwrap();
+            single.push_str(&format!("{}:{count}\n", hash.to_lowercase()));
+        }
+        single.push_str("not a hash line\n\n");
+        let file = dir.join("hashes.txt");
+        fs::write(&file, single).unwrap();
+
+        [
+            BreachedPasswords::open(&ranges).unwrap(),
+            BreachedPasswords::open(&file).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn finds_breached_passwords_in_either_format() {
+        for breached in fixtures() {
+            assert!(breached.contains("hunter2").unwrap(), "{breached:?}");
+            assert!(breached.contains("letmein").unwrap(), "{breached:?}");
+            // Hashes are of the exact password.
+            assert!(!breached.contains("Hunter2").unwrap(), "{breached:?}");
+        }
+    }
+
+    #[test]
+    fn ignores_padding_and_unknown_passwords() {
+        for breached in fixtures() {
+            assert!(!breached.contains("Summer2024").unwrap(), "{breached:?}");
+            assert!(
+                !breached.contains("gravel tulip orbit").unwrap(),
+                "{breached:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn loads_only_real_hashes_from_a_single_file() {
+        let [_, BreachedPasswords::Hashes(hashes)] = fixtures() else {
+            panic!("expected hashes");
+        };
+        assert_eq!(hashes.len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::session::Session;
+use crate::models::user::UserRole;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    /// The session the token was issued for.
+    pub sid: Uuid,
+    pub role: UserRole,
+    /// Whether the login included a second factor.
+    #[serde(default)]
+    pub mfa: bool,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Short-lived token handed out after the password step when the account
+/// has two-factor authentication enabled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeClaims {
+    pub sub: Uuid,
+    pub purpose: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Short-lived state a client has to hand back unchanged, such as an OIDC
+/// login in progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedClaims<T> {
+    purpose: String,
+    iat: i64,
+    exp: i64,
+    #[serde(flatten)]
+    payload: T,
+}
+
+const CHALLENGE_PURPOSE: &str = "two_factor";
+pub const CHALLENGE_TTL_MINUTES: i64 = 5;
+
+#[derive(Clone)]
+pub struct JwtKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    pub ttl: Duration,
+}
+
+impl JwtKeys {
+    pub fn new(secret: &[u8], ttl: Duration) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            ttl,
+        }
+    }
+
+    pub fn issue(&self, session: &Session, role: UserRole) -> jsonwebtoken::errors::Result<String> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: session.user_id,
+            sid: session.id,
+            role,
+            mfa: session.mfa,
+            iat: now.timestamp(),
+            exp: (now + self.ttl).timestamp(),
+        };
+        jsonwebtoken::encode(&Header::default(), &claims, &self.encoding)
+    }
+
+    pub fn verify(&self, token: &str) -> jsonwebtoken::errors::Result<Claims> {
+        jsonwebtoken::decode(token, &self.decoding, &Validation::default()).map(|data| data.claims)
+    }
+
+    pub fn issue_challenge(&self, user_id: Uuid) -> jsonwebtoken::errors::Result<String> {
+        let now = Utc::now();
+        let claims = ChallengeClaims {
+            sub: user_id,
+            purpose: CHALLENGE_PURPOSE.to_string(),
+            iat: now.timestamp(),
+            exp: (now + Duration::minutes(CHALLENGE_TTL_MINUTES)).timestamp(),
+        };
+        jsonwebtoken::encode(&Header::default(), &claims, &self.encoding)
+    }
+
+    /// Returns the user id the challenge was issued for.
+    pub fn verify_challenge(&self, token: &str) -> Option<Uuid> {
+        let claims: ChallengeClaims =
+            jsonwebtoken::decode(token, &self.decoding, &Validation::default())
+                .ok()?
+                .claims;
+        (claims.purpose == CHALLENGE_PURPOSE).then_some(claims.sub)
+    }
+
+    /// Signs `payload` so it can be stored with the client and checked when
+    /// it comes back. The payload is readable, not encrypted.
+    pub fn seal<T: Serialize>(
+        &self,
+        purpose: &str,
+        payload: T,
+        ttl: Duration,
+    ) -> jsonwebtoken::errors::Result<String> {
+        let now = Utc::now();
+        let claims = SealedClaims {
+            purpose: purpose.to_string(),
+            iat: now.timestamp(),
+            exp: (now + ttl).timestamp(),
+            payload,
+        };
+        jsonwebtoken::encode(&Header::default(), &claims, &self.encoding)
+    }
+
+    pub fn unseal<T: DeserializeOwned>(&self, purpose: &str, token: &str) -> Option<T> {
+        let claims: SealedClaims<T> =
+            jsonwebtoken::decode(token, &self.decoding, &Validation::default())
+                .ok()?
+                .claims;
+        (claims.purpose == purpose).then_some(claims.payload)
+    }
+}
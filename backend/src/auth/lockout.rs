@@ -0,0 +1,133 @@
+//! Progressive account lockout after repeated failed logins.
+//!
+//! Once an account reaches the threshold of consecutive failures it is locked
+//! for the base duration. Every further failure doubles the lock, up to the
+//! configured maximum. A successful login resets the counter.
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::mail::{self, Email};
+use crate::models::user::User;
+use crate::schema::users;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    pub threshold: i32,
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl LockoutPolicy {
+    /// How long the account is locked after `failures` consecutive failures.
+    fn lock_for(&self, failures: i32) -> Option<Duration> {
+        let doublings = failures.checked_sub(self.threshold)?;
+        let factor = 1i32
+            .checked_shl(doublings.try_into().ok()?)
+            .unwrap_or(i32::MAX);
+        Some(
+            self.base
+                .checked_mul(factor)
+                .map_or(self.max, |lock| lock.min(self.max)),
+        )
+    }
+}
+
+/// Rejects the attempt if the account is currently locked.
+pub fn ensure_unlocked(user: &User) -> Result<(), AppError> {
+    match user.locked_until {
+        Some(until) if until > Utc::now() => Err(AppError::TooManyRequests {
+            message: "account is temporarily locked after too many failed logins".into(),
+            retry_after: (until - Utc::now()).to_std().unwrap_or_default(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Counts a failed login. Returns the lock expiry if this failure is the one
+/// that locked the account.
+pub fn record_failure(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    policy: &LockoutPolicy,
+) -> QueryResult<Option<DateTime<Utc>>> {
+    // Incremented in SQL so concurrent failures are all counted.
+    let failures: i32 = diesel::update(users::table.find(user_id))
+        .set(users::failed_login_attempts.eq(users::failed_login_attempts + 1))
+        .returning(users::failed_login_attempts)
+        .get_result(conn)?;
+    let Some(lock) = policy.lock_for(failures) else {
+        return Ok(None);
+    };
+
+    let locked_until = Utc::now() + lock;
+    diesel::update(users::table.find(user_id))
+        .set(users::locked_until.eq(locked_until))
+        .execute(conn)?;
+    Ok((failures == policy.threshold).then_some(locked_until))
+}
+
+pub fn reset(conn: &mut PgConnection, user: &User) -> QueryResult<()> {
+    if user.failed_login_attempts > 0 || user.locked_until.is_some() {
+        diesel::update(users::table.find(user.id))
+            .set((
+                users::failed_login_attempts.eq(0),
+                users::locked_until.eq(None::<DateTime<Utc>>),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Tells the owner that their account was locked.
+pub fn notify(
+    conn: &mut PgConnection,
+    user: &User,
+    locked_until: DateTime<Utc>,
+) -> QueryResult<()> {
+    mail::enqueue(
+        conn,
+        &user.email,
+        user.locale,
+        user.tz(),
+        Email::AccountLocked {
+            name: user.name.clone(),
+            until: locked_until,
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: LockoutPolicy = LockoutPolicy {
+        threshold: 3,
+        base: Duration::minutes(1),
+        max: Duration::minutes(60),
+    };
+
+    #[test]
+    fn locks_from_the_threshold_on() {
+        assert_eq!(POLICY.lock_for(0), None);
+        assert_eq!(POLICY.lock_for(2), None);
+        assert_eq!(POLICY.lock_for(3), Some(Duration::minutes(1)));
+    }
+
+    #[test]
+    fn doubles_with_every_further_failure() {
+        assert_eq!(POLICY.lock_for(4), Some(Duration::minutes(2)));
+        assert_eq!(POLICY.lock_for(5), Some(Duration::minutes(4)));
+        assert_eq!(POLICY.lock_for(8), Some(Duration::minutes(32)));
+    }
+
+    #[test]
+    fn caps_at_the_maximum() {
+        assert_eq!(POLICY.lock_for(9), Some(Duration::minutes(60)));
+        assert_eq!(POLICY.lock_for(40), Some(Duration::minutes(60)));
+        assert_eq!(POLICY.lock_for(i32::MAX), Some(Duration::minutes(60)));
+    }
+}
@@ -0,0 +1,223 @@
+pub mod api_token;
+pub mod breached;
+pub mod jwt;
+pub mod lockout;
+pub mod oidc;
+pub mod password;
+pub mod password_policy;
+pub mod session;
+pub mod strength;
+pub mod token;
+pub mod two_factor;
+
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use uuid::Uuid;
+
+use crate::db;
+use crate::error::AppError;
+use crate::models::api_token::ApiTokenScope;
+use crate::models::user::UserRole;
+use crate::state::AppState;
+
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+/// Refresh tokens are only sent to the endpoints that need them.
+const REFRESH_TOKEN_PATH: &str = "/api/auth";
+/// Hands the two-factor challenge of a login through the identity provider
+/// to the endpoint that finishes it, as redirect URLs end up in histories
+/// and logs.
+pub const CHALLENGE_COOKIE: &str = "two_factor_challenge";
+const CHALLENGE_COOKIE_PATH: &str = "/api/auth/login/2fa";
+
+/// The authenticated caller, taken from the access token cookie or an
+/// `Authorization: Bearer` header. Admins are rejected until they have set up
+/// two-factor authentication if the deployment requires it.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub role: UserRole,
+    pub mfa: bool,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let PartialAuthUser(user) = PartialAuthUser::from_request_parts(parts, state).await?;
+        if state.require_admin_2fa && user.role == UserRole::Admin && !user.mfa {
+            return Err(AppError::Forbidden(
+                "two-factor authentication is required for admin accounts".into(),
+            ));
+        }
+        Ok(user)
+    }
+}
+
+/// Like [`AuthUser`], but without the two-factor requirement for admins, so
+/// they can still reach the enrollment endpoints.
+#[derive(Debug, Clone, Copy)]
+pub struct PartialAuthUser(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<AppState> for PartialAuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let bearer = bearer_token(&parts.headers).map(str::to_owned);
+        if bearer
+            .as_deref()
+            .is_some_and(|token| token.starts_with(api_token::PREFIX))
+        {
+            return Err(AppError::Forbidden(
+                "personal access tokens cannot be used for this endpoint".into(),
+            ));
+        }
+        let token = bearer
+            .or_else(|| {
+                CookieJar::from_headers(&parts.headers)
+                    .get(ACCESS_TOKEN_COOKIE)
+                    .map(|cookie| cookie.value().to_owned())
+            })
+            .ok_or(AppError::Unauthorized)?;
+
+        let claims = state
+            .jwt
+            .verify(&token)
+            .map_err(|_| AppError::Unauthorized)?;
+
+        // Access tokens are short-lived, but revoking a session has to take
+        // effect immediately.
+        let active = db::run(&state.pool, move |conn| {
+            session::check_active(conn, claims.sub, claims.sid)
+        })
+        .await?;
+        if !active {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok(PartialAuthUser(AuthUser {
+            id: claims.sub,
+            session_id: claims.sid,
+            role: claims.role,
+            mfa: claims.mfa,
+        }))
+    }
+}
+
+/// A caller of the notes API: either a logged-in user with full access or a
+/// personal access token limited to its scopes. Handlers check the scope they
+/// need with [`ApiUser::require`].
+#[derive(Debug, Clone)]
+pub struct ApiUser {
+    pub id: Uuid,
+    pub role: UserRole,
+    /// `None` for sessions, which are not limited by scopes.
+    scopes: Option<Vec<ApiTokenScope>>,
+}
+
+impl ApiUser {
+    pub fn require(&self, scope: ApiTokenScope) -> Result<(), AppError> {
+        if scope == ApiTokenScope::Admin && self.role != UserRole::Admin {
+            return Err(AppError::Forbidden("admin access required".into()));
+        }
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err(AppError::Forbidden(format!(
+                "token lacks the {scope} scope"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for ApiUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)
+            .filter(|token| token.starts_with(api_token::PREFIX))
+            .map(str::to_owned);
+        let Some(token) = token else {
+            let user = AuthUser::from_request_parts(parts, state).await?;
+            return Ok(ApiUser {
+                id: user.id,
+                role: user.role,
+                scopes: None,
+            });
+        };
+
+        let (api_token, role) = db::run(&state.pool, move |conn| {
+            api_token::authenticate(conn, &token)
+        })
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+        Ok(ApiUser {
+            id: api_token.user_id,
+            role,
+            scopes: Some(api_token.scopes),
+        })
+    }
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+pub fn access_cookie(token: String, state: &AppState) -> Cookie<'static> {
+    Cookie::build((ACCESS_TOKEN_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .secure(state.secure_cookies)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(state.jwt.ttl.num_seconds()))
+        .build()
+}
+
+pub fn refresh_cookie(token: String, state: &AppState) -> Cookie<'static> {
+    Cookie::build((REFRESH_TOKEN_COOKIE, token))
+        .path(REFRESH_TOKEN_PATH)
+        .http_only(true)
+        .secure(state.secure_cookies)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::seconds(state.session_ttl.num_seconds()))
+        .build()
+}
+
+pub fn challenge_cookie(token: String, state: &AppState) -> Cookie<'static> {
+    Cookie::build((CHALLENGE_COOKIE, token))
+        .path(CHALLENGE_COOKIE_PATH)
+        .http_only(true)
+        .secure(state.secure_cookies)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::minutes(jwt::CHALLENGE_TTL_MINUTES))
+        .build()
+}
+
+pub fn remove_challenge_cookie(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build(CHALLENGE_COOKIE).path(CHALLENGE_COOKIE_PATH))
+}
+
+/// Clears both the access and the refresh token cookie.
+pub fn remove_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build(ACCESS_TOKEN_COOKIE).path("/"))
+        .remove(Cookie::build(REFRESH_TOKEN_COOKIE).path(REFRESH_TOKEN_PATH))
+}
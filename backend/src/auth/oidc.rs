@@ -0,0 +1,348 @@
+//! OpenID Connect single sign-on using the authorization code flow with PKCE.
+//!
+//! The provider is discovered on first use. While the user is at the provider
+//! the CSRF state, nonce and PKCE verifier wait in a signed cookie.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::{
+    reqwest, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet,
+    EndpointNotSet, EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier,
+    RedirectUrl, Scope, TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+use super::password::{self, HashParams};
+use super::{session, token};
+use crate::error::{AppError, AppResult};
+use crate::models::user::{Locale, NewUser, User};
+use crate::models::user_identity::{NewUserIdentity, UserIdentity};
+use crate::schema::{user_identities, users};
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// Where the provider sends the user back to, i.e. our callback route.
+    pub redirect_url: String,
+    /// Where the browser ends up after a successful login.
+    pub post_login_url: String,
+}
+
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("invalid OIDC configuration: {0}")]
+    Config(String),
+    #[error("OIDC discovery failed: {0}")]
+    Discovery(String),
+    #[error("login state does not match, please try again")]
+    StateMismatch,
+    #[error("code exchange failed: {0}")]
+    Exchange(String),
+    #[error("provider returned no ID token")]
+    MissingIdToken,
+    #[error("invalid ID token: {0}")]
+    InvalidIdToken(String),
+}
+
+impl From<OidcError> for AppError {
+    fn from(err: OidcError) -> Self {
+        match err {
+            OidcError::StateMismatch => AppError::BadRequest(err.to_string()),
+            // Usually a code that was already used or has expired.
+            OidcError::Exchange(_) => {
+                tracing::warn!("OIDC login failed: {err}");
+                AppError::BadRequest(
+                    "the identity provider rejected the login, please try again".into(),
+                )
+            }
+            OidcError::InvalidIdToken(_) => {
+                tracing::warn!("rejected OIDC login: {err}");
+                AppError::Unauthorized
+            }
+            other => AppError::internal(other),
+        }
+    }
+}
+
+/// What has to survive the round trip through the provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub csrf_state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+}
+
+#[derive(Debug)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub pending: PendingLogin,
+}
+
+/// The verified claims of an ID token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+    /// Language of the emails for a new account.
+    pub locale: Option<Locale>,
+    /// Time zone for a new account, if the provider knows a valid one.
+    pub time_zone: Option<String>,
+}
+
+/// A client set up from discovered metadata. Only the authorization endpoint
+/// is guaranteed; the token and user info endpoints are optional in discovery.
+type ProviderClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+#[derive(Clone)]
+pub struct Oidc {
+    config: Arc<OidcConfig>,
+    http: reqwest::Client,
+    metadata: Arc<OnceCell<CoreProviderMetadata>>,
+}
+
+impl Oidc {
+    pub fn new(config: OidcConfig) -> Result<Self, OidcError> {
+        let http = reqwest::ClientBuilder::new()
+            // Following redirects would let a provider point us anywhere.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|err| OidcError::Config(err.to_string()))?;
+        Ok(Self {
+            config: Arc::new(config),
+            http,
+            metadata: Arc::new(OnceCell::new()),
+        })
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    async fn metadata(&self) -> Result<&CoreProviderMetadata, OidcError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let issuer = IssuerUrl::new(self.config.issuer_url.clone())
+                    .map_err(|err| OidcError::Config(err.to_string()))?;
+                CoreProviderMetadata::discover_async(issuer, &self.http)
+                    .await
+                    .map_err(|err| OidcError::Discovery(err.to_string()))
+            })
+            .await
+    }
+
+    /// Builds the provider URL to send the user to.
+    pub async fn authorize(&self) -> Result<AuthorizationRequest, OidcError> {
+        let client = self.client().await?;
+
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, csrf_state, nonce) = client
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scope(Scope::new("email".into()))
+            .add_scope(Scope::new("profile".into()))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            pending: PendingLogin {
+                csrf_state: csrf_state.into_secret(),
+                nonce: nonce.secret().clone(),
+                pkce_verifier: pkce_verifier.into_secret(),
+            },
+        })
+    }
+
+    /// Redeems the code the provider sent back and verifies the ID token.
+    pub async fn exchange(
+        &self,
+        code: &str,
+        state: &str,
+        pending: PendingLogin,
+    ) -> Result<ExternalIdentity, OidcError> {
+        if state != pending.csrf_state {
+            return Err(OidcError::StateMismatch);
+        }
+
+        let client = self.client().await?;
+
+        let response = client
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .map_err(|err| OidcError::Config(err.to_string()))?
+            .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+            .request_async(&self.http)
+            .await
+            .map_err(|err| OidcError::Exchange(err.to_string()))?;
+
+        let id_token = response.id_token().ok_or(OidcError::MissingIdToken)?;
+        let claims = id_token
+            .claims(&client.id_token_verifier(), &Nonce::new(pending.nonce))
+            .map_err(|err| OidcError::InvalidIdToken(err.to_string()))?;
+
+        let name = claims
+            .name()
+            .and_then(|name| name.get(None))
+            .map(|name| name.to_string())
+            .or_else(|| claims.preferred_username().map(|name| name.to_string()));
+        Ok(ExternalIdentity {
+            issuer: claims.issuer().to_string(),
+            subject: claims.subject().to_string(),
+            email: claims.email().map(|email| email.to_string()),
+            email_verified: claims.email_verified().unwrap_or(false),
+            name,
+            locale: claims
+                .locale()
+                .and_then(|tag| Locale::from_accept_language(tag.as_str())),
+            time_zone: claims
+                .zoneinfo()
+                .map(|zone| zone.to_string())
+                .filter(|zone| zone.parse::<Tz>().is_ok()),
+        })
+    }
+
+    async fn client(&self) -> Result<ProviderClient, OidcError> {
+        let redirect_url = RedirectUrl::new(self.config.redirect_url.clone())
+            .map_err(|err| OidcError::Config(err.to_string()))?;
+        Ok(CoreClient::from_provider_metadata(
+            self.metadata().await?.clone(),
+            ClientId::new(self.config.client_id.clone()),
+            self.config.client_secret.clone().map(ClientSecret::new),
+        )
+        .set_redirect_uri(redirect_url))
+    }
+}
+
+/// Finds the user an external identity logs in as. Unknown identities are
+/// linked to the account with the same email address, or get a new account,
+/// but only if the provider verified that address. Passwords they get are
+/// hashed with `params`.
+pub fn link_or_create(
+    conn: &mut PgConnection,
+    identity: &ExternalIdentity,
+    params: &HashParams,
+) -> AppResult<User> {
+    conn.transaction(|conn| {
+        let linked: Option<UserIdentity> = user_identities::table
+            .filter(user_identities::issuer.eq(&identity.issuer))
+            .filter(user_identities::subject.eq(&identity.subject))
+            .select(UserIdentity::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?;
+        if let Some(linked) = linked {
+            diesel::update(user_identities::table.find(linked.id))
+                .set((
+                    user_identities::email.eq(&identity.email),
+                    user_identities::last_login_at.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+            return Ok(users::table
+                .find(linked.user_id)
+                .select(User::as_select())
+                .first(conn)?);
+        }
+
+        let email = identity
+            .email
+            .as_deref()
+            .filter(|_| identity.email_verified)
+            .ok_or_else(|| {
+                AppError::Forbidden(
+                    "the identity provider did not confirm your email address".into(),
+                )
+            })?;
+
+        let existing: Option<User> = users::table
+            .filter(users::email.eq(email))
+            .select(User::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?;
+        let user = match existing {
+            Some(user) if user.verified => user,
+            Some(user) => claim_unverified(conn, user, params)?,
+            None => create_user(conn, identity, email, params)?,
+        };
+
+        diesel::insert_into(user_identities::table)
+            .values(NewUserIdentity {
+                user_id: user.id,
+                issuer: identity.issuer.clone(),
+                subject: identity.subject.clone(),
+                email: identity.email.clone(),
+            })
+            .execute(conn)?;
+        tracing::info!(user_id = %user.id, issuer = %identity.issuer, "linked external identity");
+        Ok(user)
+    })
+}
+
+/// Somebody signed up with this address but never proved they own it, while
+/// the provider just did. Whoever set the password may not be the owner, so
+/// it is replaced and their sessions end.
+fn claim_unverified(conn: &mut PgConnection, user: User, params: &HashParams) -> AppResult<User> {
+    let password = password::hash(&token::generate(), params).map_err(AppError::internal)?;
+    let user = diesel::update(users::table.find(user.id))
+        .set((
+            users::verified.eq(true),
+            users::password.eq(password),
+            users::verification_token.eq(None::<String>),
+            users::token_expires_at.eq(None::<chrono::DateTime<Utc>>),
+        ))
+        .returning(User::as_returning())
+        .get_result(conn)?;
+    session::revoke_all(conn, user.id, None)?;
+    tracing::warn!(user_id = %user.id, "unverified account claimed through external login");
+    Ok(user)
+}
+
+fn create_user(
+    conn: &mut PgConnection,
+    identity: &ExternalIdentity,
+    email: &str,
+    params: &HashParams,
+) -> AppResult<User> {
+    let name = identity
+        .name
+        .clone()
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email).to_string());
+    // The account can only be used through the provider until its owner
+    // sets a password of their own.
+    let password = password::hash(&token::generate(), params).map_err(AppError::internal)?;
+
+    Ok(diesel::insert_into(users::table)
+        .values(NewUser {
+            name: name.trim().chars().take(100).collect(),
+            email: email.to_string(),
+            verified: true,
+            password,
+            verification_token: None,
+            token_expires_at: None,
+            locale: identity.locale.unwrap_or_default(),
+            time_zone: identity.time_zone.clone().unwrap_or_else(|| "UTC".into()),
+        })
+        .returning(User::as_returning())
+        .get_result(conn)?)
+}
@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+/// Argon2id cost parameters for new hashes. Existing hashes carry their own
+/// parameters and keep verifying after these change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for HashParams {
+    /// The argon2 crate defaults, which follow the OWASP minimum.
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl HashParams {
+    pub fn argon2(&self) -> Result<Argon2<'static>, argon2::Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+pub fn hash(password: &str, params: &HashParams) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    params
+        .argon2()?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+/// Returns false for malformed hashes as well as for wrong passwords.
+pub fn verify(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed))
+        .is_ok()
+}
+
+/// Whether `hash` was made with anything but argon2id and `params`, so it
+/// should be replaced the next time the password is known.
+pub fn needs_rehash(hash: &str, params: &HashParams) -> bool {
+    let Ok(parsed) = PasswordHash::new(hash) else {
+        return true;
+    };
+    let Ok(current) = Params::try_from(&parsed) else {
+        return true;
+    };
+    parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13.into())
+        || current.m_cost() != params.memory_kib
+        || current.t_cost() != params.iterations
+        || current.p_cost() != params.parallelism
+}
+
+/// Finds the strongest parameters that hash within `target` on this host.
+/// Memory is the main cost, as it is what makes GPU attacks expensive: the
+/// largest amount up to `max_memory_kib` that fits the target with a single
+/// pass is used, then passes are added while there is time left. Returns the
+/// parameters with the time a hash took.
+pub fn recommend(
+    target: Duration,
+    max_memory_kib: u32,
+    parallelism: u32,
+) -> Result<(HashParams, Duration), argon2::Error> {
+    let mut params = HashParams {
+        memory_kib: max_memory_kib,
+        iterations: 1,
+        parallelism,
+    };
+    let mut elapsed = measure(&params)?;
+    while elapsed > target && params.memory_kib / 2 >= Params::DEFAULT_M_COST {
+        params.memory_kib /= 2;
+        elapsed = measure(&params)?;
+    }
+
+    loop {
+        let next = HashParams {
+            iterations: params.iterations + 1,
+            ..params
+        };
+        let next_elapsed = measure(&next)?;
+        if next_elapsed > target {
+            break;
+        }
+        params = next;
+        elapsed = next_elapsed;
+    }
+    Ok((params, elapsed))
+}
+
+/// Best of three, to smooth over noise from other processes.
+fn measure(params: &HashParams) -> Result<Duration, argon2::Error> {
+    let argon2 = params.argon2()?;
+    let mut output = [0u8; 32];
+    let mut best = Duration::MAX;
+    for _ in 0..3 {
+        let start = Instant::now();
+        argon2.hash_password_into(b"benchmark password", b"benchmark salt", &mut output)?;
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
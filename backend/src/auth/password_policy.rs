@@ -0,0 +1,212 @@
+//! Rules every new password has to satisfy, whether it is chosen when
+//! registering, resetting a forgotten password or changing it.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use validator::{ValidationError, ValidationErrors};
+
+use super::breached::BreachedPasswords;
+use super::password::{self, HashParams};
+use super::strength;
+use crate::error::{AppError, AppResult};
+
+/// Hashing cost grows with the length; nobody types more than this.
+pub const MAX_LENGTH: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Minimum strength score from 0 to 4, see [`strength::estimate`].
+    pub min_score: u8,
+    pub breached: Option<Arc<BreachedPasswords>>,
+}
+
+impl PasswordPolicy {
+    /// Checks `candidate` against the policy. `user_inputs` are details of
+    /// the account, such as its name and email address, that must not make
+    /// the password easier to guess. Problems are reported for `field`.
+    pub fn check(
+        &self,
+        candidate: &str,
+        user_inputs: &[&str],
+        field: &'static str,
+    ) -> AppResult<()> {
+        let mut errors = ValidationErrors::new();
+        let length = candidate.chars().count();
+        if length < self.min_length {
+            errors.add(
+                field,
+                error(
+                    "password_too_short",
+                    format!("use at least {} characters", self.min_length),
+                ),
+            );
+        } else if length > MAX_LENGTH {
+            errors.add(
+                field,
+                error(
+                    "password_too_long",
+                    format!("use at most {MAX_LENGTH} characters"),
+                ),
+            );
+        } else {
+            let mut inputs = vec!["notesapp"];
+            inputs.extend_from_slice(user_inputs);
+            let estimate = strength::estimate(candidate, &inputs);
+            if estimate.score < self.min_score {
+                let reason = estimate
+                    .weakness
+                    .map_or("it is not long or varied enough", |weakness| {
+                        weakness.advice()
+                    });
+                errors.add(
+                    field,
+                    error(
+                        "password_too_weak",
+                        format!(
+                            "this password is too easy to guess: {reason}. \
+                             Add another word or two; uncommon words are better"
+                        ),
+                    ),
+                );
+            }
+        }
+
+        if let Some(breached) = &self.breached {
+            if breached.contains(candidate).map_err(AppError::internal)? {
+                errors.add(
+                    field,
+                    error(
+                        "password_breached",
+                        "this password has appeared in a data breach and is likely to be \
+                         tried by attackers; choose a different one"
+                            .into(),
+                    ),
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into())
+        }
+    }
+
+    /// Checks and hashes a new password off the async runtime; both the
+    /// breach lookup and hashing block.
+    pub async fn hash_new(
+        &self,
+        params: HashParams,
+        candidate: String,
+        user_inputs: Vec<String>,
+        field: &'static str,
+    ) -> AppResult<String> {
+        let policy = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let inputs: Vec<&str> = user_inputs.iter().map(String::as_str).collect();
+            policy.check(&candidate, &inputs, field)?;
+            password::hash(&candidate, &params).map_err(AppError::internal)
+        })
+        .await?
+    }
+}
+
+fn error(code: &'static str, message: String) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Owned(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha1::{Digest, Sha1};
+
+    use super::*;
+
+    const BREACHED: &str = "gravel tulip orbit vintage";
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 12,
+            min_score: 3,
+            breached: Some(Arc::new(BreachedPasswords::Hashes(vec![Sha1::digest(
+                BREACHED,
+            )
+            .into()]))),
+        }
+    }
+
+    /// The error codes reported for the `password` field.
+    fn codes(candidate: &str, user_inputs: &[&str]) -> Vec<String> {
+        match policy().check(candidate, user_inputs, "password") {
+            Ok(()) => Vec::new(),
+            Err(AppError::Validation(errors)) => errors.field_errors()["password"]
+                .iter()
+                .map(|error| error.code.to_string())
+                .collect(),
+            Err(other) => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_a_strong_password() {
+        assert!(codes("quiet lantern mossy bicycle", &["Ann Smith"]).is_empty());
+    }
+
+    #[test]
+    fn rejects_short_passwords() {
+        assert_eq!(codes("x7#Lq9!vT2", &[]), ["password_too_short"]);
+        // Length counts characters, not bytes.
+        assert_eq!(codes("ééééééééééé", &[]), ["password_too_short"]);
+    }
+
+    #[test]
+    fn rejects_long_passwords() {
+        let candidate = "quiet lantern ".repeat(10);
+        assert_eq!(codes(&candidate, &[]), ["password_too_long"]);
+    }
+
+    #[test]
+    fn rejects_weak_passwords() {
+        assert_eq!(codes("password1234", &[]), ["password_too_weak"]);
+        assert_eq!(codes("notesapp2024!", &[]), ["password_too_weak"]);
+    }
+
+    #[test]
+    fn rejects_passwords_built_from_user_inputs() {
+        let candidate = "Smithers-Annabel-2";
+        assert!(codes(candidate, &[]).is_empty());
+        assert_eq!(
+            codes(candidate, &["Annabel Smithers", "annabel@example.com"]),
+            ["password_too_weak"]
+        );
+    }
+
+    #[test]
+    fn rejects_breached_passwords() {
+        assert_eq!(codes(BREACHED, &[]), ["password_breached"]);
+        assert_eq!(
+            codes("password", &[]),
+            ["password_too_short"],
+            "not in the breach data"
+        );
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let mut policy = policy();
+        policy.breached = Some(Arc::new(BreachedPasswords::Hashes(vec![Sha1::digest(
+            "password",
+        )
+        .into()])));
+        let Err(AppError::Validation(errors)) = policy.check("password", &[], "new_password")
+        else {
+            panic!("expected validation errors");
+        };
+        let codes: Vec<_> = errors.field_errors()["new_password"]
+            .iter()
+            .map(|error| error.code.to_string())
+            .collect();
+        assert_eq!(codes, ["password_too_short", "password_breached"]);
+    }
+}
@@ -0,0 +1,208 @@
+//! Login sessions backed by rotating refresh tokens.
+//!
+//! Refresh tokens have the form `<session id>.<secret>`; only a hash of the
+//! current secret is stored. Presenting an outdated secret means the token
+//! was copied, so the whole session is revoked.
+
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::header::USER_AGENT;
+use axum::http::request::Parts;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::token;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::models::session::{NewSession, Session};
+use crate::schema::sessions;
+use crate::state::AppState;
+
+/// How stale `last_seen_at` may get before a request refreshes it.
+const LAST_SEEN_RESOLUTION_MINUTES: i64 = 5;
+/// Revoked sessions stay listed in exports for a while before being purged.
+const REVOKED_RETENTION_DAYS: i64 = 30;
+
+/// Where a request came from.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Origin {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Origin {
+            ip_address: parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string()),
+            user_agent: parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+        })
+    }
+}
+
+/// Starts a session and returns it with its first refresh token.
+pub fn create(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    mfa: bool,
+    origin: &Origin,
+    ttl: Duration,
+) -> QueryResult<(Session, String)> {
+    let secret = token::generate();
+    let session: Session = diesel::insert_into(sessions::table)
+        .values(NewSession {
+            user_id,
+            refresh_token_hash: token::hash(&secret),
+            mfa,
+            ip_address: origin.ip_address.clone(),
+            user_agent: origin.user_agent.clone(),
+            expires_at: Utc::now() + ttl,
+        })
+        .returning(Session::as_returning())
+        .get_result(conn)?;
+
+    let refresh_token = format!("{}.{secret}", session.id);
+    Ok((session, refresh_token))
+}
+
+/// Exchanges a refresh token for a new one and extends the session.
+pub fn rotate(
+    conn: &mut PgConnection,
+    refresh_token: &str,
+    origin: &Origin,
+    ttl: Duration,
+) -> AppResult<(Session, String)> {
+    let (session_id, secret) = parse(refresh_token).ok_or(AppError::Unauthorized)?;
+
+    let rotated = conn.transaction(|conn| {
+        let session: Session = sessions::table
+            .find(session_id)
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(Utc::now()))
+            .select(Session::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?
+            .ok_or(AppError::Unauthorized)?;
+        if session.refresh_token_hash != token::hash(secret) {
+            return Ok(None);
+        }
+
+        let secret = token::generate();
+        let session = diesel::update(sessions::table.find(session_id))
+            .set((
+                sessions::refresh_token_hash.eq(token::hash(&secret)),
+                sessions::ip_address.eq(&origin.ip_address),
+                sessions::user_agent.eq(&origin.user_agent),
+                sessions::last_seen_at.eq(Utc::now()),
+                sessions::expires_at.eq(Utc::now() + ttl),
+            ))
+            .returning(Session::as_returning())
+            .get_result(conn)?;
+        Ok::<_, AppError>(Some((session, format!("{session_id}.{secret}"))))
+    })?;
+
+    let Some(rotated) = rotated else {
+        tracing::warn!(%session_id, "refresh token reused, revoking session");
+        diesel::update(sessions::table.find(session_id))
+            .set(sessions::revoked_at.eq(Utc::now()))
+            .execute(conn)?;
+        return Err(AppError::Unauthorized);
+    };
+    Ok(rotated)
+}
+
+/// Returns the session a refresh token belongs to, if the token is current.
+pub fn find_by_refresh_token(
+    conn: &mut PgConnection,
+    refresh_token: &str,
+) -> QueryResult<Option<Session>> {
+    let Some((session_id, secret)) = parse(refresh_token) else {
+        return Ok(None);
+    };
+    sessions::table
+        .find(session_id)
+        .filter(sessions::refresh_token_hash.eq(token::hash(secret)))
+        .select(Session::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Checks that an access token's session is still alive and keeps its
+/// `last_seen_at` roughly up to date.
+pub fn check_active(conn: &mut PgConnection, user_id: Uuid, session_id: Uuid) -> QueryResult<bool> {
+    let now = Utc::now();
+    let last_seen: Option<DateTime<Utc>> = sessions::table
+        .find(session_id)
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(now))
+        .select(sessions::last_seen_at)
+        .first(conn)
+        .optional()?;
+
+    let Some(last_seen) = last_seen else {
+        return Ok(false);
+    };
+    if now - last_seen > Duration::minutes(LAST_SEEN_RESOLUTION_MINUTES) {
+        diesel::update(sessions::table.find(session_id))
+            .set(sessions::last_seen_at.eq(now))
+            .execute(conn)?;
+    }
+    Ok(true)
+}
+
+pub fn revoke(conn: &mut PgConnection, user_id: Uuid, session_id: Uuid) -> QueryResult<bool> {
+    let revoked = diesel::update(sessions::table.find(session_id))
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(Utc::now()))
+        .execute(conn)?;
+    Ok(revoked > 0)
+}
+
+/// Revokes every session of the user except `keep`.
+pub fn revoke_all(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    keep: Option<Uuid>,
+) -> QueryResult<usize> {
+    diesel::update(sessions::table)
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::id.ne_all(keep))
+        .set(sessions::revoked_at.eq(Utc::now()))
+        .execute(conn)
+}
+
+/// Removes expired sessions and revoked ones past their retention.
+pub async fn purge_stale(state: AppState) -> AppResult<usize> {
+    db::run(&state.pool, |conn| {
+        let now = Utc::now();
+        diesel::delete(sessions::table)
+            .filter(
+                sessions::expires_at
+                    .le(now)
+                    .or(sessions::revoked_at.le(now - Duration::days(REVOKED_RETENTION_DAYS))),
+            )
+            .execute(conn)
+    })
+    .await
+}
+
+fn parse(refresh_token: &str) -> Option<(Uuid, &str)> {
+    let (id, secret) = refresh_token.split_once('.')?;
+    Some((id.parse().ok()?, secret))
+}
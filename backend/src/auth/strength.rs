@@ -0,0 +1,363 @@
+//! Password strength estimation in the spirit of zxcvbn: the password is
+//! split into the cheapest combination of guessable patterns (common
+//! passwords, personal details, sequences, keyboard rows, repeats and years)
+//! and brute-forced characters, and the number of guesses an attacker needs
+//! is mapped to a score from 0 to 4.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use chrono::{Datelike, Utc};
+
+/// Ranked by popularity, most common first.
+static COMMON_PASSWORDS: LazyLock<HashMap<&'static str, usize>> = LazyLock::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(rank, word)| (word, rank + 1))
+        .collect()
+});
+
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Orders of magnitude of guesses needed to reach each score.
+const SCORE_THRESHOLDS: [f64; 4] = [3.0, 6.0, 8.0, 10.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weakness {
+    CommonPassword,
+    PersonalInfo,
+    Sequence,
+    KeyboardRow,
+    Repeat,
+    Year,
+}
+
+impl Weakness {
+    pub fn advice(self) -> &'static str {
+        match self {
+            Weakness::CommonPassword => "it is similar to a commonly used password",
+            Weakness::PersonalInfo => "it contains your name or email address",
+            Weakness::Sequence => "sequences like abc or 6543 are easy to guess",
+            Weakness::KeyboardRow => "straight rows of keys like qwerty are easy to guess",
+            Weakness::Repeat => "repeats like aaa or abcabc are easy to guess",
+            Weakness::Year => "years are easy to guess",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    /// Orders of magnitude of guesses needed.
+    pub guesses_log10: f64,
+    /// 0 (guessable within a few attempts) to 4 (very hard to guess).
+    pub score: u8,
+    /// The pattern that contributed most to making the password guessable.
+    pub weakness: Option<Weakness>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    weakness: Weakness,
+}
+
+/// Estimates how hard `password` is to guess. `user_inputs` are words an
+/// attacker targeting this account would try first, such as the user's name.
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Estimate {
+    let chars: Vec<char> = password.chars().collect();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    // Lowercasing can change the length of some scripts; patterns are then
+    // only looked for in the original characters.
+    let lower = if lower.len() == chars.len() {
+        lower
+    } else {
+        chars.clone()
+    };
+
+    let mut matches = Vec::new();
+    dictionary_matches(&chars, &lower, user_inputs, &mut matches);
+    sequence_matches(&lower, &mut matches);
+    keyboard_matches(&lower, &mut matches);
+    repeat_matches(&lower, &mut matches);
+    year_matches(&lower, &mut matches);
+
+    // Cheapest way to guess each prefix of the password.
+    let per_char = bruteforce_cardinality(&chars).log10();
+    let mut best = vec![0.0; chars.len() + 1];
+    let mut used: Vec<Option<Match>> = vec![None; chars.len() + 1];
+    for end in 1..=chars.len() {
+        best[end] = best[end - 1] + per_char;
+        for m in matches.iter().filter(|m| m.end == end) {
+            let cost = best[m.start] + m.guesses.max(1.0).log10();
+            if cost < best[end] {
+                best[end] = cost;
+                used[end] = Some(*m);
+            }
+        }
+    }
+
+    let mut weakest: Option<Match> = None;
+    let mut end = chars.len();
+    while end > 0 {
+        match used[end] {
+            Some(m) => {
+                if weakest.is_none_or(|w| m.end - m.start > w.end - w.start) {
+                    weakest = Some(m);
+                }
+                end = m.start;
+            }
+            None => end -= 1,
+        }
+    }
+
+    let guesses_log10 = best[chars.len()];
+    Estimate {
+        guesses_log10,
+        score: SCORE_THRESHOLDS
+            .iter()
+            .take_while(|&&threshold| guesses_log10 >= threshold)
+            .count() as u8,
+        weakness: weakest.map(|m| m.weakness),
+    }
+}
+
+fn bruteforce_cardinality(chars: &[char]) -> f64 {
+    let mut cardinality = 0.0;
+    if chars.iter().any(char::is_ascii_lowercase) {
+        cardinality += 26.0;
+    }
+    if chars.iter().any(char::is_ascii_uppercase) {
+        cardinality += 26.0;
+    }
+    if chars.iter().any(char::is_ascii_digit) {
+        cardinality += 10.0;
+    }
+    if chars.iter().any(char::is_ascii_punctuation) || chars.contains(&' ') {
+        cardinality += 33.0;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        cardinality += 100.0;
+    }
+    f64::max(cardinality, 10.0)
+}
+
+/// Undoes the usual letter substitutions, e.g. `p4ssw0rd` becomes `password`.
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        other => other,
+    }
+}
+
+fn dictionary_matches(chars: &[char], lower: &[char], user_inputs: &[&str], out: &mut Vec<Match>) {
+    // Each input counts as a whole and by its words, so `Ann Smith` and
+    // `ann.smith@example.com` also catch `smith`.
+    let mut words = Vec::new();
+    for input in user_inputs {
+        let input = input.trim().to_lowercase();
+        words.extend(
+            input
+                .split(|c: char| !c.is_alphanumeric())
+                .map(str::to_string),
+        );
+        words.push(input);
+    }
+    let mut user_inputs: HashMap<String, usize> = HashMap::new();
+    for word in words.into_iter().filter(|word| word.chars().count() >= 3) {
+        let rank = user_inputs.len() + 1;
+        user_inputs.entry(word).or_insert(rank);
+    }
+
+    for start in 0..lower.len() {
+        for end in start + 3..=lower.len() {
+            let word: String = lower[start..end].iter().collect();
+            let unleeted: String = lower[start..end].iter().map(|&c| unleet(c)).collect();
+            let reversed: String = word.chars().rev().collect();
+
+            let candidates = [(&word, 1.0), (&unleeted, 2.0), (&reversed, 2.0)];
+            for (candidate, variations) in candidates {
+                let found = user_inputs
+                    .get(candidate.as_str())
+                    .map(|&rank| (rank, Weakness::PersonalInfo))
+                    .or_else(|| {
+                        COMMON_PASSWORDS
+                            .get(candidate.as_str())
+                            .map(|&rank| (rank, Weakness::CommonPassword))
+                    });
+                if let Some((rank, weakness)) = found {
+                    out.push(Match {
+                        start,
+                        end,
+                        guesses: rank as f64 * variations * case_variations(&chars[start..end]),
+                        weakness,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Capitalizing the first letter or everything is tried early, anything
+/// else multiplies the work.
+fn case_variations(chars: &[char]) -> f64 {
+    let upper = chars.iter().filter(|c| c.is_uppercase()).count();
+    let letters = chars.iter().filter(|c| c.is_alphabetic()).count();
+    if upper == 0 {
+        1.0
+    } else if upper == letters || (upper == 1 && chars[0].is_uppercase()) {
+        2.0
+    } else {
+        2f64.powi(upper.min(letters - upper) as i32 + 1)
+    }
+}
+
+/// Runs like `abcd`, `9876` or `acegi` with a constant step of 1 or 2.
+fn sequence_matches(lower: &[char], out: &mut Vec<Match>) {
+    let mut start = 0;
+    while start + 2 < lower.len() {
+        let step = lower[start + 1] as i64 - lower[start] as i64;
+        let mut end = start + 1;
+        if (1..=2).contains(&step.abs()) {
+            while end < lower.len() && lower[end] as i64 - lower[end - 1] as i64 == step {
+                end += 1;
+            }
+        }
+        if end - start >= 3 && lower[start].is_ascii_alphanumeric() {
+            let first = lower[start];
+            let base = if matches!(first, 'a' | 'z' | '0' | '1' | '9') {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction = if step > 0 { 1.0 } else { 2.0 };
+            out.push(Match {
+                start,
+                end,
+                guesses: base * (end - start) as f64 * direction,
+                weakness: Weakness::Sequence,
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+/// Four or more adjacent keys from one row, in either direction.
+fn keyboard_matches(lower: &[char], out: &mut Vec<Match>) {
+    for start in 0..lower.len() {
+        for end in start + 4..=lower.len() {
+            let run: String = lower[start..end].iter().collect();
+            let reversed: String = run.chars().rev().collect();
+            let forward = KEYBOARD_ROWS.iter().any(|row| row.contains(&run));
+            if forward || KEYBOARD_ROWS.iter().any(|row| row.contains(&reversed)) {
+                let direction = if forward { 1.0 } else { 2.0 };
+                out.push(Match {
+                    start,
+                    end,
+                    guesses: 40.0 * (end - start) as f64 * direction,
+                    weakness: Weakness::KeyboardRow,
+                });
+            }
+        }
+    }
+}
+
+/// A chunk repeated at least twice, like `aaaa` or `abcabc`.
+fn repeat_matches(lower: &[char], out: &mut Vec<Match>) {
+    for start in 0..lower.len() {
+        for len in 1..=(lower.len() - start) / 2 {
+            let chunk = &lower[start..start + len];
+            let mut end = start + len;
+            while end + len <= lower.len() && &lower[end..end + len] == chunk {
+                end += len;
+            }
+            let count = (end - start) / len;
+            if count >= 2 && end - start >= 3 {
+                out.push(Match {
+                    start,
+                    end,
+                    guesses: bruteforce_cardinality(chunk).powi(len as i32) * count as f64,
+                    weakness: Weakness::Repeat,
+                });
+            }
+        }
+    }
+}
+
+/// Four digit years from 1900 to 2099; recent ones are tried first.
+fn year_matches(lower: &[char], out: &mut Vec<Match>) {
+    let current = Utc::now().year();
+    for start in 0..lower.len().saturating_sub(3) {
+        let digits = &lower[start..start + 4];
+        if !digits.iter().all(char::is_ascii_digit) {
+            continue;
+        }
+        let year = digits
+            .iter()
+            .fold(0, |year, &digit| year * 10 + (digit as i32 - '0' as i32));
+        if (1900..=2099).contains(&year) {
+            out.push(Match {
+                start,
+                end: start + 4,
+                guesses: f64::from((year - current).abs().max(20)),
+                weakness: Weakness::Year,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weakness(password: &str) -> Option<Weakness> {
+        estimate(password, &[]).weakness
+    }
+
+    #[test]
+    fn common_passwords_score_zero() {
+        for password in ["password", "P4ssw0rd", "drowssap"] {
+            let estimate = estimate(password, &[]);
+            assert_eq!(estimate.score, 0, "{password}");
+            assert_eq!(estimate.weakness, Some(Weakness::CommonPassword));
+        }
+    }
+
+    #[test]
+    fn user_inputs_make_passwords_weaker() {
+        let inputs = ["Annabel Smithers", "annabel.smithers@example.com"];
+        let without = estimate("smithersannabel", &[]);
+        let with = estimate("smithersannabel", &inputs);
+        assert!(with.guesses_log10 < without.guesses_log10);
+        assert_eq!(with.weakness, Some(Weakness::PersonalInfo));
+        assert_eq!(with.score, 0);
+    }
+
+    #[test]
+    fn finds_guessable_patterns() {
+        assert_eq!(weakness("abcdefghij"), Some(Weakness::Sequence));
+        assert_eq!(weakness("hgfedcba"), Some(Weakness::Sequence));
+        assert_eq!(weakness("sdfghjk"), Some(Weakness::KeyboardRow));
+        assert_eq!(weakness("xkxkxkxkxk"), Some(Weakness::Repeat));
+        assert_eq!(weakness("1987"), Some(Weakness::Year));
+    }
+
+    #[test]
+    fn long_unusual_passwords_score_four() {
+        let estimate = estimate("gravel tulip orbit vintage", &["Ann Smith"]);
+        assert_eq!(estimate.score, 4);
+    }
+}
@@ -0,0 +1,176 @@
+//! TOTP based two-factor authentication with single-use recovery codes.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use super::password::{self, HashParams};
+use crate::error::{AppError, AppResult};
+use crate::models::recovery_code::{NewRecoveryCode, RecoveryCode};
+use crate::schema::{recovery_codes, users};
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+/// Lowercase letters and digits without look-alikes such as `l`, `1`, `o` and `0`.
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+#[derive(Debug, Serialize)]
+pub struct Enrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub qr_svg: String,
+}
+
+/// Generates a fresh secret for `account` and everything an authenticator
+/// app needs to pick it up.
+pub fn enroll(issuer: &str, account: &str) -> AppResult<Enrollment> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let totp = build(&secret, issuer, account)?;
+    let otpauth_uri = totp.get_url();
+    let qr_svg = QrCode::new(otpauth_uri.as_bytes())
+        .map_err(AppError::internal)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    Ok(Enrollment {
+        secret,
+        otpauth_uri,
+        qr_svg,
+    })
+}
+
+/// Checks `code` against the current time step and its direct neighbours.
+/// Returns the matched step, which must be newer than `last_step` so that a
+/// code cannot be replayed.
+pub fn verify_code(secret: &str, code: &str, last_step: Option<i64>) -> AppResult<Option<i64>> {
+    verify_code_at(secret, code, last_step, Utc::now())
+}
+
+/// [`verify_code`] as of `now`.
+pub fn verify_code_at(
+    secret: &str,
+    code: &str,
+    last_step: Option<i64>,
+    now: DateTime<Utc>,
+) -> AppResult<Option<i64>> {
+    let totp = build(secret, "", "")?;
+    let code = code.trim();
+    let current = now.timestamp() / STEP_SECONDS as i64;
+
+    let matched = (current - 1..=current + 1)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| totp.check(code, *step as u64 * STEP_SECONDS));
+    Ok(matched)
+}
+
+/// Records `step` as the last one used by `user_id`, unless another request
+/// got there first with the same or a newer one. Returns false in that
+/// case, so the code counts as replayed.
+pub fn use_step(conn: &mut PgConnection, user_id: Uuid, step: i64) -> QueryResult<bool> {
+    let updated = diesel::update(users::table.find(user_id))
+        .filter(
+            users::totp_last_step
+                .is_null()
+                .or(users::totp_last_step.lt(step)),
+        )
+        .set(users::totp_last_step.eq(step))
+        .execute(conn)?;
+    Ok(updated > 0)
+}
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars = (0..10)
+                .map(|_| *RECOVERY_ALPHABET.choose(&mut rng).unwrap() as char)
+                .collect::<String>();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+/// Replaces all recovery codes of the user with the hashes of `codes`.
+pub fn store_recovery_codes(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    codes: &[String],
+) -> AppResult<()> {
+    // The codes are random, so the cheaper default parameters are plenty and
+    // enrolling stays fast however costly password hashing is configured.
+    let params = HashParams::default();
+    let rows = codes
+        .iter()
+        .map(|code| {
+            Ok(NewRecoveryCode {
+                user_id,
+                code_hash: password::hash(&normalize(code), &params).map_err(AppError::internal)?,
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    conn.transaction(|conn| {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::insert_into(recovery_codes::table)
+            .values(&rows)
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Marks a matching unused recovery code as used. Returns false if none matched.
+pub fn consume_recovery_code(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    code: &str,
+) -> QueryResult<bool> {
+    let code = normalize(code);
+    let unused: Vec<RecoveryCode> = recovery_codes::table
+        .filter(recovery_codes::user_id.eq(user_id))
+        .filter(recovery_codes::used_at.is_null())
+        .select(RecoveryCode::as_select())
+        .load(conn)?;
+
+    let Some(matched) = unused
+        .iter()
+        .find(|candidate| password::verify(&code, &candidate.code_hash))
+    else {
+        return Ok(false);
+    };
+
+    let updated = diesel::update(recovery_codes::table.find(matched.id))
+        .filter(recovery_codes::used_at.is_null())
+        .set(recovery_codes::used_at.eq(Utc::now()))
+        .execute(conn)?;
+    Ok(updated == 1)
+}
+
+fn build(secret: &str, issuer: &str, account: &str) -> AppResult<TOTP> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|err| AppError::internal(format!("invalid TOTP secret: {err:?}")))?;
+    let issuer = (!issuer.is_empty()).then(|| issuer.to_string());
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECONDS,
+        bytes,
+        issuer,
+        account.to_string(),
+    )
+    .map_err(AppError::internal)
+}
+
+fn normalize(code: &str) -> String {
+    code.trim().to_lowercase().replace(' ', "")
+}
@@ -0,0 +1,162 @@
+//! Writes iCalendar (RFC 5545) text: escaped values, folded lines, and the
+//! time zone definitions that local times refer to.
+
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, Offset, TimeDelta, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz, TzOffset};
+
+/// Longest line in octets, without the line break.
+const LINE_LIMIT: usize = 75;
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    out: String,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin(&mut self, component: &str) {
+        self.raw("BEGIN", component);
+    }
+
+    pub fn end(&mut self, component: &str) {
+        self.raw("END", component);
+    }
+
+    /// A property whose value needs no escaping, e.g. a number or a rule.
+    pub fn raw(&mut self, name: &str, value: &str) {
+        let line = format!("{name}:{value}");
+        self.fold(&line);
+    }
+
+    /// A TEXT property.
+    pub fn text(&mut self, name: &str, value: &str) {
+        self.raw(name, &escape(value));
+    }
+
+    /// A DATE-TIME property in UTC.
+    pub fn utc(&mut self, name: &str, time: DateTime<Utc>) {
+        self.raw(name, &time.format("%Y%m%dT%H%M%SZ").to_string());
+    }
+
+    /// A DATE-TIME property in local time of `tz`, which needs a
+    /// [`Writer::time_zone`] definition in the same calendar.
+    pub fn local(&mut self, name: &str, time: DateTime<Utc>, tz: Tz) {
+        let local = time.with_timezone(&tz).format("%Y%m%dT%H%M%S");
+        self.raw(&format!("{name};TZID={}", tz.name()), &local.to_string());
+    }
+
+    /// Defines `tz` with every offset change in `years`. Calendar apps keep
+    /// using the last offset after the range.
+    pub fn time_zone(&mut self, tz: Tz, years: RangeInclusive<i32>) {
+        let start = Utc
+            .with_ymd_and_hms(*years.start(), 1, 1, 0, 0, 0)
+            .single()
+            .unwrap_or(DateTime::UNIX_EPOCH);
+        let end = Utc
+            .with_ymd_and_hms(years.end() + 1, 1, 1, 0, 0, 0)
+            .single()
+            .unwrap_or(start);
+        let offset_at = |time: DateTime<Utc>| tz.offset_from_utc_datetime(&time.naive_utc());
+
+        self.begin("VTIMEZONE");
+        self.raw("TZID", tz.name());
+        let mut current = offset_at(start);
+        self.observance(start, &current, &current);
+        // Offsets change at most once a day, at a quarter hour.
+        let mut day = start;
+        while day < end {
+            let next_day = day + TimeDelta::days(1);
+            if !same_offset(&offset_at(next_day), &current) {
+                let mut change = day;
+                while same_offset(&offset_at(change), &current) {
+                    change += TimeDelta::minutes(15);
+                }
+                let next = offset_at(change);
+                self.observance(change, &current, &next);
+                current = next;
+            }
+            day = next_day;
+        }
+        self.end("VTIMEZONE");
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    /// The offset `to` that applies from `from` on.
+    fn observance(&mut self, at: DateTime<Utc>, from: &TzOffset, to: &TzOffset) {
+        let component = if to.dst_offset().is_zero() {
+            "STANDARD"
+        } else {
+            "DAYLIGHT"
+        };
+        self.begin(component);
+        // In the local time before the change.
+        let local = at.naive_utc() + TimeDelta::seconds(from.fix().local_minus_utc().into());
+        self.raw("DTSTART", &local.format("%Y%m%dT%H%M%S").to_string());
+        self.raw("TZOFFSETFROM", &utc_offset(from));
+        self.raw("TZOFFSETTO", &utc_offset(to));
+        if let Some(name) = to.abbreviation() {
+            self.text("TZNAME", name);
+        }
+        self.end(component);
+    }
+
+    /// Splits `line` into lines of at most [`LINE_LIMIT`] octets, without
+    /// breaking characters apart. Continuation lines start with a space.
+    fn fold(&mut self, line: &str) {
+        let mut limit = LINE_LIMIT;
+        let mut rest = line;
+        while rest.len() > limit {
+            let mut split = limit;
+            while !rest.is_char_boundary(split) {
+                split -= 1;
+            }
+            self.out.push_str(&rest[..split]);
+            self.out.push_str("\r\n ");
+            rest = &rest[split..];
+            limit = LINE_LIMIT - 1;
+        }
+        self.out.push_str(rest);
+        self.out.push_str("\r\n");
+    }
+}
+
+fn same_offset(a: &TzOffset, b: &TzOffset) -> bool {
+    a.fix() == b.fix() && a.dst_offset() == b.dst_offset()
+}
+
+/// `+0100`, with seconds only when there are any.
+fn utc_offset(offset: &TzOffset) -> String {
+    let seconds = offset.fix().local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.unsigned_abs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if seconds == 0 {
+        format!("{sign}{hours:02}{minutes:02}")
+    } else {
+        format!("{sign}{hours:02}{minutes:02}{seconds:02}")
+    }
+}
+
+/// Escapes a TEXT value.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
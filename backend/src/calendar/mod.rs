@@ -0,0 +1,145 @@
+//! Calendar feeds: the notes with reminders as an iCalendar file behind a
+//! secret URL that calendar apps can subscribe to. Creating a new URL
+//! revokes the old one.
+//!
+//! Every reminder becomes an event at its current occurrence, in the
+//! owner's time zone, with its recurrence rule.
+
+pub mod ical;
+
+use chrono::{DateTime, Datelike, NaiveTime, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use self::ical::Writer;
+use crate::auth::token;
+use crate::models::note::Note;
+use crate::models::reminder::NoteReminder;
+use crate::models::user::User;
+use crate::reminders::recurrence::{Recurrence, Until};
+use crate::schema::{note_reminders, notes, users};
+
+/// Years after the current one that time zone changes are listed for.
+const TIME_ZONE_YEARS: i32 = 10;
+
+pub fn url(public_url: &str, feed_token: &str) -> String {
+    format!("{}/cal/{feed_token}.ics", public_url.trim_end_matches('/'))
+}
+
+/// Gives the user a new feed token, which replaces the old one.
+pub fn regenerate_token(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<String> {
+    let feed_token = token::generate();
+    diesel::update(users::table.find(user_id))
+        .set(users::calendar_token_hash.eq(token::hash(&feed_token)))
+        .execute(conn)?;
+    Ok(feed_token)
+}
+
+/// Turns the feed off. Returns whether it was on.
+pub fn revoke_token(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<bool> {
+    let updated = diesel::update(users::table.find(user_id))
+        .filter(users::calendar_token_hash.is_not_null())
+        .set(users::calendar_token_hash.eq(None::<String>))
+        .execute(conn)?;
+    Ok(updated > 0)
+}
+
+/// The feed `feed_token` belongs to, or `None` if it belongs to no one.
+pub fn feed(
+    conn: &mut PgConnection,
+    feed_token: &str,
+    public_url: &str,
+) -> QueryResult<Option<String>> {
+    let Some(user) = users::table
+        .filter(users::calendar_token_hash.eq(token::hash(feed_token)))
+        .select(User::as_select())
+        .first(conn)
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let entries: Vec<(Note, NoteReminder)> = notes::table
+        .inner_join(note_reminders::table)
+        .filter(notes::user_id.eq(user.id))
+        .order(note_reminders::remind_at.asc())
+        .select((Note::as_select(), NoteReminder::as_select()))
+        .load(conn)?;
+    Ok(Some(render(user.tz(), &entries, public_url, Utc::now())))
+}
+
+/// Writes the calendar for notes with their reminders. Time zone changes
+/// are listed from the first reminder until some years after `now`.
+pub fn render(
+    tz: Tz,
+    entries: &[(Note, NoteReminder)],
+    public_url: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let public_url = public_url.trim_end_matches('/');
+    let mut calendar = Writer::new();
+    calendar.begin("VCALENDAR");
+    calendar.raw("VERSION", "2.0");
+    calendar.raw("PRODID", "-//NotesApp//Reminders//EN");
+    calendar.raw("CALSCALE", "GREGORIAN");
+    calendar.text("X-WR-CALNAME", "NotesApp");
+    calendar.raw("X-WR-TIMEZONE", tz.name());
+
+    let this_year = now.year();
+    let first_year = entries
+        .iter()
+        .map(|(_, reminder)| reminder.remind_at.year())
+        .min()
+        .unwrap_or(this_year)
+        .min(this_year);
+    calendar.time_zone(tz, first_year..=this_year + TIME_ZONE_YEARS);
+
+    for (note, reminder) in entries {
+        calendar.begin("VEVENT");
+        calendar.text("UID", &format!("{}@notesapp", note.id));
+        calendar.utc("DTSTAMP", reminder.updated_at);
+        calendar.utc("CREATED", reminder.created_at);
+        calendar.utc("LAST-MODIFIED", note.updated_at.max(reminder.updated_at));
+        calendar.local("DTSTART", reminder.remind_at, tz);
+        if let Some(rule) = reminder
+            .recurrence
+            .as_deref()
+            .and_then(|rule| rule.parse::<Recurrence>().ok())
+        {
+            calendar.raw("RRULE", &for_calendar(rule, tz).to_string());
+        }
+        let title = if note.title.trim().is_empty() {
+            "Untitled note"
+        } else {
+            &note.title
+        };
+        calendar.text("SUMMARY", title);
+        if !note.content.is_empty() {
+            calendar.text("DESCRIPTION", &note.content);
+        }
+        calendar.raw("URL", &format!("{public_url}/notes/{}", note.id));
+        if reminder.completed_at.is_none() {
+            calendar.begin("VALARM");
+            calendar.raw("ACTION", "DISPLAY");
+            calendar.text("DESCRIPTION", title);
+            calendar.raw("TRIGGER", "PT0S");
+            calendar.end("VALARM");
+        }
+        calendar.end("VEVENT");
+    }
+
+    calendar.end("VCALENDAR");
+    calendar.finish()
+}
+
+/// RFC 5545 wants `UNTIL` in UTC when the start has a time zone, so an end
+/// date becomes the end of that day in `tz`.
+fn for_calendar(mut rule: Recurrence, tz: Tz) -> Recurrence {
+    if let Some(Until::Date(date)) = rule.until {
+        let end_of_day = date.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap_or_default());
+        if let Some(time) = end_of_day.and_local_timezone(tz).latest() {
+            rule.until = Some(Until::Time(time.with_timezone(&Utc)));
+        }
+    }
+    rule
+}
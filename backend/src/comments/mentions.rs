@@ -0,0 +1,50 @@
+//! `@mentions` in comments. People are mentioned by their email address,
+//! like `@ann@example.com`, since names need not be unique.
+
+/// The addresses mentioned in `text`, lowercased, each once and in the
+/// order they first appear.
+pub fn parse(text: &str) -> Vec<String> {
+    let mut mentioned: Vec<String> = Vec::new();
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        // An `@` inside a word belongs to a plain address, not a mention.
+        let starts_mention = c == '@' && !previous.is_some_and(is_local_char);
+        previous = Some(c);
+        if !starts_mention {
+            continue;
+        }
+        if let Some(address) = address_at(&text[i + 1..]) {
+            let address = address.to_lowercase();
+            if !mentioned.contains(&address) {
+                mentioned.push(address);
+            }
+        }
+    }
+    mentioned
+}
+
+/// The email address at the start of `text`, without punctuation that
+/// ends the sentence around it.
+fn address_at(text: &str) -> Option<&str> {
+    let local_len = text.find(|c| !is_local_char(c)).unwrap_or(text.len());
+    if local_len == 0 || !text[local_len..].starts_with('@') {
+        return None;
+    }
+    let domain_start = local_len + 1;
+    let rest = &text[domain_start..];
+    let domain = &rest[..rest.find(|c| !is_domain_char(c)).unwrap_or(rest.len())];
+    let domain = domain.trim_end_matches(['.', '-']);
+    let valid = domain.contains('.')
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && !label.starts_with('-'));
+    valid.then(|| &text[..domain_start + domain.len()])
+}
+
+fn is_local_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-')
+}
+
+fn is_domain_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '-')
+}
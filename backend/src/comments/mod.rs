@@ -0,0 +1,231 @@
+//! Comments on notes, in threads. The first comment of a thread may be
+//! anchored to part of the note's content; replies follow it, and the
+//! thread is resolved as a whole. Authors edit and delete their own
+//! comments, and people they mention are notified, see [`mentions`].
+//!
+//! The owner of a note and those it is shared with as commenters can
+//! comment; viewers only read along, see [`crate::shares`].
+
+pub mod mentions;
+
+use std::ops::Range;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::mail::Email;
+use crate::models::comment::{NewNoteComment, NoteComment};
+use crate::models::note::Note;
+use crate::models::notification::{NewNotification, NotificationKind};
+use crate::models::user::User;
+use crate::notifications;
+use crate::schema::note_comments;
+use crate::shares;
+
+/// All comments on the note, oldest first.
+pub fn list(conn: &mut PgConnection, note_id: Uuid) -> QueryResult<Vec<NoteComment>> {
+    note_comments::table
+        .filter(note_comments::note_id.eq(note_id))
+        .order((note_comments::created_at.asc(), note_comments::id.asc()))
+        .select(NoteComment::as_select())
+        .load(conn)
+}
+
+pub fn find(conn: &mut PgConnection, note_id: Uuid, id: Uuid) -> QueryResult<NoteComment> {
+    note_comments::table
+        .find(id)
+        .filter(note_comments::note_id.eq(note_id))
+        .select(NoteComment::as_select())
+        .first(conn)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    /// Any comment of the thread to reply to; starts a new thread if `None`.
+    pub reply_to: Option<Uuid>,
+    pub body: String,
+    /// Characters of the note's content that a new thread is about.
+    pub anchor: Option<Range<usize>>,
+}
+
+/// Adds a comment by `author` and notifies those it mentions. Replies to a
+/// reply join the same thread.
+pub fn create(
+    conn: &mut PgConnection,
+    note: &Note,
+    author: &User,
+    draft: Draft,
+    public_url: &str,
+) -> Result<NoteComment, AppError> {
+    let parent_id = match draft.reply_to {
+        Some(id) => {
+            if draft.anchor.is_some() {
+                return Err(AppError::BadRequest(
+                    "only the first comment of a thread can be anchored".into(),
+                ));
+            }
+            let replied = find(conn, note.id, id).optional()?.ok_or_else(|| {
+                AppError::BadRequest("the comment to reply to does not exist".into())
+            })?;
+            Some(replied.parent_id.unwrap_or(replied.id))
+        }
+        None => None,
+    };
+    let (anchor_start, anchor_end, anchor_text) = match draft.anchor {
+        Some(range) => {
+            let text = anchored_text(&note.content, range.clone()).ok_or_else(|| {
+                AppError::BadRequest("the anchor is outside the note's content".into())
+            })?;
+            (Some(range.start as i32), Some(range.end as i32), Some(text))
+        }
+        None => (None, None, None),
+    };
+
+    let comment: NoteComment = diesel::insert_into(note_comments::table)
+        .values(NewNoteComment {
+            note_id: note.id,
+            user_id: author.id,
+            parent_id,
+            body: draft.body,
+            anchor_start,
+            anchor_end,
+            anchor_text,
+        })
+        .returning(NoteComment::as_returning())
+        .get_result(conn)?;
+    notify_mentioned(conn, note, &comment, author, &[], public_url)?;
+    Ok(comment)
+}
+
+/// Changes the body of `author`'s comment. Only people who were not
+/// mentioned before are notified.
+pub fn edit(
+    conn: &mut PgConnection,
+    note: &Note,
+    comment: &NoteComment,
+    author: &User,
+    body: String,
+    public_url: &str,
+) -> Result<NoteComment, AppError> {
+    if comment.user_id != author.id {
+        return Err(AppError::Forbidden(
+            "only the author can change a comment".into(),
+        ));
+    }
+    let mentioned_before = mentions::parse(&comment.body);
+    let comment: NoteComment = diesel::update(note_comments::table.find(comment.id))
+        .set(note_comments::body.eq(body))
+        .returning(NoteComment::as_returning())
+        .get_result(conn)?;
+    notify_mentioned(conn, note, &comment, author, &mentioned_before, public_url)?;
+    Ok(comment)
+}
+
+/// Deletes `user_id`'s comment, and the replies if it starts a thread.
+pub fn delete(
+    conn: &mut PgConnection,
+    comment: &NoteComment,
+    user_id: Uuid,
+) -> Result<(), AppError> {
+    if comment.user_id != user_id {
+        return Err(AppError::Forbidden(
+            "only the author can delete a comment".into(),
+        ));
+    }
+    diesel::delete(note_comments::table.find(comment.id)).execute(conn)?;
+    Ok(())
+}
+
+/// Resolves or reopens the thread that `comment` starts. A thread that is
+/// resolved again keeps who resolved it first.
+pub fn set_resolved(
+    conn: &mut PgConnection,
+    comment: &NoteComment,
+    user_id: Uuid,
+    resolved: bool,
+) -> Result<NoteComment, AppError> {
+    if comment.parent_id.is_some() {
+        return Err(AppError::BadRequest(
+            "replies are resolved with their thread".into(),
+        ));
+    }
+    if resolved == comment.resolved_at.is_some() {
+        return Ok(comment.clone());
+    }
+    let (resolved_at, resolved_by) = if resolved {
+        (Some(Utc::now()), Some(user_id))
+    } else {
+        (None, None)
+    };
+    Ok(diesel::update(note_comments::table.find(comment.id))
+        .set((
+            note_comments::resolved_at.eq(resolved_at),
+            note_comments::resolved_by.eq(resolved_by),
+        ))
+        .returning(NoteComment::as_returning())
+        .get_result(conn)?)
+}
+
+/// The characters of `content` in `range`, if it lies within it.
+fn anchored_text(content: &str, range: Range<usize>) -> Option<String> {
+    if range.is_empty() || range.end > content.chars().count() || range.end > i32::MAX as usize {
+        return None;
+    }
+    Some(
+        content
+            .chars()
+            .skip(range.start)
+            .take(range.len())
+            .collect(),
+    )
+}
+
+/// Notifies the members of `note` that `comment` mentions, except its
+/// author and those in `already_mentioned`. Nobody else learns about the
+/// note.
+fn notify_mentioned(
+    conn: &mut PgConnection,
+    note: &Note,
+    comment: &NoteComment,
+    author: &User,
+    already_mentioned: &[String],
+    public_url: &str,
+) -> QueryResult<()> {
+    let mentioned: Vec<String> = mentions::parse(&comment.body)
+        .into_iter()
+        .filter(|address| !already_mentioned.contains(address))
+        .collect();
+    if mentioned.is_empty() {
+        return Ok(());
+    }
+    for user in shares::members(conn, note)? {
+        if user.id == author.id || !mentioned.contains(&user.email.to_lowercase()) {
+            continue;
+        }
+        notifications::notify(
+            conn,
+            &user,
+            &NewNotification {
+                user_id: user.id,
+                kind: NotificationKind::Mention,
+                note_id: Some(note.id),
+                data: json!({
+                    "note_title": note.title,
+                    "comment_id": comment.id,
+                    "author_name": author.name,
+                }),
+            },
+            Email::Mention {
+                name: user.name.clone(),
+                author: author.name.clone(),
+                note_title: note.title.clone(),
+                comment: comment.body.clone(),
+                link: format!("{}/notes/{}", public_url.trim_end_matches('/'), note.id),
+            },
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! The Postgres connection pool. Diesel is synchronous, so queries run on
+//! tokio's blocking thread pool through [`run`]; async worker threads never
+//! wait for the database, not even while the pool is exhausted.
+
+use std::time::Duration;
+
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, Pool, PoolError};
+use diesel::{sql_query, PgConnection, RunQueryDsl};
+use serde::Serialize;
+
+use crate::error::{AppError, AppResult};
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    /// Connections kept open while idle; `None` keeps `max_size` open.
+    pub min_idle: Option<u32>,
+    /// How long a query waits for a free connection before giving up.
+    pub connection_timeout: Duration,
+    /// Idle connections above `min_idle` are closed after this long.
+    pub idle_timeout: Option<Duration>,
+    /// Connections are replaced after this long, whether idle or not.
+    pub max_lifetime: Option<Duration>,
+    /// Cancels statements that run longer than this.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_idle: Some(1),
+            connection_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            statement_timeout: None,
+        }
+    }
+}
+
+/// Applies per-session settings to every new connection.
+#[derive(Debug)]
+struct SessionSettings {
+    statement_timeout: Option<Duration>,
+}
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for SessionSettings {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        if let Some(timeout) = self.statement_timeout {
+            sql_query(format!("SET statement_timeout = {}", timeout.as_millis()))
+                .execute(conn)
+                .map_err(r2d2::Error::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the pool. Fails if the initial connections cannot be established
+/// within the connection timeout.
+pub fn build_pool(database_url: &str, config: &PoolConfig) -> Result<DbPool, PoolError> {
+    Pool::builder()
+        .max_size(config.max_size)
+        .min_idle(config.min_idle)
+        .connection_timeout(config.connection_timeout)
+        .idle_timeout(config.idle_timeout)
+        .max_lifetime(config.max_lifetime)
+        .connection_customizer(Box::new(SessionSettings {
+            statement_timeout: config.statement_timeout,
+        }))
+        .build(ConnectionManager::new(database_url))
+}
+
+/// Runs blocking Diesel work on a pooled connection without stalling the
+/// async runtime.
+pub async fn run<F, T, E>(pool: &DbPool, f: F) -> AppResult<T>
+where
+    F: FnOnce(&mut PgConnection) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Into<AppError>,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        f(&mut conn).map_err(Into::into)
+    })
+    .await?
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PoolStatus {
+    pub connections: u32,
+    pub idle: u32,
+    pub max_size: u32,
+}
+
+/// Checks that a connection can be checked out and answers a query.
+pub async fn health_check(pool: &DbPool) -> AppResult<PoolStatus> {
+    run(pool, |conn| sql_query("SELECT 1").execute(conn)).await?;
+    let state = pool.state();
+    Ok(PoolStatus {
+        connections: state.connections,
+        idle: state.idle_connections,
+        max_size: pool.max_size(),
+    })
+}
@@ -0,0 +1,300 @@
+//! The error type of every handler. Errors are returned to clients as RFC 7807
+//! problem details (`application/problem+json`):
+//!
+//! ```json
+//! {
+//!   "type": "urn:notesapp:problem:validation-failed",
+//!   "title": "The request is invalid",
+//!   "status": 400,
+//!   "detail": "password: use at least 10 characters",
+//!   "errors": { "password": [{ "code": "password_too_short", "message": "use at least 10 characters" }] }
+//! }
+//! ```
+//!
+//! `type` tells clients what went wrong, `detail` explains it to humans.
+//! Details of internal errors are only logged.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+pub type AppResult<T> = Result<T, AppError>;
+
+const PROBLEM_TYPE_PREFIX: &str = "urn:notesapp:problem:";
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{}", describe(.0))]
+    Validation(ValidationErrors),
+    #[error("authentication required")]
+    Unauthorized,
+    #[error("invalid email or password")]
+    InvalidCredentials,
+    #[error("invalid two-factor code")]
+    InvalidTwoFactorCode,
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("not found")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Gone(String),
+    #[error("method not allowed")]
+    MethodNotAllowed,
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    #[error("{message}")]
+    TooManyRequests {
+        message: String,
+        retry_after: Duration,
+    },
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn internal(err: impl std::fmt::Display) -> Self {
+        AppError::Internal(err.to_string())
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) | AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized
+            | AppError::InvalidCredentials
+            | AppError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Gone(_) => StatusCode::GONE,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The problem type slug and its title.
+    fn problem(&self) -> (&'static str, &'static str) {
+        match self {
+            AppError::BadRequest(_) => ("bad-request", "The request cannot be processed"),
+            AppError::Validation(_) => ("validation-failed", "The request is invalid"),
+            AppError::Unauthorized => ("unauthorized", "Authentication required"),
+            AppError::InvalidCredentials => ("invalid-credentials", "Invalid email or password"),
+            AppError::InvalidTwoFactorCode => {
+                ("invalid-two-factor-code", "Invalid two-factor code")
+            }
+            AppError::Forbidden(_) => ("forbidden", "Not allowed"),
+            AppError::NotFound => ("not-found", "Not found"),
+            AppError::Conflict(_) => ("conflict", "Conflicts with existing data"),
+            AppError::Gone(_) => ("gone", "No longer available"),
+            AppError::MethodNotAllowed => ("method-not-allowed", "Method not allowed"),
+            AppError::PayloadTooLarge(_) => ("payload-too-large", "The request body is too large"),
+            AppError::UnsupportedMediaType(_) => {
+                ("unsupported-media-type", "Unsupported media type")
+            }
+            AppError::TooManyRequests { .. } => ("too-many-requests", "Too many requests"),
+            AppError::Unavailable(_) => ("unavailable", "Temporarily unavailable"),
+            AppError::Internal(_) => ("internal", "Internal server error"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FieldError {
+    code: String,
+    message: String,
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let (slug, title) = self.problem();
+        let detail = match &self {
+            AppError::Internal(details) => {
+                tracing::error!("internal error: {details}");
+                "internal server error".to_string()
+            }
+            other => other.to_string(),
+        };
+
+        let mut body = json!({
+            "type": format!("{PROBLEM_TYPE_PREFIX}{slug}"),
+            "title": title,
+            "status": status.as_u16(),
+            "detail": detail,
+        });
+        if let AppError::Validation(errors) = &self {
+            body["errors"] = json!(field_errors(errors));
+        }
+
+        let mut response = (status, Json(body)).into_response();
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        if let AppError::TooManyRequests { retry_after, .. } = self {
+            // Round up so clients never retry a moment too early.
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.headers_mut().insert(RETRY_AFTER, seconds.into());
+        }
+        response
+    }
+}
+
+/// Validation errors by field path, such as `tags` or `items[2].name`.
+fn field_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<FieldError>> {
+    fn collect(
+        errors: &ValidationErrors,
+        prefix: &str,
+        out: &mut BTreeMap<String, Vec<FieldError>>,
+    ) {
+        for (field, kind) in errors.errors() {
+            let path = if prefix.is_empty() {
+                field.to_string()
+            } else {
+                format!("{prefix}.{field}")
+            };
+            match kind {
+                ValidationErrorsKind::Field(field_errors) => {
+                    out.entry(path)
+                        .or_default()
+                        .extend(field_errors.iter().map(|error| FieldError {
+                            code: error.code.to_string(),
+                            message: error.message.as_ref().map_or_else(
+                                || default_message(error),
+                                |message| message.to_string(),
+                            ),
+                        }));
+                }
+                ValidationErrorsKind::Struct(nested) => collect(nested, &path, out),
+                ValidationErrorsKind::List(items) => {
+                    for (index, nested) in items {
+                        collect(nested, &format!("{path}[{index}]"), out);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    collect(errors, "", &mut out);
+    out
+}
+
+/// Explains the checks of the validator crate that come without a message.
+fn default_message(error: &ValidationError) -> String {
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("email", _, _) => "must be a valid email address".into(),
+        ("url", _, _) => "must be a valid URL".into(),
+        ("required", _, _) => "is required".into(),
+        ("length", Some(min), Some(max)) => format!("must have a length between {min} and {max}"),
+        ("length", Some(min), None) => format!("must have a length of at least {min}"),
+        ("length", None, Some(max)) => format!("must have a length of at most {max}"),
+        ("range", Some(min), Some(max)) => format!("must be between {min} and {max}"),
+        ("range", Some(min), None) => format!("must be at least {min}"),
+        ("range", None, Some(max)) => format!("must be at most {max}"),
+        (code, _, _) => code.to_string(),
+    }
+}
+
+/// All problems on one line, e.g. `password: use at least 10 characters`.
+fn describe(errors: &ValidationErrors) -> String {
+    field_errors(errors)
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors
+                .into_iter()
+                .map(move |error| format!("{field}: {}", error.message))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl From<DieselError> for AppError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => AppError::NotFound,
+            // The constraint name would reveal the schema; it is only logged.
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                tracing::debug!(
+                    constraint = info.constraint_name(),
+                    "unique violation: {}",
+                    info.message()
+                );
+                AppError::Conflict("a record with the same unique values already exists".into())
+            }
+            other => AppError::internal(other),
+        }
+    }
+}
+
+/// No connection could be had within the connection timeout, either because
+/// all of them stayed busy or because the database cannot be reached.
+impl From<diesel::r2d2::PoolError> for AppError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        tracing::warn!("no database connection available: {err}");
+        AppError::Unavailable("the service is busy, please try again".into())
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        AppError::internal(err)
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(err: ValidationErrors) -> Self {
+        AppError::Validation(err)
+    }
+}
+
+/// Maps the rejection of an axum extractor by its status, keeping axum's
+/// explanation as the detail.
+fn rejected(status: StatusCode, message: String) -> AppError {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(message),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => AppError::UnsupportedMediaType(message),
+        status if status.is_server_error() => AppError::Internal(message),
+        _ => AppError::BadRequest(message),
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
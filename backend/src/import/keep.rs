@@ -0,0 +1,396 @@
+//! Importer for Google Keep notes exported through Google Takeout.
+//!
+//! Takeout writes one JSON document per note into `Takeout/Keep/`, with the
+//! note's attachments stored as plain files next to it.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::attachment::NewAttachment;
+use crate::models::note::{NewNote, NoteColor};
+use crate::models::tag::{self, NoteTag};
+use crate::schema::{attachments, note_tags, notes};
+use crate::storage::BlobStore;
+
+const MAX_TITLE_CHARS: usize = 255;
+const MAX_TAG_CHARS: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("failed to read {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{} is not a valid Keep note: {source}", path.display())]
+    InvalidNote {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("failed to store attachment {}: {source}", path.display())]
+    Storage { path: PathBuf, source: io::Error },
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeepNote {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub text_content: String,
+    #[serde(default)]
+    pub list_content: Vec<KeepListItem>,
+    #[serde(default)]
+    pub labels: Vec<KeepLabel>,
+    #[serde(default)]
+    pub attachments: Vec<KeepAttachment>,
+    #[serde(default)]
+    pub annotations: Vec<KeepAnnotation>,
+    #[serde(default)]
+    pub color: String,
+    #[serde(default)]
+    pub is_pinned: bool,
+    #[serde(default)]
+    pub is_archived: bool,
+    #[serde(default)]
+    pub is_trashed: bool,
+    pub created_timestamp_usec: Option<i64>,
+    pub user_edited_timestamp_usec: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeepListItem {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub is_checked: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeepLabel {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeepAttachment {
+    pub file_path: String,
+    pub mimetype: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeepAnnotation {
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub url: String,
+}
+
+impl KeepNote {
+    pub fn color(&self) -> NoteColor {
+        match self.color.as_str() {
+            "RED" => NoteColor::Red,
+            "ORANGE" => NoteColor::Orange,
+            "YELLOW" => NoteColor::Yellow,
+            "GREEN" => NoteColor::Green,
+            "TEAL" => NoteColor::Teal,
+            "BLUE" => NoteColor::Blue,
+            "CERULEAN" => NoteColor::Cerulean,
+            "PURPLE" => NoteColor::Purple,
+            "PINK" => NoteColor::Pink,
+            "BROWN" => NoteColor::Brown,
+            "GRAY" => NoteColor::Gray,
+            _ => NoteColor::Default,
+        }
+    }
+
+    /// Renders the note body as Markdown, turning checklists into GFM task lists.
+    pub fn content(&self) -> String {
+        let mut blocks = Vec::new();
+
+        let text = self.text_content.trim_end();
+        if !text.is_empty() {
+            blocks.push(text.to_string());
+        }
+
+        if !self.list_content.is_empty() {
+            let items = self
+                .list_content
+                .iter()
+                .map(|item| {
+                    let mark = if item.is_checked { 'x' } else { ' ' };
+                    let text = item.text.lines().collect::<Vec<_>>().join(" ");
+                    format!("- [{mark}] {}", text.trim())
+                })
+                .collect::<Vec<_>>();
+            blocks.push(items.join("\n"));
+        }
+
+        let links = self
+            .annotations
+            .iter()
+            .filter(|annotation| annotation.source == "WEBLINK" && !annotation.url.is_empty())
+            .map(|annotation| {
+                let title = annotation.title.trim();
+                let title = if title.is_empty() {
+                    &annotation.url
+                } else {
+                    title
+                };
+                format!("- [{title}]({})", annotation.url)
+            })
+            .collect::<Vec<_>>();
+        if !links.is_empty() {
+            blocks.push(links.join("\n"));
+        }
+
+        blocks.join("\n\n")
+    }
+
+    /// Label names, trimmed and deduplicated.
+    pub fn tags(&self) -> Vec<String> {
+        self.labels
+            .iter()
+            .map(|label| truncate(label.name.trim(), MAX_TAG_CHARS))
+            .filter(|name| !name.is_empty())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros(self.user_edited_timestamp_usec).unwrap_or_else(Utc::now)
+    }
+
+    /// Older exports lack a creation timestamp, so the last edit is used instead.
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_timestamp_usec
+            .and_then(DateTime::from_timestamp_micros)
+            .unwrap_or_else(|| self.updated_at())
+    }
+
+    pub fn to_new_note(&self, user_id: Uuid) -> NewNote {
+        NewNote {
+            user_id,
+            title: truncate(self.title.trim(), MAX_TITLE_CHARS),
+            content: self.content(),
+            color: self.color(),
+            pinned: self.is_pinned,
+            archived: self.is_archived,
+            created_at: self.created_at(),
+            updated_at: self.updated_at(),
+        }
+    }
+}
+
+/// A parsed note together with the file it was read from.
+#[derive(Debug, Clone)]
+pub struct KeepEntry {
+    pub path: PathBuf,
+    pub note: KeepNote,
+}
+
+impl KeepEntry {
+    /// Locates an attachment on disk. Takeout sometimes records a different
+    /// extension than the one it writes (`.jpeg` vs `.jpg`), so fall back to
+    /// any file sharing the same stem. Only plain file names are looked up,
+    /// so a crafted export cannot reach files outside its directory.
+    pub fn resolve_attachment(&self, attachment: &KeepAttachment) -> Option<PathBuf> {
+        let dir = self.path.parent()?;
+        let name = Path::new(&attachment.file_path);
+        let mut components = name.components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return None;
+        }
+        let exact = dir.join(name);
+        if exact.is_file() {
+            return Some(exact);
+        }
+
+        let stem = name.file_stem()?;
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| path.is_file() && path.file_stem() == Some(stem))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped_trashed: usize,
+    pub attachments: usize,
+    pub missing_attachments: Vec<String>,
+}
+
+/// Reads every note in a Takeout export. `dir` may point at the extracted
+/// archive root or directly at its `Keep` folder.
+pub fn read_takeout(dir: &Path) -> Result<Vec<KeepEntry>, ImportError> {
+    let dir = keep_dir(dir);
+    let io_err = |source| ImportError::Io {
+        path: dir.clone(),
+        source,
+    };
+
+    let mut paths = fs::read_dir(&dir)
+        .map_err(io_err)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_err)?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let json = fs::read_to_string(&path).map_err(|source| ImportError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            match serde_json::from_str(&json) {
+                Ok(note) => Ok(KeepEntry { path, note }),
+                Err(source) => Err(ImportError::InvalidNote { path, source }),
+            }
+        })
+        .collect()
+}
+
+/// Imports a Takeout export for `user_id`. Blobs are written before the
+/// database transaction and removed again if it fails.
+pub fn import_takeout(
+    conn: &mut PgConnection,
+    store: &dyn BlobStore,
+    user_id: Uuid,
+    dir: &Path,
+) -> Result<ImportSummary, ImportError> {
+    let mut summary = ImportSummary::default();
+    let mut staged = Vec::new();
+    let mut written_keys = Vec::new();
+
+    let result = (|| {
+        for entry in read_takeout(dir)? {
+            if entry.note.is_trashed {
+                summary.skipped_trashed += 1;
+                continue;
+            }
+
+            let mut files = Vec::new();
+            for attachment in &entry.note.attachments {
+                let Some(path) = entry.resolve_attachment(attachment) else {
+                    summary
+                        .missing_attachments
+                        .push(attachment.file_path.clone());
+                    continue;
+                };
+                let data = fs::read(&path).map_err(|source| ImportError::Io {
+                    path: path.clone(),
+                    source,
+                })?;
+                let storage_key = format!("attachments/{}", Uuid::new_v4());
+                store
+                    .put(&storage_key, &data)
+                    .map_err(|source| ImportError::Storage {
+                        path: path.clone(),
+                        source,
+                    })?;
+                written_keys.push(storage_key.clone());
+
+                files.push(StagedAttachment {
+                    file_name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| attachment.file_path.clone()),
+                    mime_type: attachment.mimetype.clone(),
+                    size_bytes: data.len() as i64,
+                    storage_key,
+                });
+            }
+
+            staged.push((entry.note, files));
+        }
+
+        conn.transaction(|conn| {
+            for (note, files) in &staged {
+                let note_id: Uuid = diesel::insert_into(notes::table)
+                    .values(note.to_new_note(user_id))
+                    .returning(notes::id)
+                    .get_result(conn)?;
+
+                for name in note.tags() {
+                    let tag_id = tag::find_or_create(conn, user_id, &name)?;
+                    diesel::insert_into(note_tags::table)
+                        .values(NoteTag { note_id, tag_id })
+                        .execute(conn)?;
+                }
+
+                if !files.is_empty() {
+                    let rows = files
+                        .iter()
+                        .map(|file| file.clone().into_new(note_id))
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(attachments::table)
+                        .values(&rows)
+                        .execute(conn)?;
+                }
+
+                summary.imported += 1;
+                summary.attachments += files.len();
+            }
+            Ok::<_, ImportError>(())
+        })
+    })();
+
+    if let Err(err) = result {
+        for key in &written_keys {
+            let _ = store.delete(key);
+        }
+        return Err(err);
+    }
+
+    Ok(summary)
+}
+
+#[derive(Debug, Clone)]
+struct StagedAttachment {
+    file_name: String,
+    mime_type: String,
+    size_bytes: i64,
+    storage_key: String,
+}
+
+impl StagedAttachment {
+    fn into_new(self, note_id: Uuid) -> NewAttachment {
+        NewAttachment {
+            note_id,
+            file_name: self.file_name,
+            mime_type: self.mime_type,
+            size_bytes: self.size_bytes,
+            storage_key: self.storage_key,
+        }
+    }
+}
+
+fn keep_dir(dir: &Path) -> PathBuf {
+    [dir.join("Takeout").join("Keep"), dir.join("Keep")]
+        .into_iter()
+        .find(|candidate| candidate.is_dir())
+        .unwrap_or_else(|| dir.to_path_buf())
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    value.chars().take(max_chars).collect()
+}
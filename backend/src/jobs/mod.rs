@@ -0,0 +1,283 @@
+//! Durable background jobs, queued in Postgres and run by a pool of workers
+//! inside the backend; see [`worker`].
+//!
+//! A job is a serializable value implementing [`BackgroundJob`]. Queueing it
+//! takes a connection, so it can happen in the same transaction as the
+//! change that calls for it. Failed jobs are retried with exponential
+//! backoff; after their last attempt they are kept as dead until an admin
+//! retries or deletes them.
+
+pub mod worker;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::models::job::{Job, JobStatus, NewJob};
+use crate::schema::jobs;
+use crate::state::AppState;
+
+#[async_trait]
+pub trait BackgroundJob: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Stored with every job to find its handler, so it must not change once
+    /// jobs of this kind have been queued.
+    const KIND: &'static str;
+    const MAX_ATTEMPTS: i32 = 5;
+    /// An attempt that takes longer fails.
+    const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+    /// Whether completed jobs are kept for the retention period. Jobs whose
+    /// payload should not outlive them are deleted right away instead.
+    const KEEP_COMPLETED: bool = true;
+
+    async fn run(&self, state: &AppState) -> AppResult<()>;
+
+    /// Called once the last attempt has failed.
+    async fn dead(&self, _state: &AppState) {}
+}
+
+/// Queues `job` to run as soon as a worker is free.
+pub fn enqueue<J: BackgroundJob>(conn: &mut PgConnection, job: &J) -> QueryResult<Uuid> {
+    schedule(conn, job, Utc::now())
+}
+
+/// Queues `job` to run at `run_at`.
+pub fn schedule<J: BackgroundJob>(
+    conn: &mut PgConnection,
+    job: &J,
+    run_at: DateTime<Utc>,
+) -> QueryResult<Uuid> {
+    let payload =
+        serde_json::to_value(job).map_err(|err| DieselError::SerializationError(err.into()))?;
+    let id = diesel::insert_into(jobs::table)
+        .values(NewJob {
+            kind: J::KIND,
+            payload,
+            max_attempts: J::MAX_ATTEMPTS,
+            run_at,
+        })
+        .returning(jobs::id)
+        .get_result(conn)?;
+    tracing::debug!(job_id = %id, kind = J::KIND, "job queued");
+    Ok(id)
+}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = AppResult<()>> + Send>>;
+type DeadFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Clone)]
+struct Handler {
+    run: Arc<dyn Fn(AppState, Value) -> HandlerFuture + Send + Sync>,
+    dead: Arc<dyn Fn(AppState, Value) -> DeadFuture + Send + Sync>,
+    timeout: Duration,
+    keep_completed: bool,
+}
+
+/// The job kinds this process knows how to run. Workers only pick up jobs
+/// of registered kinds.
+#[derive(Clone, Default)]
+pub struct Registry {
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl Registry {
+    pub fn register<J: BackgroundJob>(mut self) -> Self {
+        let handler = Handler {
+            run: Arc::new(|state, payload| {
+                Box::pin(async move {
+                    let job: J = serde_json::from_value(payload).map_err(|err| {
+                        AppError::Internal(format!("invalid {} payload: {err}", J::KIND))
+                    })?;
+                    job.run(&state).await
+                })
+            }),
+            dead: Arc::new(|state, payload| {
+                Box::pin(async move {
+                    if let Ok(job) = serde_json::from_value::<J>(payload) {
+                        job.dead(&state).await;
+                    }
+                })
+            }),
+            timeout: J::TIMEOUT,
+            keep_completed: J::KEEP_COMPLETED,
+        };
+        self.handlers.insert(J::KIND, handler);
+        self
+    }
+
+    fn kinds(&self) -> Vec<String> {
+        self.handlers.keys().map(|kind| kind.to_string()).collect()
+    }
+
+    fn get(&self, kind: &str) -> Option<&Handler> {
+        self.handlers.get(kind)
+    }
+
+    /// How long a worker may hold a job before it is handed to another one:
+    /// the longest timeout, plus time to record the outcome.
+    fn lease(&self) -> Duration {
+        self.handlers
+            .values()
+            .map(|handler| handler.timeout)
+            .max()
+            .unwrap_or_default()
+            + Duration::from_secs(60)
+    }
+}
+
+/// Pending jobs, due or scheduled, by kind.
+pub async fn pending_counts(state: &AppState) -> AppResult<Vec<(String, i64)>> {
+    db::run(&state.pool, |conn| {
+        jobs::table
+            .filter(jobs::status.eq(JobStatus::Pending))
+            .group_by(jobs::kind)
+            .select((jobs::kind, count_star()))
+            .order(jobs::kind.asc())
+            .load(conn)
+    })
+    .await
+}
+
+/// Number of jobs by kind and status.
+pub async fn counts(state: &AppState) -> AppResult<Vec<(String, JobStatus, i64)>> {
+    db::run(&state.pool, |conn| {
+        jobs::table
+            .group_by((jobs::kind, jobs::status))
+            .select((jobs::kind, jobs::status, count_star()))
+            .order((jobs::kind.asc(), jobs::status.asc()))
+            .load(conn)
+    })
+    .await
+}
+
+#[derive(Debug, Default)]
+pub struct JobFilter {
+    pub status: Option<JobStatus>,
+    pub kind: Option<String>,
+    /// Only jobs created before this one, for paging.
+    pub before: Option<Uuid>,
+    pub limit: i64,
+}
+
+/// Jobs matching `filter`, newest first.
+pub async fn list(state: &AppState, filter: JobFilter) -> AppResult<Vec<Job>> {
+    db::run(&state.pool, move |conn| {
+        let mut query = jobs::table
+            .order((jobs::created_at.desc(), jobs::id.desc()))
+            .limit(filter.limit)
+            .select(Job::as_select())
+            .into_boxed();
+        if let Some(status) = filter.status {
+            query = query.filter(jobs::status.eq(status));
+        }
+        if let Some(kind) = filter.kind {
+            query = query.filter(jobs::kind.eq(kind));
+        }
+        if let Some(before) = filter.before {
+            let (created_at, id) = jobs::table
+                .find(before)
+                .select((jobs::created_at, jobs::id))
+                .first::<(DateTime<Utc>, Uuid)>(conn)?;
+            query = query.filter(
+                jobs::created_at
+                    .lt(created_at)
+                    .or(jobs::created_at.eq(created_at).and(jobs::id.lt(id))),
+            );
+        }
+        query.load(conn)
+    })
+    .await
+}
+
+pub async fn find(state: &AppState, id: Uuid) -> AppResult<Job> {
+    db::run(&state.pool, move |conn| {
+        jobs::table.find(id).select(Job::as_select()).first(conn)
+    })
+    .await
+}
+
+/// Runs a dead job again with a fresh set of attempts, or a pending one
+/// right away.
+pub async fn retry(state: &AppState, id: Uuid) -> AppResult<Job> {
+    db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let job: Job = jobs::table
+                .find(id)
+                .select(Job::as_select())
+                .for_update()
+                .first(conn)?;
+            let attempts = match job.status {
+                JobStatus::Dead => 0,
+                JobStatus::Pending => job.attempts,
+                JobStatus::Running | JobStatus::Completed => {
+                    return Err(AppError::Conflict(format!(
+                        "the job is {} and cannot be retried",
+                        job.status
+                    )));
+                }
+            };
+            let job = diesel::update(jobs::table.find(id))
+                .set((
+                    jobs::status.eq(JobStatus::Pending),
+                    jobs::attempts.eq(attempts),
+                    jobs::run_at.eq(Utc::now()),
+                    jobs::finished_at.eq(None::<DateTime<Utc>>),
+                ))
+                .returning(Job::as_returning())
+                .get_result(conn)?;
+            Ok(job)
+        })
+    })
+    .await
+}
+
+/// Deletes a job that is not running.
+pub async fn delete(state: &AppState, id: Uuid) -> AppResult<()> {
+    db::run(&state.pool, move |conn| {
+        let deleted = diesel::delete(
+            jobs::table
+                .find(id)
+                .filter(jobs::status.ne(JobStatus::Running)),
+        )
+        .execute(conn)?;
+        if deleted == 0 {
+            let exists =
+                diesel::select(diesel::dsl::exists(jobs::table.find(id))).get_result(conn)?;
+            return Err(if exists {
+                AppError::Conflict("the job is running and cannot be deleted".into())
+            } else {
+                AppError::NotFound
+            });
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Deletes completed jobs older than the retention period. Dead jobs are
+/// kept until an admin deals with them.
+pub async fn purge_completed(state: AppState) -> AppResult<usize> {
+    let cutoff = Utc::now() - state.job_retention;
+    db::run(&state.pool, move |conn| {
+        diesel::delete(
+            jobs::table
+                .filter(jobs::status.eq(JobStatus::Completed))
+                .filter(jobs::finished_at.le(cutoff)),
+        )
+        .execute(conn)
+    })
+    .await
+}
@@ -0,0 +1,307 @@
+//! Workers that claim due jobs with `FOR UPDATE SKIP LOCKED`, so any number
+//! of them, in any number of processes, can share the queue without
+//! running a job twice.
+//!
+//! A claimed job is leased to its worker. If the worker dies, the job is
+//! picked up again once the lease runs out, which counts as an attempt; if
+//! it was the last attempt, the job is dead instead of run again. The dead
+//! handler runs before a job is marked as dead, so a dead job has always
+//! been handled. On shutdown, workers stop claiming jobs; a job still
+//! running at the deadline is put back without counting the attempt.
+
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use rand::Rng;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use super::Registry;
+use crate::config::JobsConfig;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::models::job::{Job, JobStatus};
+use crate::schema::jobs;
+use crate::state::AppState;
+
+/// Delay before the second attempt; it doubles with every further one.
+const BACKOFF_BASE: Duration = Duration::from_secs(10);
+const BACKOFF_MAX: Duration = Duration::from_secs(60 * 60);
+
+/// Starts the configured number of workers.
+pub fn spawn(state: &AppState, registry: Registry, config: &JobsConfig) {
+    if config.workers == 0 {
+        tracing::info!("no job workers configured; jobs are left to other instances");
+        return;
+    }
+    for _ in 0..config.workers {
+        state
+            .shutdown
+            .spawn(work(state.clone(), registry.clone(), config.poll_interval));
+    }
+}
+
+async fn work(state: AppState, registry: Registry, poll_interval: Duration) {
+    let kinds = registry.kinds();
+    let lease = chrono::Duration::seconds(registry.lease().as_secs() as i64);
+    while !state.shutdown.is_triggered() {
+        let kinds = kinds.clone();
+        match db::run(&state.pool, move |conn| claim(conn, &kinds, lease)).await {
+            Ok(Some(Claimed::Run(job))) => process(&state, &registry, job).await,
+            Ok(Some(Claimed::Expired(job))) => {
+                tracing::error!(
+                    job_id = %job.id,
+                    kind = %job.kind,
+                    "job lease ran out on the last attempt"
+                );
+                if let Some(handler) = registry.get(&job.kind) {
+                    (handler.dead)(state.clone(), job.payload.clone()).await;
+                }
+                let error = "lease ran out before the last attempt finished".to_string();
+                if let Err(err) = finish(&state, &job, JobStatus::Dead, Some(error), None).await {
+                    tracing::error!("cannot record the outcome of the job: {err}");
+                }
+            }
+            Ok(None) => idle(&state, poll_interval).await,
+            Err(err) => {
+                tracing::error!("cannot fetch jobs: {err}");
+                idle(&state, poll_interval).await;
+            }
+        }
+    }
+}
+
+async fn idle(state: &AppState, poll_interval: Duration) {
+    tokio::select! {
+        () = tokio::time::sleep(poll_interval) => {}
+        () = state.shutdown.triggered() => {}
+    }
+}
+
+#[derive(Debug)]
+enum Claimed {
+    /// Leased to the caller, who has to run it.
+    Run(Job),
+    /// Its lease ran out on the last attempt. It is leased to the caller
+    /// again, who has to run the dead handler and mark it as dead.
+    Expired(Job),
+}
+
+/// Takes the job that has been due the longest, if any.
+fn claim(
+    conn: &mut PgConnection,
+    kinds: &[String],
+    lease: chrono::Duration,
+) -> QueryResult<Option<Claimed>> {
+    conn.transaction(|conn| {
+        let now = Utc::now();
+        let due = jobs::table
+            .filter(jobs::kind.eq_any(kinds))
+            .filter(
+                jobs::status
+                    .eq(JobStatus::Pending)
+                    .and(jobs::run_at.le(now))
+                    .or(jobs::status
+                        .eq(JobStatus::Running)
+                        .and(jobs::locked_until.lt(now).assume_not_null())),
+            )
+            .order(jobs::run_at.asc())
+            .select((jobs::id, jobs::status, jobs::attempts, jobs::max_attempts))
+            .for_update()
+            .skip_locked()
+            .first::<(Uuid, JobStatus, i32, i32)>(conn)
+            .optional()?;
+        let Some((id, status, attempts, max_attempts)) = due else {
+            return Ok(None);
+        };
+
+        // The worker running the last attempt died or hung.
+        if status == JobStatus::Running && attempts >= max_attempts {
+            return diesel::update(jobs::table.find(id))
+                .set(jobs::locked_until.eq(now + lease))
+                .returning(Job::as_returning())
+                .get_result(conn)
+                .map(|job| Some(Claimed::Expired(job)));
+        }
+
+        diesel::update(jobs::table.find(id))
+            .set((
+                jobs::status.eq(JobStatus::Running),
+                jobs::attempts.eq(jobs::attempts + 1),
+                jobs::locked_until.eq(now + lease),
+            ))
+            .returning(Job::as_returning())
+            .get_result(conn)
+            .map(|job| Some(Claimed::Run(job)))
+    })
+}
+
+async fn process(state: &AppState, registry: &Registry, job: Job) {
+    let Some(handler) = registry.get(&job.kind).cloned() else {
+        return;
+    };
+    let span = tracing::info_span!(
+        "job",
+        job_id = %job.id,
+        kind = %job.kind,
+        attempt = job.attempts,
+    );
+    async move {
+        let start = Instant::now();
+        let outcome = tokio::select! {
+            result = tokio::time::timeout(
+                handler.timeout,
+                (handler.run)(state.clone(), job.payload.clone()),
+            ) => Some(result.unwrap_or_else(|_| Err(AppError::Internal(format!(
+                "timed out after {} seconds",
+                handler.timeout.as_secs()
+            ))))),
+            () = state.shutdown.stopping() => None,
+        };
+
+        let recorded = match outcome {
+            Some(Ok(())) => {
+                tracing::info!(
+                    elapsed_ms = start.elapsed().as_millis() as u64,
+                    "job completed"
+                );
+                if handler.keep_completed {
+                    finish(state, &job, JobStatus::Completed, None, None).await
+                } else {
+                    remove(state, &job).await
+                }
+            }
+            Some(Err(err)) if job.attempts >= job.max_attempts => {
+                tracing::error!("job failed for the last time: {err}");
+                (handler.dead)(state.clone(), job.payload.clone()).await;
+                finish(state, &job, JobStatus::Dead, Some(err.to_string()), None).await
+            }
+            Some(Err(err)) => {
+                let delay = backoff(job.attempts);
+                tracing::warn!("job failed, retrying in {} seconds: {err}", delay.as_secs());
+                let run_at = Utc::now() + chrono::Duration::seconds(delay.as_secs() as i64);
+                finish(
+                    state,
+                    &job,
+                    JobStatus::Pending,
+                    Some(err.to_string()),
+                    Some(run_at),
+                )
+                .await
+            }
+            None => {
+                tracing::warn!("job interrupted by shutdown; it will run again");
+                release(state, &job).await
+            }
+        };
+        if let Err(err) = recorded {
+            tracing::error!("cannot record the outcome of the job: {err}");
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Records the outcome of an attempt. The attempt number guards against
+/// overwriting the outcome of another worker that claimed the job after the
+/// lease ran out.
+async fn finish(
+    state: &AppState,
+    job: &Job,
+    status: JobStatus,
+    error: Option<String>,
+    retry_at: Option<DateTime<Utc>>,
+) -> AppResult<()> {
+    let (id, attempts) = (job.id, job.attempts);
+    db::run(&state.pool, move |conn| {
+        let now = Utc::now();
+        let finished_at = (status != JobStatus::Pending).then_some(now);
+        diesel::update(jobs::table.find(id))
+            .filter(jobs::status.eq(JobStatus::Running))
+            .filter(jobs::attempts.eq(attempts))
+            .set((
+                jobs::status.eq(status),
+                jobs::locked_until.eq(None::<DateTime<Utc>>),
+                jobs::run_at.eq(retry_at.unwrap_or(now)),
+                jobs::finished_at.eq(finished_at),
+                error.map(|error| jobs::last_error.eq(error)),
+            ))
+            .execute(conn)
+    })
+    .await?;
+    Ok(())
+}
+
+/// Puts a job back without counting the attempt, guarded like [`finish`].
+async fn release(state: &AppState, job: &Job) -> AppResult<()> {
+    let (id, attempts) = (job.id, job.attempts);
+    db::run(&state.pool, move |conn| {
+        diesel::update(jobs::table.find(id))
+            .filter(jobs::status.eq(JobStatus::Running))
+            .filter(jobs::attempts.eq(attempts))
+            .set((
+                jobs::status.eq(JobStatus::Pending),
+                jobs::attempts.eq(attempts - 1),
+                jobs::locked_until.eq(None::<DateTime<Utc>>),
+                jobs::run_at.eq(Utc::now()),
+            ))
+            .execute(conn)
+    })
+    .await?;
+    Ok(())
+}
+
+/// Deletes a completed job, guarded like [`finish`].
+async fn remove(state: &AppState, job: &Job) -> AppResult<()> {
+    let (id, attempts) = (job.id, job.attempts);
+    db::run(&state.pool, move |conn| {
+        diesel::delete(jobs::table.find(id))
+            .filter(jobs::status.eq(JobStatus::Running))
+            .filter(jobs::attempts.eq(attempts))
+            .execute(conn)
+    })
+    .await?;
+    Ok(())
+}
+
+/// Exponential, with up to a fifth added at random so jobs that failed
+/// together do not all come back at once.
+fn backoff(attempt: i32) -> Duration {
+    let exponent = attempt.saturating_sub(1).clamp(0, 16) as u32;
+    let delay = BACKOFF_BASE
+        .saturating_mul(2u32.pow(exponent))
+        .min(BACKOFF_MAX);
+    let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 5);
+    delay + Duration::from_millis(jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_between(delay: Duration, secs: u64) {
+        let base = Duration::from_secs(secs);
+        assert!(
+            delay >= base && delay <= base + base / 5,
+            "{delay:?} not within a fifth above {base:?}"
+        );
+    }
+
+    #[test]
+    fn doubles_the_delay_with_every_attempt() {
+        assert_between(backoff(1), 10);
+        assert_between(backoff(2), 20);
+        assert_between(backoff(3), 40);
+        assert_between(backoff(6), 320);
+    }
+
+    #[test]
+    fn caps_the_delay() {
+        assert_between(backoff(10), 60 * 60);
+        assert_between(backoff(i32::MAX), 60 * 60);
+        // Never faster than the first retry.
+        assert_between(backoff(0), 10);
+        assert_between(backoff(-3), 10);
+    }
+}
@@ -0,0 +1,25 @@
+pub mod account;
+pub mod auth;
+pub mod calendar;
+pub mod comments;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod extract;
+pub mod import;
+pub mod jobs;
+pub mod mail;
+pub mod metrics;
+pub mod migrations;
+pub mod models;
+pub mod notifications;
+pub mod rate_limit;
+pub mod reminders;
+pub mod routes;
+pub mod schema;
+pub mod shares;
+pub mod shutdown;
+pub mod state;
+pub mod storage;
+pub mod tasks;
+pub mod telemetry;
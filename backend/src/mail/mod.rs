@@ -0,0 +1,188 @@
+//! Outgoing email. Emails are queued as [`SendEmail`] jobs, usually in the
+//! transaction of the change they are about, and rendered in the recipient's
+//! language when the job runs.
+
+mod templates;
+
+pub use templates::{render, Email, Rendered};
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::file::Error as FileError;
+use lettre::transport::smtp::Error as SmtpError;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::{MailConfig, MailTransport};
+use crate::error::{AppError, AppResult};
+use crate::jobs::{self, BackgroundJob};
+use crate::metrics::Metrics;
+use crate::models::user::Locale;
+use crate::state::AppState;
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("invalid email address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("failed to render email: {0:#}")]
+    Template(#[from] minijinja::Error),
+    #[error("failed to build email: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("failed to send email: {0}")]
+    Transport(#[from] SmtpError),
+    #[error("failed to write email: {0}")]
+    File(#[from] FileError),
+    #[error("mail directory {0:?} is not writable")]
+    Dir(PathBuf),
+}
+
+#[derive(Clone)]
+enum Transport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File {
+        transport: AsyncFileTransport<Tokio1Executor>,
+        dir: PathBuf,
+    },
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Transport,
+    from: Mailbox,
+    metrics: Arc<Metrics>,
+}
+
+impl Mailer {
+    pub fn new(config: &MailConfig, metrics: Arc<Metrics>) -> Result<Self, MailError> {
+        let transport = match &config.transport {
+            MailTransport::Smtp { url } => {
+                Transport::Smtp(AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build())
+            }
+            MailTransport::File { dir } => Transport::File {
+                transport: AsyncFileTransport::new(dir),
+                dir: dir.clone(),
+            },
+        };
+        Ok(Self {
+            transport,
+            from: config.from.parse()?,
+            metrics,
+        })
+    }
+
+    /// Renders `email` in the language of `locale`, with times in
+    /// `time_zone`, and sends it to `to`.
+    pub async fn send(
+        &self,
+        to: &str,
+        locale: Locale,
+        time_zone: Tz,
+        email: &Email,
+    ) -> Result<(), MailError> {
+        let result = self.deliver(to, locale, time_zone, email).await;
+        self.metrics.record_email(result.is_ok());
+        result
+    }
+
+    /// Checks that the mail server accepts connections, or that the mail
+    /// directory exists.
+    pub async fn check(&self) -> Result<(), MailError> {
+        match &self.transport {
+            Transport::Smtp(transport) => {
+                transport.test_connection().await?;
+            }
+            Transport::File { dir, .. } => {
+                let writable = tokio::fs::metadata(dir)
+                    .await
+                    .is_ok_and(|meta| meta.is_dir() && !meta.permissions().readonly());
+                if !writable {
+                    return Err(MailError::Dir(dir.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn deliver(
+        &self,
+        to: &str,
+        locale: Locale,
+        time_zone: Tz,
+        email: &Email,
+    ) -> Result<(), MailError> {
+        let rendered = render(locale, time_zone, email)?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(rendered.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                rendered.text,
+                rendered.html,
+            ))?;
+        match &self.transport {
+            Transport::Smtp(transport) => {
+                transport.send(message).await?;
+            }
+            Transport::File { transport, .. } => {
+                transport.send(message).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Queues `email` to be sent to `to`.
+pub fn enqueue(
+    conn: &mut PgConnection,
+    to: &str,
+    locale: Locale,
+    time_zone: Tz,
+    email: Email,
+) -> QueryResult<Uuid> {
+    jobs::enqueue(
+        conn,
+        &SendEmail {
+            to: to.to_string(),
+            locale,
+            time_zone,
+            email,
+        },
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendEmail {
+    pub to: String,
+    pub locale: Locale,
+    /// Missing from emails queued before time zones were known.
+    #[serde(default = "utc")]
+    pub time_zone: Tz,
+    pub email: Email,
+}
+
+fn utc() -> Tz {
+    Tz::UTC
+}
+
+#[async_trait]
+impl BackgroundJob for SendEmail {
+    const KIND: &'static str = "email";
+    /// The payload may contain links that sign their reader in or change
+    /// the account.
+    const KEEP_COMPLETED: bool = false;
+
+    async fn run(&self, state: &AppState) -> AppResult<()> {
+        state
+            .mailer
+            .send(&self.to, self.locale, self.time_zone, &self.email)
+            .await
+            .map_err(AppError::internal)
+    }
+}
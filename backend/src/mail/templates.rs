@@ -0,0 +1,228 @@
+//! The emails the backend sends, rendered from the templates in
+//! `backend/templates/email`, one directory per locale.
+//!
+//! Every email has a `{name}.txt` template with a `subject` and a `body`
+//! block, and a `{name}.html` template. Both extend the layout of their
+//! locale. Dates and times go through the `datetime` filter, which formats
+//! them the way the locale expects, in the recipient's time zone.
+
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use minijinja::{context, Environment, Error, ErrorKind, State, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::models::user::Locale;
+
+/// One email with what it says. Queued as part of a job, so changes have to
+/// stay readable from payloads already in the queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "template", rename_all = "snake_case")]
+pub enum Email {
+    Verification {
+        name: String,
+        link: String,
+        valid_hours: i64,
+    },
+    PasswordReset {
+        name: String,
+        link: String,
+        valid_minutes: i64,
+    },
+    /// Sent to the old address when someone asks to change it.
+    EmailChangeNotice {
+        name: String,
+        new_email: String,
+        cancel_link: String,
+    },
+    EmailChangeConfirmation {
+        name: String,
+        link: String,
+        valid_hours: i64,
+    },
+    /// A successful login from a device not seen before.
+    LoginAlert {
+        name: String,
+        time: DateTime<Utc>,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    },
+    AccountLocked {
+        name: String,
+        until: DateTime<Utc>,
+    },
+    DeletionScheduled {
+        name: String,
+        deletion_at: DateTime<Utc>,
+    },
+    ExportReady {
+        name: String,
+        link: String,
+        valid_hours: i64,
+    },
+    /// Sent to someone a note has been shared with.
+    ShareInvitation {
+        inviter: String,
+        note_title: String,
+        link: String,
+    },
+    Reminder {
+        name: String,
+        note_title: String,
+        link: String,
+        remind_at: DateTime<Utc>,
+    },
+    /// Sent to someone mentioned in a comment on a note.
+    Mention {
+        name: String,
+        author: String,
+        note_title: String,
+        comment: String,
+        link: String,
+    },
+}
+
+impl Email {
+    /// Name of the templates, without locale and extension.
+    pub fn template(&self) -> &'static str {
+        match self {
+            Email::Verification { .. } => "verification",
+            Email::PasswordReset { .. } => "password_reset",
+            Email::EmailChangeNotice { .. } => "email_change_notice",
+            Email::EmailChangeConfirmation { .. } => "email_change_confirmation",
+            Email::LoginAlert { .. } => "login_alert",
+            Email::AccountLocked { .. } => "account_locked",
+            Email::DeletionScheduled { .. } => "deletion_scheduled",
+            Email::ExportReady { .. } => "export_ready",
+            Email::ShareInvitation { .. } => "share_invitation",
+            Email::Reminder { .. } => "reminder",
+            Email::Mention { .. } => "mention",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+macro_rules! templates {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_str!(concat!("../../templates/email/", $name)))),*]
+    };
+}
+
+const TEMPLATES: &[(&str, &str)] = templates![
+    "macros.html",
+    "de/layout.html",
+    "de/layout.txt",
+    "de/account_locked.html",
+    "de/account_locked.txt",
+    "de/deletion_scheduled.html",
+    "de/deletion_scheduled.txt",
+    "de/email_change_confirmation.html",
+    "de/email_change_confirmation.txt",
+    "de/email_change_notice.html",
+    "de/email_change_notice.txt",
+    "de/export_ready.html",
+    "de/export_ready.txt",
+    "de/login_alert.html",
+    "de/login_alert.txt",
+    "de/mention.html",
+    "de/mention.txt",
+    "de/password_reset.html",
+    "de/password_reset.txt",
+    "de/reminder.html",
+    "de/reminder.txt",
+    "de/share_invitation.html",
+    "de/share_invitation.txt",
+    "de/verification.html",
+    "de/verification.txt",
+    "en/layout.html",
+    "en/layout.txt",
+    "en/account_locked.html",
+    "en/account_locked.txt",
+    "en/deletion_scheduled.html",
+    "en/deletion_scheduled.txt",
+    "en/email_change_confirmation.html",
+    "en/email_change_confirmation.txt",
+    "en/email_change_notice.html",
+    "en/email_change_notice.txt",
+    "en/export_ready.html",
+    "en/export_ready.txt",
+    "en/login_alert.html",
+    "en/login_alert.txt",
+    "en/mention.html",
+    "en/mention.txt",
+    "en/password_reset.html",
+    "en/password_reset.txt",
+    "en/reminder.html",
+    "en/reminder.txt",
+    "en/share_invitation.html",
+    "en/share_invitation.txt",
+    "en/verification.html",
+    "en/verification.txt",
+];
+
+/// HTML templates are escaped by their extension.
+static TEMPLATE_ENV: LazyLock<Environment<'static>> = LazyLock::new(|| {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_keep_trailing_newline(true);
+    for (name, source) in TEMPLATES {
+        env.add_template(name, source)
+            .unwrap_or_else(|err| panic!("invalid email template {name}: {err:#}"));
+    }
+    env.add_filter("datetime", datetime);
+    env
+});
+
+/// Renders `email` in the language of `locale`, with times in `time_zone`.
+pub fn render(locale: Locale, time_zone: Tz, email: &Email) -> Result<Rendered, Error> {
+    let name = email.template();
+    let ctx = context! {
+        locale => locale.as_str(),
+        time_zone => time_zone.name(),
+        ..Value::from_serialize(email)
+    };
+
+    let mut text = TEMPLATE_ENV
+        .get_template(&format!("{locale}/{name}.txt"))?
+        .render_captured(&ctx)?;
+    let subject = text
+        .with_state_mut(|state| state.render_block("subject"))?
+        .trim()
+        .to_string();
+    let text = text.into_output();
+    let html = TEMPLATE_ENV
+        .get_template(&format!("{locale}/{name}.html"))?
+        .render(context! { subject => &subject, ..ctx })?;
+    Ok(Rendered {
+        subject,
+        text,
+        html,
+    })
+}
+
+/// Formats an RFC 3339 timestamp for the locale and time zone being
+/// rendered.
+fn datetime(state: &State, value: &str) -> Result<String, Error> {
+    let time = DateTime::parse_from_rfc3339(value).map_err(|err| {
+        Error::new(ErrorKind::InvalidOperation, "not a date and time").with_source(err)
+    })?;
+    let time_zone = state
+        .lookup("time_zone")
+        .as_ref()
+        .and_then(Value::as_str)
+        .and_then(|name| name.parse().ok())
+        .unwrap_or(Tz::UTC);
+    let locale = state.lookup("locale");
+    let format = match locale.as_ref().and_then(Value::as_str) {
+        Some("de") => "%d.%m.%Y um %H:%M %Z",
+        _ => "%B %-d, %Y at %H:%M %Z",
+    };
+    Ok(time.with_timezone(&time_zone).format(format).to_string())
+}
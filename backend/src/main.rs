@@ -1,3 +1,273 @@
-fn main() {
-    println!("Hello, world!");
+use std::error::Error;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use backend::account::{deletion, export};
+use backend::auth::{password, session};
+use backend::config::{CliConfig, Config, ConfigError};
+use backend::import::keep;
+use backend::jobs;
+use backend::mail;
+use backend::migrations;
+use backend::notifications;
+use backend::reminders;
+use backend::schema::users;
+use backend::state::AppState;
+use backend::tasks;
+use backend::{rate_limit, routes, shutdown, telemetry};
+use clap::{Parser, Subcommand};
+use diesel::prelude::*;
+use dotenv::dotenv;
+use tokio::time::Instant;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Configuration file [default: $CONFIG_FILE, or config.toml if it exists]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP API (default)
+    Serve,
+    /// Import a Google Keep Takeout export into a user's notes
+    ImportKeep {
+        /// Email address of the user who will own the imported notes
+        #[arg(long)]
+        email: String,
+        /// Extracted Takeout directory, or its `Keep` folder
+        dir: PathBuf,
+    },
+    /// Apply, revert or list database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+    /// Benchmark password hashing and recommend argon2 parameters for this host
+    Argon2Params {
+        /// How long hashing a password may take, in milliseconds
+        #[arg(long, default_value_t = 500)]
+        target_ms: u64,
+        /// Upper bound for the memory cost, in MiB
+        #[arg(long, default_value_t = 256)]
+        max_memory_mib: u32,
+        /// Lanes hashed in parallel
+        #[arg(long, default_value_t = 1)]
+        parallelism: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// Apply all pending migrations
+    Up,
+    /// Revert the most recently applied migrations
+    Down {
+        /// How many migrations to revert
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List all migrations and whether they have been applied
+    Status,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    dotenv().ok();
+    let cli = Cli::parse();
+
+    let file = cli.config.as_deref();
+    let exit = |err: ConfigError| -> ! {
+        eprintln!("{err}");
+        process::exit(2);
+    };
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let config = Config::load(file).unwrap_or_else(|err| exit(err));
+            tokio::runtime::Runtime::new()?.block_on(serve(config))
+        }
+        Command::ImportKeep { email, dir } => {
+            let config = Config::load_cli(file).unwrap_or_else(|err| exit(err));
+            import_keep(&config, &email, &dir)
+        }
+        Command::Migrate { action } => {
+            let config = Config::load_cli(file).unwrap_or_else(|err| exit(err));
+            migrate(&config, action).map_err(|err| err.to_string().into())
+        }
+        Command::Argon2Params {
+            target_ms,
+            max_memory_mib,
+            parallelism,
+        } => argon2_params(target_ms, max_memory_mib, parallelism),
+    }
+}
+
+async fn serve(config: Config) -> Result<(), Box<dyn Error>> {
+    telemetry::init(&config.log);
+
+    let state = AppState::new(&config)?;
+    if config.server.run_migrations {
+        let pool = state.pool.clone();
+        let applied = tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            migrations::run_pending(&mut conn)
+        })
+        .await?
+        .map_err(|err| format!("cannot apply migrations: {err}"))?;
+        for name in applied {
+            tracing::info!("applied migration {name}");
+        }
+    }
+    let addr = config.server.bind_addr;
+
+    let hourly = Duration::from_secs(60 * 60);
+    tasks::spawn_periodic("export purge", hourly, state.clone(), export::purge_expired);
+    tasks::spawn_periodic(
+        "account deletion",
+        hourly,
+        state.clone(),
+        deletion::purge_due,
+    );
+    tasks::spawn_periodic("session purge", hourly, state.clone(), session::purge_stale);
+    tasks::spawn_periodic(
+        "rate limit purge",
+        hourly,
+        state.clone(),
+        rate_limit::purge_idle,
+    );
+    tasks::spawn_periodic("job purge", hourly, state.clone(), jobs::purge_completed);
+    tasks::spawn_periodic(
+        "reminders",
+        Duration::from_secs(30),
+        state.clone(),
+        reminders::send_due,
+    );
+
+    let registry = jobs::Registry::default()
+        .register::<export::AssembleExport>()
+        .register::<mail::SendEmail>();
+    jobs::worker::spawn(&state, registry, &config.jobs);
+    notifications::stream::spawn_listener(
+        state.notifications.clone(),
+        config.database.url.clone(),
+        state.shutdown.clone(),
+    );
+
+    let shutdown = state.shutdown.clone();
+    tokio::spawn(shutdown::handle_signals(
+        shutdown.clone(),
+        config.server.drain_delay,
+    ));
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("listening on {addr}");
+    let server = axum::serve(
+        listener,
+        routes::router(state, &config.cors).into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move { shutdown.closing().await }
+    })
+    .into_future();
+    tokio::pin!(server);
+
+    // Only an error ends the server before it is asked to close.
+    let deadline = tokio::select! {
+        biased;
+        () = shutdown.closing() => Instant::now() + config.server.shutdown_timeout,
+        result = &mut server => return Ok(result?),
+    };
+    tokio::select! {
+        result = &mut server => result?,
+        () = tokio::time::sleep_until(deadline) => {
+            tracing::warn!("cutting off requests still running at the shutdown deadline");
+        }
+        () = shutdown.stopping() => {}
+    }
+    match shutdown.wait_for_tasks(deadline).await {
+        0 => tracing::info!("shut down"),
+        remaining => tracing::warn!("shut down with {remaining} background tasks still running"),
+    }
+    Ok(())
+}
+
+fn import_keep(
+    config: &CliConfig,
+    email: &str,
+    dir: &std::path::Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut conn = PgConnection::establish(&config.database.url)?;
+    let store = config.storage.open();
+
+    let user_id = users::table
+        .filter(users::email.eq(email))
+        .select(users::id)
+        .first(&mut conn)
+        .optional()?
+        .ok_or_else(|| format!("no user with email {email}"))?;
+
+    let summary = keep::import_takeout(&mut conn, store.as_ref(), user_id, dir)?;
+    println!(
+        "Imported {} notes with {} attachments ({} trashed notes skipped)",
+        summary.imported, summary.attachments, summary.skipped_trashed
+    );
+    for file in &summary.missing_attachments {
+        eprintln!("warning: attachment {file} was not found in the export");
+    }
+    Ok(())
+}
+
+fn migrate(config: &CliConfig, action: MigrateAction) -> diesel::migration::Result<()> {
+    let mut conn = PgConnection::establish(&config.database.url)?;
+    match action {
+        MigrateAction::Up => {
+            let applied = migrations::run_pending(&mut conn)?;
+            if applied.is_empty() {
+                println!("The database is up to date");
+            }
+            for name in applied {
+                println!("Applied {name}");
+            }
+        }
+        MigrateAction::Down { steps } => {
+            for name in migrations::revert(&mut conn, steps)? {
+                println!("Reverted {name}");
+            }
+        }
+        MigrateAction::Status => {
+            for migration in migrations::status(&mut conn)? {
+                let mark = if migration.applied { 'x' } else { ' ' };
+                println!("[{mark}] {}", migration.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn argon2_params(
+    target_ms: u64,
+    max_memory_mib: u32,
+    parallelism: u32,
+) -> Result<(), Box<dyn Error>> {
+    let target = Duration::from_millis(target_ms);
+    let (params, elapsed) = password::recommend(target, max_memory_mib * 1024, parallelism)
+        .map_err(|err| format!("invalid argon2 parameters: {err}"))?;
+    if elapsed > target {
+        eprintln!(
+            "warning: even the smallest recommended parameters take {} ms on this host",
+            elapsed.as_millis()
+        );
+    }
+    println!("# one hash takes {} ms", elapsed.as_millis());
+    println!("ARGON2_MEMORY_KIB={}", params.memory_kib);
+    println!("ARGON2_ITERATIONS={}", params.iterations);
+    println!("ARGON2_PARALLELISM={}", params.parallelism);
+    Ok(())
 }
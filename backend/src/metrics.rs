@@ -0,0 +1,265 @@
+//! Prometheus metrics, served in the text exposition format at `/metrics`.
+//!
+//! Counters and histograms are updated as things happen; gauges such as the
+//! pool usage are read when the metrics are scraped.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::state::AppState;
+
+/// Upper bounds of the latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Stands in for the route of requests that matched none, so arbitrary
+/// paths cannot blow up the number of series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Request counts by method, route and status.
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// Request latencies by method and route.
+    latencies: Mutex<BTreeMap<(String, String), Histogram>>,
+    emails_sent: AtomicU64,
+    emails_failed: AtomicU64,
+    sse_connections: AtomicI64,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations per bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// Values read when the metrics are scraped.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub pool_connections: u32,
+    pub pool_idle: u32,
+    pub pool_max_size: u32,
+    /// Jobs waiting to be processed, by kind.
+    pub queue_depths: Vec<(String, i64)>,
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(seconds);
+    }
+
+    pub fn record_email(&self, sent: bool) {
+        let counter = if sent {
+            &self.emails_sent
+        } else {
+            &self.emails_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a server-sent event stream as open until the guard is dropped.
+    pub fn sse_opened(self: &Arc<Self>) -> ConnectionGuard {
+        self.sse_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Renders everything in the Prometheus text format.
+    pub fn render(&self, snapshot: &Snapshot) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "notesapp_http_requests_total",
+            "counter",
+            "HTTP requests handled, by method, route and status.",
+        );
+        for ((method, route, status), count) in self.requests.lock().unwrap().iter() {
+            let labels = format_labels(&[
+                ("method", method),
+                ("route", route),
+                ("status", &status.to_string()),
+            ]);
+            let _ = writeln!(out, "notesapp_http_requests_total{labels} {count}");
+        }
+
+        header(
+            &mut out,
+            "notesapp_http_request_duration_seconds",
+            "histogram",
+            "Time taken to handle HTTP requests, by method and route.",
+        );
+        for ((method, route), histogram) in self.latencies.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let labels = format_labels(&[
+                    ("method", method),
+                    ("route", route),
+                    ("le", &bound.to_string()),
+                ]);
+                let _ = writeln!(
+                    out,
+                    "notesapp_http_request_duration_seconds_bucket{labels} {cumulative}"
+                );
+            }
+            let labels_inf = format_labels(&[("method", method), ("route", route), ("le", "+Inf")]);
+            let labels = format_labels(&[("method", method), ("route", route)]);
+            let _ = writeln!(
+                out,
+                "notesapp_http_request_duration_seconds_bucket{labels_inf} {}\n\
+                 notesapp_http_request_duration_seconds_sum{labels} {}\n\
+                 notesapp_http_request_duration_seconds_count{labels} {}",
+                histogram.count, histogram.sum, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "notesapp_db_pool_connections",
+            "gauge",
+            "Open database connections, by whether they are in use.",
+        );
+        let active = snapshot.pool_connections.saturating_sub(snapshot.pool_idle);
+        let _ = writeln!(
+            out,
+            "notesapp_db_pool_connections{{state=\"active\"}} {active}\n\
+             notesapp_db_pool_connections{{state=\"idle\"}} {}",
+            snapshot.pool_idle
+        );
+        header(
+            &mut out,
+            "notesapp_db_pool_max_connections",
+            "gauge",
+            "Most database connections the pool opens.",
+        );
+        let _ = writeln!(
+            out,
+            "notesapp_db_pool_max_connections {}",
+            snapshot.pool_max_size
+        );
+
+        header(
+            &mut out,
+            "notesapp_job_queue_depth",
+            "gauge",
+            "Background jobs waiting to be processed, by queue.",
+        );
+        for (queue, depth) in &snapshot.queue_depths {
+            let _ = writeln!(
+                out,
+                "notesapp_job_queue_depth{} {depth}",
+                format_labels(&[("queue", queue.as_str())])
+            );
+        }
+
+        header(
+            &mut out,
+            "notesapp_emails_total",
+            "counter",
+            "Emails handed to the mail server, by result.",
+        );
+        let _ = writeln!(
+            out,
+            "notesapp_emails_total{{result=\"sent\"}} {}\n\
+             notesapp_emails_total{{result=\"failed\"}} {}",
+            self.emails_sent.load(Ordering::Relaxed),
+            self.emails_failed.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "notesapp_sse_connections",
+            "gauge",
+            "Server-sent event streams currently open.",
+        );
+        let _ = writeln!(
+            out,
+            "notesapp_sse_connections {}",
+            self.sse_connections.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+/// Keeps a connection counted while it is alive.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    metrics: Arc<Metrics>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics.sse_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Records the route, status and latency of every request.
+pub async fn track(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+        .to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.record_request(
+        &method,
+        &route,
+        response.status().as_u16(),
+        start.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+fn format_labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
@@ -0,0 +1,83 @@
+//! The schema migrations in `migrations/`, compiled into the binary so a
+//! release can bring its database up to date without the diesel CLI.
+
+use diesel::migration::{Migration, MigrationSource, Result};
+use diesel::pg::Pg;
+use diesel::{sql_query, PgConnection, RunQueryDsl};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+/// Arbitrary, but shared by every instance of the backend.
+const LOCK_KEY: i64 = 0x6e6f_7465_735f_6d67;
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub applied: bool,
+}
+
+/// Applies all pending migrations and returns their names. Instances that
+/// start at the same time wait for each other instead of racing.
+pub fn run_pending(conn: &mut PgConnection) -> Result<Vec<String>> {
+    with_lock(conn, |conn| {
+        let mut applied = Vec::new();
+        for migration in conn.pending_migrations(MIGRATIONS)? {
+            conn.run_migration(&migration)?;
+            applied.push(migration.name().to_string());
+        }
+        Ok(applied)
+    })
+}
+
+/// Reverts the `steps` most recently applied migrations, newest first, and
+/// returns their names. Stops early once none are left.
+pub fn revert(conn: &mut PgConnection, steps: usize) -> Result<Vec<String>> {
+    with_lock(conn, |conn| {
+        let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)?;
+        let steps = steps.min(conn.applied_migrations()?.len());
+        let mut reverted = Vec::with_capacity(steps);
+        for _ in 0..steps {
+            let version = conn.revert_last_migration(MIGRATIONS)?;
+            let name = migrations
+                .iter()
+                .find(|migration| migration.name().version() == version)
+                .map_or_else(
+                    || version.to_string(),
+                    |migration| migration.name().to_string(),
+                );
+            reverted.push(name);
+        }
+        Ok(reverted)
+    })
+}
+
+/// Every known migration in order, and whether it has been applied.
+pub fn status(conn: &mut PgConnection) -> Result<Vec<MigrationStatus>> {
+    let applied = conn.applied_migrations()?;
+    let mut migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)?;
+    migrations.sort_unstable_by(|a, b| a.name().version().cmp(&b.name().version()));
+    Ok(migrations
+        .iter()
+        .map(|migration| MigrationStatus {
+            name: migration.name().to_string(),
+            applied: applied.contains(&migration.name().version()),
+        })
+        .collect())
+}
+
+/// Names of the migrations that have not been applied yet.
+pub fn pending(conn: &mut PgConnection) -> Result<Vec<String>> {
+    let pending = conn.pending_migrations(MIGRATIONS)?;
+    Ok(pending.iter().map(|m| m.name().to_string()).collect())
+}
+
+fn with_lock<T>(
+    conn: &mut PgConnection,
+    f: impl FnOnce(&mut PgConnection) -> Result<T>,
+) -> Result<T> {
+    sql_query(format!("SELECT pg_advisory_lock({LOCK_KEY})")).execute(conn)?;
+    let result = f(conn);
+    sql_query(format!("SELECT pg_advisory_unlock({LOCK_KEY})")).execute(conn)?;
+    result
+}
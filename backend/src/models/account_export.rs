@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::pg_enum;
+use crate::schema::{account_exports, sql_types};
+
+pg_enum! {
+    pub enum ExportStatus: sql_types::ExportStatus {
+        Pending => "pending",
+        Ready => "ready",
+        Failed => "failed",
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = account_exports)]
+#[diesel(check_for_backend(Pg))]
+pub struct AccountExport {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: ExportStatus,
+    pub token_hash: String,
+    pub storage_key: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = account_exports)]
+pub struct NewAccountExport {
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportStatusResponse {
+    pub id: Uuid,
+    pub status: ExportStatus,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<&AccountExport> for ExportStatusResponse {
+    fn from(export: &AccountExport) -> Self {
+        Self {
+            id: export.id,
+            status: export.status,
+            created_at: export.created_at,
+            expires_at: export.expires_at,
+        }
+    }
+}
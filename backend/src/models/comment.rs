@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::schema::note_comments;
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = note_comments)]
+#[diesel(check_for_backend(Pg))]
+pub struct NoteComment {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    /// The author.
+    pub user_id: Uuid,
+    /// The first comment of the thread, unless this is it.
+    pub parent_id: Option<Uuid>,
+    pub body: String,
+    /// The characters of the note's content from `anchor_start` up to
+    /// `anchor_end` that a thread is about, and the text they held then.
+    pub anchor_start: Option<i32>,
+    pub anchor_end: Option<i32>,
+    pub anchor_text: Option<String>,
+    /// Set on the first comment only; resolves the whole thread.
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = note_comments)]
+pub struct NewNoteComment {
+    pub note_id: Uuid,
+    pub user_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub body: String,
+    pub anchor_start: Option<i32>,
+    pub anchor_end: Option<i32>,
+    pub anchor_text: Option<String>,
+}
@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::email_changes;
+
+/// A requested email change waiting for the new address to be confirmed.
+/// Only hashes of the emailed confirm and cancel tokens are kept.
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Insertable, AsChangeset)]
+#[diesel(table_name = email_changes)]
+#[diesel(primary_key(user_id))]
+#[diesel(check_for_backend(Pg))]
+pub struct EmailChange {
+    pub user_id: Uuid,
+    pub new_email: String,
+    pub confirm_token_hash: String,
+    pub cancel_token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,91 @@
+pub mod account_export;
+pub mod api_token;
+pub mod attachment;
+pub mod comment;
+pub mod email_change;
+pub mod job;
+pub mod login_event;
+pub mod note;
+pub mod notification;
+pub mod recovery_code;
+pub mod reminder;
+pub mod session;
+pub mod share;
+pub mod tag;
+pub mod user;
+pub mod user_identity;
+
+/// Declares a Rust enum backed by a Postgres enum type, with Diesel and serde
+/// support. Variants map to the given database labels.
+macro_rules! pg_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident: $sql_type:path {
+            $($(#[$variant_meta:meta])* $variant:ident => $label:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            PartialEq,
+            Eq,
+            diesel::expression::AsExpression,
+            diesel::deserialize::FromSqlRow,
+            serde::Serialize,
+            serde::Deserialize,
+        )]
+        #[diesel(sql_type = $sql_type)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                #[serde(rename = $label)]
+                $variant,
+            )+
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $label,)+
+                }
+            }
+
+            pub fn parse(value: &str) -> Option<Self> {
+                match value {
+                    $($label => Some($name::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl diesel::serialize::ToSql<$sql_type, diesel::pg::Pg> for $name {
+            fn to_sql<'b>(
+                &'b self,
+                out: &mut diesel::serialize::Output<'b, '_, diesel::pg::Pg>,
+            ) -> diesel::serialize::Result {
+                use std::io::Write;
+                out.write_all(self.as_str().as_bytes())?;
+                Ok(diesel::serialize::IsNull::No)
+            }
+        }
+
+        impl diesel::deserialize::FromSql<$sql_type, diesel::pg::Pg> for $name {
+            fn from_sql(bytes: diesel::pg::PgValue<'_>) -> diesel::deserialize::Result<Self> {
+                let value = std::str::from_utf8(bytes.as_bytes())?;
+                $name::parse(value).ok_or_else(|| {
+                    format!("Unrecognized {} variant: {value}", stringify!($name)).into()
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use pg_enum;
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::pg_enum;
+use crate::schema::{notes, sql_types};
+
+pg_enum! {
+    #[derive(Default)]
+    pub enum NoteColor: sql_types::NoteColor {
+        #[default]
+        Default => "default",
+        Red => "red",
+        Orange => "orange",
+        Yellow => "yellow",
+        Green => "green",
+        Teal => "teal",
+        Blue => "blue",
+        Cerulean => "cerulean",
+        Purple => "purple",
+        Pink => "pink",
+        Brown => "brown",
+        Gray => "gray",
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = notes)]
+#[diesel(check_for_backend(Pg))]
+pub struct Note {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub color: NoteColor,
+    pub pinned: bool,
+    pub archived: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = notes)]
+pub struct NewNote {
+    pub user_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub color: NoteColor,
+    pub pinned: bool,
+    pub archived: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::pg_enum;
+use crate::schema::{notification_preferences, notifications, sql_types};
+
+pg_enum! {
+    #[derive(Hash, PartialOrd, Ord)]
+    pub enum NotificationKind: sql_types::NotificationKind {
+        Reminder => "reminder",
+        Mention => "mention",
+    }
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 2] = [NotificationKind::Reminder, NotificationKind::Mention];
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = notifications)]
+#[diesel(check_for_backend(Pg))]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    pub note_id: Option<Uuid>,
+    pub data: Value,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = notifications)]
+pub struct NewNotification {
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    pub note_id: Option<Uuid>,
+    pub data: Value,
+}
+
+/// How the user wants to hear about one kind of notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub in_app: bool,
+    pub email: bool,
+}
+
+impl Default for Delivery {
+    fn default() -> Self {
+        Self {
+            in_app: true,
+            email: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = notification_preferences)]
+#[diesel(check_for_backend(Pg))]
+pub struct NotificationPreference {
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    pub in_app: bool,
+    pub email: bool,
+}
+
+impl From<&NotificationPreference> for Delivery {
+    fn from(preference: &NotificationPreference) -> Self {
+        Self {
+            in_app: preference.in_app,
+            email: preference.email,
+        }
+    }
+}
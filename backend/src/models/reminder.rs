@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::schema::note_reminders;
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = note_reminders)]
+#[diesel(primary_key(note_id))]
+#[diesel(check_for_backend(Pg))]
+pub struct NoteReminder {
+    pub note_id: Uuid,
+    /// When the note is due. Moves to the next occurrence when a recurring
+    /// reminder is completed.
+    pub remind_at: DateTime<Utc>,
+    /// RRULE value, see [`crate::reminders::recurrence`].
+    pub recurrence: Option<String>,
+    /// When the reminder goes out, unless it already has.
+    pub notify_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, AsChangeset)]
+#[diesel(table_name = note_reminders)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewNoteReminder {
+    pub note_id: Uuid,
+    pub remind_at: DateTime<Utc>,
+    pub recurrence: Option<String>,
+    pub notify_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::pg_enum;
+use crate::schema::{note_shares, sql_types};
+
+pg_enum! {
+    pub enum SharePermission: sql_types::SharePermission {
+        /// Reads the note and its comments.
+        Viewer => "viewer",
+        /// Also comments, but cannot change the note.
+        Commenter => "commenter",
+        /// Also changes the note's title and content.
+        Editor => "editor",
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = note_shares)]
+#[diesel(primary_key(note_id, user_id))]
+#[diesel(check_for_backend(Pg))]
+pub struct NoteShare {
+    pub note_id: Uuid,
+    /// Who the note is shared with.
+    pub user_id: Uuid,
+    pub permission: SharePermission,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = note_shares)]
+pub struct NewNoteShare {
+    pub note_id: Uuid,
+    pub user_id: Uuid,
+    pub permission: SharePermission,
+}
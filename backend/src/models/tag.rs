@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::schema::{note_tags, tags};
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = tags)]
+#[diesel(check_for_backend(Pg))]
+pub struct Tag {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = tags)]
+pub struct NewTag<'a> {
+    pub user_id: Uuid,
+    pub name: &'a str,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = note_tags)]
+pub struct NoteTag {
+    pub note_id: Uuid,
+    pub tag_id: Uuid,
+}
+
+/// Returns the id of the user's tag called `name`, creating it if needed.
+pub fn find_or_create(conn: &mut PgConnection, user_id: Uuid, name: &str) -> QueryResult<Uuid> {
+    diesel::insert_into(tags::table)
+        .values(NewTag { user_id, name })
+        .on_conflict((tags::user_id, tags::name))
+        .do_nothing()
+        .execute(conn)?;
+
+    tags::table
+        .filter(tags::user_id.eq(user_id))
+        .filter(tags::name.eq(name))
+        .select(tags::id)
+        .first(conn)
+}
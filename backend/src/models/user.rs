@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::pg_enum;
+use crate::schema::{sql_types, users};
+
+pg_enum! {
+    pub enum UserRole: sql_types::UserRole {
+        Admin => "admin",
+        User => "user",
+    }
+}
+
+pg_enum! {
+    /// Language of the emails sent to a user.
+    #[derive(Default)]
+    pub enum Locale: sql_types::Locale {
+        #[default]
+        En => "en",
+        De => "de",
+    }
+}
+
+impl Locale {
+    /// The most preferred supported language of an `Accept-Language`
+    /// header, e.g. `de-CH, de;q=0.9, en;q=0.8`.
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut preferences: Vec<(f32, Locale)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.trim().split(';');
+                let tag = parts.next()?.trim();
+                let language = tag.split('-').next()?.to_ascii_lowercase();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                Some((quality, Locale::parse(&language)?))
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            .collect();
+        // Stable, so equally preferred languages keep their order.
+        preferences.sort_by(|a, b| b.0.total_cmp(&a.0));
+        preferences.first().map(|(_, locale)| *locale)
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = users)]
+#[diesel(check_for_backend(Pg))]
+pub struct User {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub verified: bool,
+    pub password: String,
+    pub verification_token: Option<String>,
+    pub token_expires_at: Option<DateTime<Utc>>,
+    pub role: UserRole,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    pub totp_last_step: Option<i64>,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub locale: Locale,
+    pub time_zone: String,
+    pub calendar_token_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = users)]
+pub struct NewUser {
+    pub name: String,
+    pub email: String,
+    pub verified: bool,
+    pub password: String,
+    pub verification_token: Option<String>,
+    pub token_expires_at: Option<DateTime<Utc>>,
+    pub locale: Locale,
+    pub time_zone: String,
+}
+
+impl User {
+    pub fn two_factor_enabled(&self) -> bool {
+        self.totp_enabled_at.is_some()
+    }
+
+    /// The user's time zone. Names are checked when they are set, but one
+    /// that a later tz database no longer knows falls back to UTC.
+    pub fn tz(&self) -> Tz {
+        parse_time_zone(&self.time_zone)
+    }
+}
+
+/// Reads a stored time zone name, see [`User::tz`].
+pub fn parse_time_zone(name: &str) -> Tz {
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+/// Accepts IANA time zone names such as `Europe/Berlin`.
+pub fn validate_time_zone(name: &str) -> Result<(), validator::ValidationError> {
+    match name.parse::<Tz>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(validator::ValidationError::new("time_zone")
+            .with_message("not a known time zone, expected a name like Europe/Berlin".into())),
+    }
+}
+
+/// The parts of a user that are safe to hand out over the API.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub verified: bool,
+    pub role: UserRole,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    pub two_factor_enabled: bool,
+    pub locale: Locale,
+    pub time_zone: String,
+    /// Whether a calendar feed URL has been created.
+    pub calendar_feed: bool,
+}
+
+impl UserProfile {
+    /// See [`User::tz`].
+    pub fn tz(&self) -> Tz {
+        parse_time_zone(&self.time_zone)
+    }
+}
+
+impl From<&User> for UserProfile {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id,
+            name: user.name.clone(),
+            email: user.email.clone(),
+            verified: user.verified,
+            role: user.role,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            deletion_scheduled_at: user.deletion_scheduled_at,
+            two_factor_enabled: user.two_factor_enabled(),
+            locale: user.locale,
+            time_zone: user.time_zone.clone(),
+            calendar_feed: user.calendar_token_hash.is_some(),
+        }
+    }
+}
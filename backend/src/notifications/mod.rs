@@ -0,0 +1,185 @@
+//! Notifications about things that happened to a user's notes. Each kind is
+//! delivered in the app, by email, or both, as the user prefers. In-app
+//! notifications are listed at `/api/notifications` and pushed to connected
+//! clients as they are committed, see [`stream`].
+
+pub mod stream;
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use uuid::Uuid;
+
+use crate::mail::{self, Email};
+use crate::models::notification::{
+    Delivery, NewNotification, Notification, NotificationKind, NotificationPreference,
+};
+use crate::models::user::User;
+use crate::schema::{notification_preferences, notifications};
+
+/// Notifies `user` in the ways they chose for its kind: in the app, with
+/// `email`, or both. Runs in the caller's transaction.
+pub fn notify(
+    conn: &mut PgConnection,
+    user: &User,
+    notification: &NewNotification,
+    email: Email,
+) -> QueryResult<()> {
+    let delivery = delivery(conn, user.id, notification.kind)?;
+    if delivery.in_app {
+        diesel::insert_into(notifications::table)
+            .values(notification)
+            .execute(conn)?;
+    }
+    if delivery.email {
+        mail::enqueue(conn, &user.email, user.locale, user.tz(), email)?;
+    }
+    Ok(())
+}
+
+pub fn delivery(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    kind: NotificationKind,
+) -> QueryResult<Delivery> {
+    let preference = notification_preferences::table
+        .find((user_id, kind))
+        .select(NotificationPreference::as_select())
+        .first(conn)
+        .optional()?;
+    Ok(preference.as_ref().map(Delivery::from).unwrap_or_default())
+}
+
+/// How the user wants to hear about every kind of notification.
+pub fn preferences(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> QueryResult<BTreeMap<NotificationKind, Delivery>> {
+    let mut preferences: BTreeMap<_, _> = NotificationKind::ALL
+        .into_iter()
+        .map(|kind| (kind, Delivery::default()))
+        .collect();
+    for preference in notification_preferences::table
+        .filter(notification_preferences::user_id.eq(user_id))
+        .select(NotificationPreference::as_select())
+        .load(conn)?
+    {
+        preferences.insert(preference.kind, Delivery::from(&preference));
+    }
+    Ok(preferences)
+}
+
+/// Changes the preferences for the given kinds and leaves the others alone.
+pub fn set_preferences(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    changes: &BTreeMap<NotificationKind, Delivery>,
+) -> QueryResult<()> {
+    let rows: Vec<NotificationPreference> = changes
+        .iter()
+        .map(|(&kind, delivery)| NotificationPreference {
+            user_id,
+            kind,
+            in_app: delivery.in_app,
+            email: delivery.email,
+        })
+        .collect();
+    if rows.is_empty() {
+        return Ok(());
+    }
+    diesel::insert_into(notification_preferences::table)
+        .values(&rows)
+        .on_conflict((
+            notification_preferences::user_id,
+            notification_preferences::kind,
+        ))
+        .do_update()
+        .set((
+            notification_preferences::in_app.eq(excluded(notification_preferences::in_app)),
+            notification_preferences::email.eq(excluded(notification_preferences::email)),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NotificationFilter {
+    pub unread: bool,
+    /// Only notifications created before this one, for paging.
+    pub before: Option<Uuid>,
+    pub limit: i64,
+}
+
+/// The user's notifications matching `filter`, newest first.
+pub fn list(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    filter: NotificationFilter,
+) -> QueryResult<Vec<Notification>> {
+    let mut query = notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .order((notifications::created_at.desc(), notifications::id.desc()))
+        .limit(filter.limit)
+        .select(Notification::as_select())
+        .into_boxed();
+    if filter.unread {
+        query = query.filter(notifications::read_at.is_null());
+    }
+    if let Some(before) = filter.before {
+        let (created_at, id) = notifications::table
+            .find(before)
+            .filter(notifications::user_id.eq(user_id))
+            .select((notifications::created_at, notifications::id))
+            .first::<(DateTime<Utc>, Uuid)>(conn)?;
+        query = query.filter(
+            notifications::created_at
+                .lt(created_at)
+                .or(notifications::created_at
+                    .eq(created_at)
+                    .and(notifications::id.lt(id))),
+        );
+    }
+    query.load(conn)
+}
+
+pub fn find(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> QueryResult<Notification> {
+    notifications::table
+        .find(id)
+        .filter(notifications::user_id.eq(user_id))
+        .select(Notification::as_select())
+        .first(conn)
+}
+
+pub fn unread_count(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<i64> {
+    notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .filter(notifications::read_at.is_null())
+        .select(count_star())
+        .first(conn)
+}
+
+/// Marks one notification as read; one that already is keeps its time.
+pub fn mark_read(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> QueryResult<Notification> {
+    let notification = find(conn, user_id, id)?;
+    if notification.read_at.is_some() {
+        return Ok(notification);
+    }
+    diesel::update(notifications::table.find(id))
+        .set(notifications::read_at.eq(Utc::now()))
+        .returning(Notification::as_returning())
+        .get_result(conn)
+}
+
+/// Marks every unread notification as read. Returns how many there were.
+pub fn mark_all_read(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<usize> {
+    diesel::update(
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null()),
+    )
+    .set(notifications::read_at.eq(Utc::now()))
+    .execute(conn)
+}
@@ -0,0 +1,140 @@
+//! Pushing new notifications to connected clients. Inserting a notification
+//! sends a Postgres `NOTIFY` once the transaction commits, whichever
+//! instance inserted it. Every instance listens on one connection of its
+//! own and passes what it hears on to the streams of the users concerned.
+//!
+//! Notifications sent while the listener reconnects are not pushed; clients
+//! catch up by listing them.
+
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel::result::ConnectionError;
+use thiserror::Error;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::shutdown::Shutdown;
+
+const CHANNEL: &str = "notifications";
+/// How often the listener checks for news.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the listener waits before reconnecting after an error.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Notifications buffered for a stream that falls behind.
+const CAPACITY: usize = 1024;
+
+/// A notification that has been committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Created {
+    pub user_id: Uuid,
+    pub id: Uuid,
+}
+
+impl Created {
+    /// Reads the payload written by the `notifications_notify` trigger.
+    fn parse(payload: &str) -> Option<Self> {
+        let (user_id, id) = payload.split_once(' ')?;
+        Some(Self {
+            user_id: user_id.parse().ok()?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// Hands committed notifications to the streams of this instance.
+#[derive(Debug, Clone)]
+pub struct Hub {
+    sender: broadcast::Sender<Created>,
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Hub {
+    /// Every notification committed from now on, for all users.
+    pub fn subscribe(&self) -> broadcast::Receiver<Created> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, created: Created) {
+        // Nobody may be listening.
+        let _ = self.sender.send(created);
+    }
+}
+
+#[derive(Debug, Error)]
+enum ListenError {
+    #[error("cannot connect: {0}")]
+    Connect(#[from] ConnectionError),
+    #[error("{0}")]
+    Query(#[from] diesel::result::Error),
+}
+
+/// Listens for new notifications on a connection to `database_url` until
+/// shutdown is triggered, reconnecting after errors.
+pub fn spawn_listener(hub: Hub, database_url: String, shutdown: Shutdown) {
+    shutdown.clone().spawn(async move {
+        let mut conn: Option<PgConnection> = None;
+        loop {
+            let url = database_url.clone();
+            let polled = tokio::task::spawn_blocking(move || {
+                let result = poll(&mut conn, &url);
+                (conn, result)
+            })
+            .await;
+            let delay = match polled {
+                Ok((returned, Ok(created))) => {
+                    conn = returned;
+                    created.into_iter().for_each(|created| hub.publish(created));
+                    POLL_INTERVAL
+                }
+                Ok((_, Err(err))) => {
+                    tracing::warn!("notification listener: {err}");
+                    conn = None;
+                    RETRY_DELAY
+                }
+                Err(err) => {
+                    tracing::error!("notification listener panicked: {err}");
+                    conn = None;
+                    RETRY_DELAY
+                }
+            };
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {}
+                () = shutdown.triggered() => break,
+            }
+        }
+    });
+}
+
+/// Connects if needed and returns the notifications received since the last
+/// poll.
+fn poll(conn: &mut Option<PgConnection>, database_url: &str) -> Result<Vec<Created>, ListenError> {
+    let conn = match conn {
+        Some(conn) => conn,
+        None => {
+            let mut new_conn = PgConnection::establish(database_url)?;
+            diesel::sql_query(format!("LISTEN {CHANNEL}")).execute(&mut new_conn)?;
+            conn.insert(new_conn)
+        }
+    };
+    let mut created = Vec::new();
+    for notification in conn.notifications_iter() {
+        let notification = notification?;
+        match Created::parse(&notification.payload) {
+            Some(parsed) if notification.channel == CHANNEL => created.push(parsed),
+            _ => tracing::warn!(
+                "unexpected notification {:?} on {}",
+                notification.payload,
+                notification.channel
+            ),
+        }
+    }
+    Ok(created)
+}
@@ -0,0 +1,323 @@
+//! Token bucket rate limiting for the login endpoints.
+//!
+//! Every client IP and every targeted account gets its own bucket.
+//! Buckets live in memory by default; deployments running several instances
+//! can keep them in Postgres so all instances share the same limits.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::body::{self, Body};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use crate::auth::{self, token};
+use crate::db::{self, DbPool};
+use crate::error::{AppError, AppResult};
+use crate::schema::rate_limit_buckets;
+use crate::state::AppState;
+
+/// Login requests are tiny; anything larger is rejected by the handler anyway.
+const MAX_LOGIN_BODY_BYTES: usize = 16 * 1024;
+
+/// A bucket holding up to `burst` tokens, refilled at `per_minute`.
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Limit {
+    pub fn new(burst: u32, per_minute: u32) -> Result<Self, String> {
+        if burst == 0 || per_minute == 0 {
+            return Err("rate limits need a burst and a refill rate of at least 1".into());
+        }
+        Ok(Self { burst, per_minute })
+    }
+
+    fn per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// How long an empty bucket takes to fill up again.
+    fn refill_time(&self) -> Duration {
+        Duration::from_secs_f64(f64::from(self.burst) / self.per_second())
+    }
+
+    /// Refills a bucket that held `tokens` `elapsed` ago and tries to take one
+    /// token. Returns the new level and, if no token was available, how long
+    /// until there is one.
+    fn take(&self, tokens: f64, elapsed: Duration) -> (f64, Option<Duration>) {
+        let refilled = (tokens + elapsed.as_secs_f64() * self.per_second()).min(self.burst.into());
+        if refilled >= 1.0 {
+            (refilled - 1.0, None)
+        } else {
+            let wait = (1.0 - refilled) / self.per_second();
+            (refilled, Some(Duration::from_secs_f64(wait)))
+        }
+    }
+}
+
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from the bucket under `key`. Returns `Some(wait)` if the
+    /// bucket is empty.
+    async fn acquire(&self, key: &str, limit: Limit) -> AppResult<Option<Duration>>;
+
+    /// Forgets buckets that have not been touched for `idle`.
+    async fn purge_idle(&self, idle: Duration) -> AppResult<usize>;
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn acquire(&self, key: &str, limit: Limit) -> AppResult<Option<Duration>> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, updated_at) = buckets
+            .get(key)
+            .copied()
+            .unwrap_or((limit.burst.into(), now));
+        let (tokens, wait) = limit.take(tokens, now - updated_at);
+        buckets.insert(key.to_owned(), (tokens, now));
+        Ok(wait)
+    }
+
+    async fn purge_idle(&self, idle: Duration) -> AppResult<usize> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, (_, updated_at)| updated_at.elapsed() < idle);
+        Ok(before - buckets.len())
+    }
+}
+
+pub struct PgStore {
+    pool: DbPool,
+}
+
+impl PgStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PgStore {
+    async fn acquire(&self, key: &str, limit: Limit) -> AppResult<Option<Duration>> {
+        let key = key.to_owned();
+        db::run(&self.pool, move |conn| {
+            // The row lock serializes concurrent instances on the same bucket.
+            conn.transaction(|conn| {
+                let now = Utc::now();
+                diesel::insert_into(rate_limit_buckets::table)
+                    .values((
+                        rate_limit_buckets::key.eq(&key),
+                        rate_limit_buckets::tokens.eq(f64::from(limit.burst)),
+                        rate_limit_buckets::updated_at.eq(now),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                let (tokens, updated_at): (f64, DateTime<Utc>) = rate_limit_buckets::table
+                    .find(&key)
+                    .select((rate_limit_buckets::tokens, rate_limit_buckets::updated_at))
+                    .for_update()
+                    .first(conn)?;
+
+                let elapsed = (now - updated_at).to_std().unwrap_or_default();
+                let (tokens, wait) = limit.take(tokens, elapsed);
+                diesel::update(rate_limit_buckets::table.find(&key))
+                    .set((
+                        rate_limit_buckets::tokens.eq(tokens),
+                        rate_limit_buckets::updated_at.eq(now),
+                    ))
+                    .execute(conn)?;
+                Ok::<_, diesel::result::Error>(wait)
+            })
+        })
+        .await
+    }
+
+    async fn purge_idle(&self, idle: Duration) -> AppResult<usize> {
+        let cutoff = Utc::now() - chrono::Duration::from_std(idle).map_err(AppError::internal)?;
+        db::run(&self.pool, move |conn| {
+            diesel::delete(rate_limit_buckets::table)
+                .filter(rate_limit_buckets::updated_at.lt(cutoff))
+                .execute(conn)
+        })
+        .await
+    }
+}
+
+/// The limits applied to logging in, finishing a login with a second factor
+/// and requesting a password reset. Each of them has its own buckets, so
+/// using up one does not block the others.
+#[derive(Clone)]
+pub struct LoginLimits {
+    pub store: Arc<dyn RateLimitStore>,
+    pub per_ip: Limit,
+    /// Per target account, by email address or two-factor challenge.
+    pub per_email: Limit,
+}
+
+/// The parts of a request body that name the targeted account.
+#[derive(Deserialize)]
+struct LoginTarget {
+    email: Option<String>,
+    challenge_token: Option<String>,
+}
+
+/// Middleware for `POST /api/auth/login`.
+pub async fn limit_login(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    limit(&state, "login", request, next, |_, target| {
+        target.email.map(|email| email_key(&email))
+    })
+    .await
+}
+
+/// Middleware for `POST /api/auth/password/forgot`. Separate from the login
+/// buckets, as anyone can request a reset for any address and must not be
+/// able to use up its owner's login attempts that way.
+pub async fn limit_password_reset(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    limit(&state, "reset", request, next, |_, target| {
+        target.email.map(|email| email_key(&email))
+    })
+    .await
+}
+
+/// Middleware for `POST /api/auth/login/2fa`, keyed by the user the
+/// challenge was issued to, so guessing codes is slowed down on top of the
+/// lockout. The challenge comes from the body or the challenge cookie.
+pub async fn limit_two_factor(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let cookie = CookieJar::from_headers(request.headers())
+        .get(auth::CHALLENGE_COOKIE)
+        .map(|cookie| cookie.value().to_owned());
+    limit(&state, "2fa", request, next, |state, target| {
+        let challenge = target.challenge_token.or(cookie)?;
+        let user_id = state.jwt.verify_challenge(&challenge)?;
+        Some(format!("user:{user_id}"))
+    })
+    .await
+}
+
+/// Hashed so the key has a fixed length and stored buckets reveal no addresses.
+fn email_key(email: &str) -> String {
+    format!("email:{}", token::hash(&email.trim().to_lowercase()))
+}
+
+/// Takes a token from the IP bucket and from the bucket of the targeted
+/// account, both under `prefix`. The IP bucket is checked first so that a
+/// client hammering many accounts cannot drain their buckets.
+async fn limit(
+    state: &AppState,
+    prefix: &str,
+    request: Request,
+    next: Next,
+    account_key: impl FnOnce(&AppState, LoginTarget) -> Option<String>,
+) -> Result<Response, AppError> {
+    let limits = &state.login_limits;
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(ip) = ip {
+        check(limits, &format!("{prefix}:ip:{ip}"), limits.per_ip).await?;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = body::to_bytes(body, MAX_LOGIN_BODY_BYTES)
+        .await
+        .map_err(|_| AppError::PayloadTooLarge("request body is too large".into()))?;
+    let key = serde_json::from_slice::<LoginTarget>(&bytes)
+        .ok()
+        .and_then(|target| account_key(state, target));
+    if let Some(key) = key {
+        check(limits, &format!("{prefix}:{key}"), limits.per_email).await?;
+    }
+
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+        .into_response())
+}
+
+async fn check(limits: &LoginLimits, key: &str, limit: Limit) -> AppResult<()> {
+    match limits.store.acquire(key, limit).await? {
+        None => Ok(()),
+        Some(retry_after) => {
+            tracing::warn!(key, "rate limit exceeded");
+            Err(AppError::TooManyRequests {
+                message: "too many attempts, try again later".into(),
+                retry_after,
+            })
+        }
+    }
+}
+
+/// Drops buckets that have refilled completely, as they behave exactly like
+/// missing ones.
+pub async fn purge_idle(state: AppState) -> AppResult<usize> {
+    let limits = &state.login_limits;
+    let idle = limits
+        .per_ip
+        .refill_time()
+        .max(limits.per_email.refill_time());
+    limits.store.purge_idle(idle).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn takes_tokens_until_empty() {
+        let limit = Limit::new(3, 60).unwrap();
+        assert_eq!(limit.take(3.0, Duration::ZERO), (2.0, None));
+        assert_eq!(limit.take(1.0, Duration::ZERO), (0.0, None));
+        assert_eq!(limit.take(0.0, Duration::ZERO), (0.0, Some(secs(1.0))));
+    }
+
+    #[test]
+    fn refills_at_the_rate_up_to_the_burst() {
+        let limit = Limit::new(5, 30).unwrap();
+        // Half a token per second.
+        assert_eq!(limit.take(0.0, secs(2.0)), (0.0, None));
+        assert_eq!(limit.take(0.0, secs(1.0)), (0.5, Some(secs(1.0))));
+        assert_eq!(limit.take(0.25, secs(0.5)), (0.5, Some(secs(1.0))));
+        assert_eq!(limit.take(4.0, secs(3600.0)), (4.0, None));
+        assert_eq!(limit.refill_time(), secs(10.0));
+    }
+
+    #[test]
+    fn rejects_empty_limits() {
+        assert!(Limit::new(0, 10).is_err());
+        assert!(Limit::new(10, 0).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Reminders on notes. A note has at most one, which may recur. When it is
+//! due a periodic task notifies the owner in the app and by email; the owner
+//! can then snooze it or complete it, which moves a recurring reminder on to
+//! its next occurrence.
+//!
+//! Times without an offset and recurrence rules are read in the owner's time
+//! zone.
+
+pub mod recurrence;
+
+use chrono::{DateTime, DurationRound, NaiveDateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+use self::recurrence::Recurrence;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::mail::Email;
+use crate::models::note::Note;
+use crate::models::notification::{NewNotification, NotificationKind};
+use crate::models::reminder::{NewNoteReminder, NoteReminder};
+use crate::models::user::{parse_time_zone, User};
+use crate::notifications;
+use crate::schema::{note_reminders, notes, users};
+use crate::state::AppState;
+
+/// Reminders sent per transaction.
+const BATCH_SIZE: i64 = 100;
+
+/// How far from now reminders may be set. Recurrences are expanded from
+/// the reminder on, so times centuries away would make that expensive.
+pub const MAX_YEARS_AWAY: i64 = 100;
+
+/// Reads an RFC 3339 time, or a local time like `2026-10-19T09:00` in
+/// `tz`. Times more than [`MAX_YEARS_AWAY`] years from now are rejected.
+pub fn parse_time(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    let time = match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => time.with_timezone(&Utc),
+        Err(_) => ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+            .into_iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .map(|local| recurrence::resolve(tz, local))?,
+    };
+    let max_away = TimeDelta::days(MAX_YEARS_AWAY * 366);
+    ((Utc::now() - time).abs() <= max_away).then_some(time)
+}
+
+/// The time zone of the user, see [`User::tz`].
+pub fn time_zone(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Tz> {
+    let name: String = users::table
+        .find(user_id)
+        .select(users::time_zone)
+        .first(conn)?;
+    Ok(parse_time_zone(&name))
+}
+
+pub fn find(conn: &mut PgConnection, note_id: Uuid) -> QueryResult<Option<NoteReminder>> {
+    note_reminders::table
+        .find(note_id)
+        .select(NoteReminder::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Sets the reminder of the note, replacing the one it had. Reminders go
+/// off on the minute.
+pub fn set(
+    conn: &mut PgConnection,
+    note_id: Uuid,
+    remind_at: DateTime<Utc>,
+    recurrence: Option<&Recurrence>,
+) -> QueryResult<NoteReminder> {
+    let remind_at = remind_at
+        .duration_trunc(TimeDelta::minutes(1))
+        .unwrap_or(remind_at);
+    let reminder = NewNoteReminder {
+        note_id,
+        remind_at,
+        recurrence: recurrence.map(Recurrence::to_string),
+        notify_at: Some(remind_at),
+        completed_at: None,
+    };
+    diesel::insert_into(note_reminders::table)
+        .values(&reminder)
+        .on_conflict(note_reminders::note_id)
+        .do_update()
+        .set(&reminder)
+        .returning(NoteReminder::as_returning())
+        .get_result(conn)
+}
+
+/// Removes the reminder of the note. Returns whether it had one.
+pub fn remove(conn: &mut PgConnection, note_id: Uuid) -> QueryResult<bool> {
+    let deleted = diesel::delete(note_reminders::table.find(note_id)).execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Sends the reminder again at `until`, whether or not it has gone out yet.
+pub fn snooze(
+    conn: &mut PgConnection,
+    note_id: Uuid,
+    until: DateTime<Utc>,
+) -> AppResult<NoteReminder> {
+    let reminder = find(conn, note_id)?.ok_or(AppError::NotFound)?;
+    if reminder.completed_at.is_some() {
+        return Err(AppError::Conflict(
+            "the reminder is already completed".into(),
+        ));
+    }
+    if until <= Utc::now() {
+        return Err(AppError::BadRequest(
+            "a reminder can only be snoozed until a later time".into(),
+        ));
+    }
+    Ok(diesel::update(note_reminders::table.find(note_id))
+        .set(note_reminders::notify_at.eq(until))
+        .returning(NoteReminder::as_returning())
+        .get_result(conn)?)
+}
+
+/// Completes the current occurrence. A recurring reminder moves on to the
+/// first occurrence still ahead; occurrences missed in between are skipped.
+pub fn complete(conn: &mut PgConnection, note_id: Uuid, tz: Tz) -> AppResult<NoteReminder> {
+    let reminder = find(conn, note_id)?.ok_or(AppError::NotFound)?;
+    if reminder.completed_at.is_some() {
+        return Err(AppError::Conflict(
+            "the reminder is already completed".into(),
+        ));
+    }
+
+    let now = Utc::now();
+    let next = match &reminder.recurrence {
+        Some(rule) => rule
+            .parse::<Recurrence>()
+            .map_err(AppError::internal)?
+            .next_after(reminder.remind_at, now, tz),
+        None => None,
+    };
+    let changes = match next {
+        Some((remind_at, rule)) => NewNoteReminder {
+            note_id,
+            remind_at,
+            recurrence: Some(rule.to_string()),
+            notify_at: Some(remind_at),
+            completed_at: None,
+        },
+        None => NewNoteReminder {
+            note_id,
+            remind_at: reminder.remind_at,
+            recurrence: reminder.recurrence,
+            notify_at: None,
+            completed_at: Some(now),
+        },
+    };
+    Ok(diesel::update(note_reminders::table.find(note_id))
+        .set(&changes)
+        .returning(NoteReminder::as_returning())
+        .get_result(conn)?)
+}
+
+/// Sends the reminders that are due, in the app and by email as each owner
+/// prefers. A reminder stays due until it is completed, but goes out once
+/// unless it is snoozed.
+pub async fn send_due(state: AppState) -> AppResult<usize> {
+    let public_url = state.public_url.trim_end_matches('/').to_string();
+    let mut sent = 0;
+    loop {
+        let public_url = public_url.clone();
+        let batch = db::run(&state.pool, move |conn| {
+            conn.transaction(|conn| send_batch(conn, &public_url))
+        })
+        .await?;
+        sent += batch;
+        if batch < BATCH_SIZE as usize {
+            return Ok(sent);
+        }
+    }
+}
+
+fn send_batch(conn: &mut PgConnection, public_url: &str) -> QueryResult<usize> {
+    // Locking only the reminders lets other instances take the next batch
+    // and keeps the notes editable meanwhile.
+    let due: Vec<Uuid> = note_reminders::table
+        .filter(note_reminders::notify_at.le(Utc::now()))
+        .order(note_reminders::notify_at.asc())
+        .limit(BATCH_SIZE)
+        .select(note_reminders::note_id)
+        .for_update()
+        .skip_locked()
+        .load(conn)?;
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    diesel::update(note_reminders::table.filter(note_reminders::note_id.eq_any(&due)))
+        .set(note_reminders::notify_at.eq(None::<DateTime<Utc>>))
+        .execute(conn)?;
+    let reminders: Vec<(NoteReminder, Note, User)> = note_reminders::table
+        .inner_join(notes::table.inner_join(users::table))
+        .filter(note_reminders::note_id.eq_any(&due))
+        .select((
+            NoteReminder::as_select(),
+            Note::as_select(),
+            User::as_select(),
+        ))
+        .load(conn)?;
+    for (reminder, note, user) in &reminders {
+        notifications::notify(
+            conn,
+            user,
+            &NewNotification {
+                user_id: user.id,
+                kind: NotificationKind::Reminder,
+                note_id: Some(note.id),
+                data: json!({
+                    "note_title": note.title,
+                    "remind_at": reminder.remind_at,
+                }),
+            },
+            Email::Reminder {
+                name: user.name.clone(),
+                note_title: note.title.clone(),
+                link: format!("{public_url}/notes/{}", note.id),
+                remind_at: reminder.remind_at,
+            },
+        )?;
+    }
+    Ok(due.len())
+}
@@ -0,0 +1,373 @@
+//! The part of RFC 5545 recurrence rules that reminders support: daily,
+//! weekly, monthly and yearly rules with an interval, weekdays, a count or
+//! an end, and the local time the reminder goes off at.
+//!
+//! Occurrences are computed in the owner's time zone, so a reminder at 9:00
+//! stays at 9:00 across daylight saving changes. A rule always describes
+//! what is left of the series from the current occurrence on: completing an
+//! occurrence lowers `COUNT` by one.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, Days, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+    TimeZone, Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+/// Upper bound for `INTERVAL` and `COUNT`.
+const MAX_VALUE: u32 = 1000;
+/// Candidates tried before a rule counts as having no further occurrence,
+/// e.g. the 31st every other month starting in February.
+const MAX_CANDIDATES: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    fn as_str(self) -> &'static str {
+        match self {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    /// The last local day occurrences may fall on.
+    Date(NaiveDate),
+    Time(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub freq: Frequency,
+    pub interval: u32,
+    /// Only for daily and weekly rules. Empty means the weekday of the
+    /// current occurrence.
+    pub by_day: Vec<Weekday>,
+    /// Local time of day; that of the current occurrence when missing.
+    pub time: Option<NaiveTime>,
+    /// Occurrences left, including the current one.
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid recurrence rule: {0}")]
+pub struct InvalidRecurrence(String);
+
+fn invalid(message: impl Into<String>) -> InvalidRecurrence {
+    InvalidRecurrence(message.into())
+}
+
+impl Recurrence {
+    pub fn new(freq: Frequency) -> Self {
+        Self {
+            freq,
+            interval: 1,
+            by_day: Vec::new(),
+            time: None,
+            count: None,
+            until: None,
+        }
+    }
+
+    /// Fixes the time of day to that of `first` unless the rule sets one,
+    /// so occurrences don't drift when one lands in a daylight saving gap.
+    pub fn anchored(mut self, first: DateTime<Utc>, tz: Tz) -> Self {
+        if self.time.is_none() {
+            let local = first.with_timezone(&tz);
+            self.time = NaiveTime::from_hms_opt(local.hour(), local.minute(), 0);
+        }
+        self
+    }
+
+    /// The occurrence after `current` and the rule for the rest of the
+    /// series from there, or `None` once the series has ended.
+    pub fn next(&self, current: DateTime<Utc>, tz: Tz) -> Option<(DateTime<Utc>, Recurrence)> {
+        let count = match self.count {
+            Some(0 | 1) => return None,
+            Some(count) => Some(count - 1),
+            None => None,
+        };
+        let local = current.with_timezone(&tz).naive_local();
+        let time = self
+            .time
+            .unwrap_or_else(|| local.time().with_nanosecond(0).unwrap_or(local.time()));
+
+        let (date, next) = self
+            .dates_after(local.date())
+            .take(MAX_CANDIDATES)
+            .map(|date| (date, resolve(tz, date.and_time(time))))
+            .find(|&(_, next)| next > current)?;
+        let within = match self.until {
+            None => true,
+            Some(Until::Date(until)) => date <= until,
+            Some(Until::Time(until)) => next <= until,
+        };
+        within.then(|| {
+            (
+                next,
+                Recurrence {
+                    count,
+                    ..self.clone()
+                },
+            )
+        })
+    }
+
+    /// The first occurrence after `now`, skipping the ones missed since
+    /// `current`, with the rule for the rest of the series from there.
+    pub fn next_after(
+        &self,
+        current: DateTime<Utc>,
+        now: DateTime<Utc>,
+        tz: Tz,
+    ) -> Option<(DateTime<Utc>, Recurrence)> {
+        let mut next = self.next(current, tz)?;
+        while next.0 <= now {
+            next = next.1.next(next.0, tz)?;
+        }
+        Some(next)
+    }
+
+    /// Days after `start` that match the rule, in order.
+    fn dates_after(&self, start: NaiveDate) -> Box<dyn Iterator<Item = NaiveDate> + '_> {
+        let interval = self.interval.max(1);
+        match self.freq {
+            Frequency::Daily => Box::new(
+                (1..)
+                    .map_while(move |step: u64| {
+                        start.checked_add_days(Days::new(step * u64::from(interval)))
+                    })
+                    .filter(|date| self.by_day.is_empty() || self.by_day.contains(&date.weekday())),
+            ),
+            Frequency::Weekly if self.by_day.is_empty() => {
+                Box::new((1..).map_while(move |step: u64| {
+                    start.checked_add_days(Days::new(step * 7 * u64::from(interval)))
+                }))
+            }
+            Frequency::Weekly => {
+                let week = week_start(start);
+                Box::new(
+                    (1..)
+                        .map_while(move |step| start.checked_add_days(Days::new(step)))
+                        .filter(move |date| {
+                            let weeks = (week_start(*date) - week).num_days() / 7;
+                            weeks % i64::from(interval) == 0
+                                && self.by_day.contains(&date.weekday())
+                        }),
+                )
+            }
+            Frequency::Monthly | Frequency::Yearly => {
+                let months = if self.freq == Frequency::Yearly {
+                    interval * 12
+                } else {
+                    interval
+                };
+                let first = start - Days::new(u64::from(start.day0()));
+                let day = start.day();
+                // Months without the day are skipped rather than clamped,
+                // as RFC 5545 asks.
+                Box::new(
+                    (1..)
+                        .map_while(move |step: u32| {
+                            first.checked_add_months(Months::new(step.checked_mul(months)?))
+                        })
+                        .filter_map(move |month| month.with_day(day)),
+                )
+            }
+        }
+    }
+}
+
+/// Monday of the week `date` is in.
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(u64::from(date.weekday().num_days_from_monday()))
+}
+
+/// Turns a local time into an instant. Times in a daylight saving gap move
+/// forward by the gap, the earlier of two ambiguous times is used.
+pub(super) fn resolve(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.with_timezone(&Utc),
+        LocalResult::None => {
+            let shifted = local + TimeDelta::hours(1);
+            tz.from_local_datetime(&shifted)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_else(|| shifted.and_utc())
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = InvalidRecurrence;
+
+    /// Accepts `daily`, `weekly`, `monthly` and `yearly` as well as RRULE
+    /// values, with or without the `RRULE:` prefix.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        match value.to_ascii_lowercase().as_str() {
+            "daily" => return Ok(Recurrence::new(Frequency::Daily)),
+            "weekly" => return Ok(Recurrence::new(Frequency::Weekly)),
+            "monthly" => return Ok(Recurrence::new(Frequency::Monthly)),
+            "yearly" => return Ok(Recurrence::new(Frequency::Yearly)),
+            _ => {}
+        }
+        let rule = value
+            .strip_prefix("RRULE:")
+            .or_else(|| value.strip_prefix("rrule:"))
+            .unwrap_or(value);
+
+        let mut freq = None;
+        let mut interval = None;
+        let mut by_day = None;
+        let mut hour = None;
+        let mut minute = None;
+        let mut count = None;
+        let mut until = None;
+        let mut seen = Vec::new();
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected KEY=VALUE, got {part:?}")))?;
+            let key = key.to_ascii_uppercase();
+            let value = value.to_ascii_uppercase();
+            if seen.contains(&key) {
+                return Err(invalid(format!("{key} is given twice")));
+            }
+            match key.as_str() {
+                "FREQ" => {
+                    freq = Some(match value.as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(invalid("FREQ must be DAILY, WEEKLY, MONTHLY or YEARLY")),
+                    })
+                }
+                "INTERVAL" => interval = Some(number(&key, &value, 1, MAX_VALUE)?),
+                "COUNT" => count = Some(number(&key, &value, 1, MAX_VALUE)?),
+                "BYHOUR" => hour = Some(number(&key, &value, 0, 23)?),
+                "BYMINUTE" => minute = Some(number(&key, &value, 0, 59)?),
+                "BYDAY" => {
+                    let mut days = value
+                        .split(',')
+                        .map(weekday)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    days.sort_by_key(|day| day.num_days_from_monday());
+                    days.dedup();
+                    by_day = Some(days);
+                }
+                "UNTIL" => until = Some(parse_until(&value)?),
+                // The only week start the weekly rules implement.
+                "WKST" if value == "MO" => {}
+                _ => return Err(invalid(format!("{key}={value} is not supported"))),
+            }
+            seen.push(key);
+        }
+
+        let freq = freq.ok_or_else(|| invalid("FREQ is missing"))?;
+        if count.is_some() && until.is_some() {
+            return Err(invalid("COUNT and UNTIL cannot both be given"));
+        }
+        let by_day = by_day.unwrap_or_default();
+        if !by_day.is_empty() && !matches!(freq, Frequency::Daily | Frequency::Weekly) {
+            return Err(invalid("BYDAY is only supported with DAILY and WEEKLY"));
+        }
+        let time = match (hour, minute) {
+            (None, None) => None,
+            (Some(hour), Some(minute)) => NaiveTime::from_hms_opt(hour, minute, 0),
+            _ => return Err(invalid("BYHOUR and BYMINUTE must be given together")),
+        };
+        Ok(Recurrence {
+            freq,
+            interval: interval.unwrap_or(1),
+            by_day,
+            time,
+            count,
+            until,
+        })
+    }
+}
+
+fn number(key: &str, value: &str, min: u32, max: u32) -> Result<u32, InvalidRecurrence> {
+    value
+        .parse()
+        .ok()
+        .filter(|number| (min..=max).contains(number))
+        .ok_or_else(|| invalid(format!("{key} must be a number from {min} to {max}")))
+}
+
+fn weekday(value: &str) -> Result<Weekday, InvalidRecurrence> {
+    Ok(match value {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return Err(invalid(format!("BYDAY {value:?} is not a weekday like MO"))),
+    })
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn parse_until(value: &str) -> Result<Until, InvalidRecurrence> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok(Until::Date(date));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+        .map(|time| Until::Time(time.and_utc()))
+        .map_err(|_| {
+            invalid("UNTIL must be a date like 20261231 or a UTC time like 20261231T235959Z")
+        })
+}
+
+/// The canonical RRULE value, without the `RRULE:` prefix.
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FREQ={}", self.freq.as_str())?;
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<_> = self.by_day.iter().map(|&day| weekday_code(day)).collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(time) = self.time {
+            write!(f, ";BYHOUR={};BYMINUTE={}", time.hour(), time.minute())?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        match self.until {
+            Some(Until::Date(date)) => write!(f, ";UNTIL={}", date.format("%Y%m%d")),
+            Some(Until::Time(time)) => write!(f, ";UNTIL={}", time.format("%Y%m%dT%H%M%SZ")),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use axum::extract::State;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, post, put};
+use axum::Router;
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::account::{deletion, email_change, export};
+use crate::auth::{self, password, session, AuthUser};
+use crate::calendar;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path, Query};
+use crate::models::account_export::ExportStatusResponse;
+use crate::models::session::{Session, SessionInfo};
+use crate::models::user::{validate_time_zone, Locale, User, UserProfile};
+use crate::schema::{sessions, users};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(show_account)
+                .patch(update_account)
+                .delete(delete_account),
+        )
+        .route("/password", put(change_password))
+        .route("/email", post(change_email))
+        .route("/email/confirm", post(confirm_email))
+        .route("/email/cancel", post(cancel_email_change))
+        .route(
+            "/calendar",
+            post(create_calendar_feed).delete(revoke_calendar_feed),
+        )
+        .route("/export", post(request_export))
+        .route("/export/:id", get(download_export))
+        .route(
+            "/sessions",
+            get(list_sessions).delete(revoke_other_sessions),
+        )
+        .route("/sessions/:id", delete(revoke_session))
+}
+
+async fn show_account(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> AppResult<Json<UserProfile>> {
+    let account = db::run(&state.pool, move |conn| {
+        users::table
+            .find(user.id)
+            .select(User::as_select())
+            .first(conn)
+    })
+    .await?;
+    Ok(Json(UserProfile::from(&account)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct UpdateAccountRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: Option<String>,
+    /// Language of the emails sent to the account.
+    locale: Option<Locale>,
+    /// IANA name. Times in emails and recurring reminders follow it.
+    #[validate(custom(function = "validate_time_zone"))]
+    time_zone: Option<String>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = users)]
+struct AccountChanges {
+    name: Option<String>,
+    locale: Option<Locale>,
+    time_zone: Option<String>,
+}
+
+async fn update_account(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<UpdateAccountRequest>,
+) -> AppResult<Json<UserProfile>> {
+    payload.validate()?;
+    let changes = AccountChanges {
+        name: payload.name.map(|name| name.trim().to_string()),
+        locale: payload.locale,
+        time_zone: payload.time_zone,
+    };
+    let account = db::run(&state.pool, move |conn| {
+        if changes.name.is_none() && changes.locale.is_none() && changes.time_zone.is_none() {
+            return users::table
+                .find(user.id)
+                .select(User::as_select())
+                .first(conn);
+        }
+        diesel::update(users::table.find(user.id))
+            .set(&changes)
+            .returning(User::as_returning())
+            .get_result(conn)
+    })
+    .await?;
+    Ok(Json(UserProfile::from(&account)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+/// Sets a new password and logs out every other session.
+async fn change_password(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> AppResult<StatusCode> {
+    let account = db::run(&state.pool, move |conn| {
+        users::table
+            .find(user.id)
+            .select(User::as_select())
+            .first(conn)
+    })
+    .await?;
+    let current_password = payload.current_password;
+    let current_hash = account.password.clone();
+    let current_ok =
+        tokio::task::spawn_blocking(move || password::verify(&current_password, &current_hash))
+            .await?;
+    if !current_ok {
+        return Err(AppError::Forbidden("password is incorrect".into()));
+    }
+
+    let new_hash = state
+        .password_policy
+        .hash_new(
+            state.password_params,
+            payload.new_password,
+            vec![account.name, account.email],
+            "new_password",
+        )
+        .await?;
+    db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            diesel::update(users::table.find(user.id))
+                .set(users::password.eq(new_hash))
+                .execute(conn)?;
+            session::revoke_all(conn, user.id, Some(user.session_id))
+        })
+    })
+    .await?;
+    tracing::info!(user_id = %user.id, "password changed");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ChangeEmailRequest {
+    #[validate(email, length(max = 255))]
+    new_email: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PendingEmailChangeResponse {
+    new_email: String,
+    expires_at: DateTime<Utc>,
+}
+
+async fn change_email(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> AppResult<(StatusCode, Json<PendingEmailChangeResponse>)> {
+    payload.validate()?;
+    let change = email_change::request(
+        &state,
+        user.id,
+        payload.new_email.trim().to_string(),
+        payload.password,
+    )
+    .await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(PendingEmailChangeResponse {
+            new_email: change.new_email,
+            expires_at: change.expires_at,
+        }),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct EmailLinkRequest {
+    token: String,
+}
+
+/// Called by the page linked from the email sent to the new address, so no
+/// login is needed.
+async fn confirm_email(
+    State(state): State<AppState>,
+    Json(payload): Json<EmailLinkRequest>,
+) -> AppResult<Json<UserProfile>> {
+    let user = email_change::confirm(&state, &payload.token).await?;
+    Ok(Json(UserProfile::from(&user)))
+}
+
+/// Called by the page linked from the notice sent to the old address.
+async fn cancel_email_change(
+    State(state): State<AppState>,
+    Json(payload): Json<EmailLinkRequest>,
+) -> AppResult<StatusCode> {
+    email_change::cancel(&state, &payload.token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The plain feed URL is only ever returned here.
+#[derive(Debug, Serialize)]
+struct CalendarFeed {
+    url: String,
+}
+
+/// Creates the calendar feed URL, revoking the one created before.
+async fn create_calendar_feed(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> AppResult<Json<CalendarFeed>> {
+    let feed_token = db::run(&state.pool, move |conn| {
+        calendar::regenerate_token(conn, user.id)
+    })
+    .await?;
+    Ok(Json(CalendarFeed {
+        url: calendar::url(&state.public_url, &feed_token),
+    }))
+}
+
+async fn revoke_calendar_feed(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> AppResult<StatusCode> {
+    db::run(&state.pool, move |conn| {
+        calendar::revoke_token(conn, user.id)
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn request_export(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> AppResult<(StatusCode, Json<ExportStatusResponse>)> {
+    let export = export::request(&state, user.id).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ExportStatusResponse::from(&export)),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadQuery {
+    token: String,
+}
+
+async fn download_export(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DownloadQuery>,
+) -> AppResult<impl IntoResponse> {
+    let archive = export::download(&state, user.id, id, &query.token).await?;
+    let file_name = format!("notesapp-export-{}.json", Utc::now().format("%Y-%m-%d"));
+    Ok((
+        [
+            (CONTENT_TYPE, "application/json".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        archive,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteAccountRequest {
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteAccountResponse {
+    deletion_scheduled_at: DateTime<Utc>,
+}
+
+async fn delete_account(
+    State(state): State<AppState>,
+    user: AuthUser,
+    jar: CookieJar,
+    Json(payload): Json<DeleteAccountRequest>,
+) -> AppResult<(StatusCode, CookieJar, Json<DeleteAccountResponse>)> {
+    let deletion_scheduled_at = deletion::schedule(&state, user.id, payload.password).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        auth::remove_cookies(jar),
+        Json(DeleteAccountResponse {
+            deletion_scheduled_at,
+        }),
+    ))
+}
+
+/// Active sessions, most recently used first.
+async fn list_sessions(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> AppResult<Json<Vec<SessionInfo>>> {
+    let active = db::run(&state.pool, move |conn| {
+        sessions::table
+            .filter(sessions::user_id.eq(user.id))
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(Utc::now()))
+            .order(sessions::last_seen_at.desc())
+            .select(Session::as_select())
+            .load(conn)
+    })
+    .await?;
+
+    Ok(Json(
+        active
+            .into_iter()
+            .map(|session| SessionInfo {
+                current: session.id == user.session_id,
+                ..SessionInfo::from(session)
+            })
+            .collect(),
+    ))
+}
+
+async fn revoke_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let revoked = db::run(&state.pool, move |conn| session::revoke(conn, user.id, id)).await?;
+    if !revoked {
+        return Err(AppError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize)]
+struct RevokedSessionsResponse {
+    revoked: usize,
+}
+
+/// Logs out everywhere except the session making the request.
+async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> AppResult<Json<RevokedSessionsResponse>> {
+    let revoked = db::run(&state.pool, move |conn| {
+        session::revoke_all(conn, user.id, Some(user.session_id))
+    })
+    .await?;
+    Ok(Json(RevokedSessionsResponse { revoked }))
+}
@@ -0,0 +1,119 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::Router;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::{api_token, AuthUser};
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path};
+use crate::models::api_token::{ApiToken, ApiTokenInfo, ApiTokenScope, NewApiToken};
+use crate::models::user::UserRole;
+use crate::schema::api_tokens;
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:id", delete(revoke))
+}
+
+async fn list(State(state): State<AppState>, user: AuthUser) -> AppResult<Json<Vec<ApiTokenInfo>>> {
+    let tokens = db::run(&state.pool, move |conn| {
+        api_tokens::table
+            .filter(api_tokens::user_id.eq(user.id))
+            .order(api_tokens::created_at.desc())
+            .select(ApiToken::as_select())
+            .load(conn)
+    })
+    .await?;
+
+    Ok(Json(tokens.into_iter().map(ApiTokenInfo::from).collect()))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct CreateTokenRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(length(min = 1))]
+    scopes: Vec<ApiTokenScope>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The plain token is only ever returned here.
+#[derive(Debug, Serialize)]
+struct CreatedToken {
+    #[serde(flatten)]
+    info: ApiTokenInfo,
+    token: String,
+}
+
+async fn create(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<CreateTokenRequest>,
+) -> AppResult<(StatusCode, Json<CreatedToken>)> {
+    payload.validate()?;
+    if payload.scopes.contains(&ApiTokenScope::Admin) && user.role != UserRole::Admin {
+        return Err(AppError::Forbidden(
+            "only admins can create tokens with the admin scope".into(),
+        ));
+    }
+    if payload
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".into(),
+        ));
+    }
+
+    let mut scopes = payload.scopes;
+    scopes.sort_by_key(ApiTokenScope::as_str);
+    scopes.dedup();
+    let (token, token_hash) = api_token::generate();
+    let created = db::run(&state.pool, move |conn| {
+        diesel::insert_into(api_tokens::table)
+            .values(NewApiToken {
+                user_id: user.id,
+                name: payload.name.trim().to_string(),
+                token_hash,
+                scopes,
+                expires_at: payload.expires_at,
+            })
+            .returning(ApiToken::as_returning())
+            .get_result(conn)
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedToken {
+            info: ApiTokenInfo::from(created),
+            token,
+        }),
+    ))
+}
+
+async fn revoke(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let deleted = db::run(&state.pool, move |conn| {
+        diesel::delete(api_tokens::table.find(id))
+            .filter(api_tokens::user_id.eq(user.id))
+            .execute(conn)
+    })
+    .await?;
+
+    if deleted == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
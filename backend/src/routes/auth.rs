@@ -0,0 +1,494 @@
+use axum::extract::State;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::Router;
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::account::{deletion, password_reset, registration};
+use crate::auth::lockout;
+use crate::auth::session::{self, Origin};
+use crate::auth::{self, password, two_factor, PartialAuthUser};
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Query};
+use crate::mail::{self, Email};
+use crate::models::login_event::NewLoginEvent;
+use crate::models::session::Session;
+use crate::models::user::{validate_time_zone, Locale, User, UserProfile, UserRole};
+use crate::rate_limit;
+use crate::schema::{login_events, users};
+use crate::state::AppState;
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/login",
+            post(login).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit::limit_login,
+            )),
+        )
+        .route("/register", post(register))
+        .route("/verify", get(verify_email))
+        .route(
+            "/password/forgot",
+            post(forgot_password).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit::limit_password_reset,
+            )),
+        )
+        .route("/password/reset", post(reset_password))
+        .route(
+            "/login/2fa",
+            post(login_two_factor).layer(middleware::from_fn_with_state(
+                state,
+                rate_limit::limit_two_factor,
+            )),
+        )
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct RegisterRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(email, length(max = 255))]
+    email: String,
+    /// Checked against the password policy.
+    password: String,
+    /// Language of the emails; taken from `Accept-Language` if missing.
+    locale: Option<Locale>,
+    /// IANA name; UTC if missing.
+    #[validate(custom(function = "validate_time_zone"))]
+    time_zone: Option<String>,
+}
+
+async fn register(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<RegisterRequest>,
+) -> AppResult<(StatusCode, Json<UserProfile>)> {
+    payload.validate()?;
+    let locale = payload
+        .locale
+        .or_else(|| {
+            headers
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Locale::from_accept_language)
+        })
+        .unwrap_or_default();
+    let user = registration::register(
+        &state,
+        payload.name.trim().to_string(),
+        payload.email.trim().to_string(),
+        payload.password,
+        locale,
+        payload.time_zone.unwrap_or_else(|| "UTC".into()),
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(UserProfile::from(&user))))
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkQuery {
+    token: String,
+}
+
+/// Opened from the link in the verification email.
+async fn verify_email(
+    State(state): State<AppState>,
+    Query(query): Query<LinkQuery>,
+) -> AppResult<Json<UserProfile>> {
+    let user = registration::verify(&state, &query.token).await?;
+    Ok(Json(UserProfile::from(&user)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ForgotPasswordRequest {
+    #[validate(email)]
+    email: String,
+}
+
+/// Always accepted, so the response does not reveal which addresses have
+/// an account.
+async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> AppResult<StatusCode> {
+    payload.validate()?;
+    password_reset::request(&state, payload.email.trim().to_string()).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetPasswordRequest {
+    token: String,
+    password: String,
+}
+
+async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> AppResult<StatusCode> {
+    password_reset::reset(&state, &payload.token, payload.password).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct LoginRequest {
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(min = 1))]
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(UserProfile),
+    TwoFactorRequired {
+        two_factor_required: bool,
+        challenge_token: String,
+    },
+}
+
+/// Result of checking the password.
+enum PasswordStep {
+    Failed,
+    TwoFactorRequired,
+    SignedIn(Session, String),
+}
+
+async fn login(
+    State(state): State<AppState>,
+    origin: Origin,
+    jar: CookieJar,
+    Json(payload): Json<LoginRequest>,
+) -> AppResult<(CookieJar, Json<LoginResponse>)> {
+    payload.validate()?;
+
+    let session_ttl = state.session_ttl;
+    let password_params = state.password_params;
+    let (user, step) = db::run(&state.pool, move |conn| {
+        let mut user = users::table
+            .filter(users::email.eq(&payload.email))
+            .select(User::as_select())
+            .first(conn)
+            .optional()?
+            .ok_or(AppError::InvalidCredentials)?;
+        lockout::ensure_unlocked(&user)?;
+
+        if !password::verify(&payload.password, &user.password) {
+            record_login(conn, user.id, &origin, false)?;
+            return Ok((user, PasswordStep::Failed));
+        }
+        upgrade_hash(conn, &mut user, &payload.password, &password_params);
+        // With two-factor enabled the login is only recorded after the second step.
+        if user.two_factor_enabled() {
+            return Ok((user, PasswordStep::TwoFactorRequired));
+        }
+        record_login(conn, user.id, &origin, true)?;
+        let (session, refresh_token) =
+            complete_login(conn, &mut user, false, &origin, session_ttl)?;
+        Ok::<_, AppError>((user, PasswordStep::SignedIn(session, refresh_token)))
+    })
+    .await?;
+
+    match step {
+        PasswordStep::Failed => Err(reject(&state, user, AppError::InvalidCredentials).await),
+        PasswordStep::TwoFactorRequired => {
+            let challenge_token = state
+                .jwt
+                .issue_challenge(user.id)
+                .map_err(AppError::internal)?;
+            Ok((
+                jar,
+                Json(LoginResponse::TwoFactorRequired {
+                    two_factor_required: true,
+                    challenge_token,
+                }),
+            ))
+        }
+        PasswordStep::SignedIn(session, refresh_token) => {
+            let jar = sign_in(&state, jar, &session, user.role, refresh_token)?;
+            Ok((
+                jar,
+                Json(LoginResponse::Authenticated(UserProfile::from(&user))),
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    /// Taken from the challenge cookie when missing, as after a login
+    /// through the identity provider.
+    pub challenge_token: Option<String>,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+async fn login_two_factor(
+    State(state): State<AppState>,
+    origin: Origin,
+    jar: CookieJar,
+    Json(payload): Json<TwoFactorLoginRequest>,
+) -> AppResult<(CookieJar, Json<UserProfile>)> {
+    let user_id = payload
+        .challenge_token
+        .as_deref()
+        .or_else(|| jar.get(auth::CHALLENGE_COOKIE).map(|cookie| cookie.value()))
+        .and_then(|token| state.jwt.verify_challenge(token))
+        .ok_or(AppError::Unauthorized)?;
+
+    let session_ttl = state.session_ttl;
+    let (user, signed_in) = db::run(&state.pool, move |conn| {
+        let mut user: User = users::table
+            .find(user_id)
+            .select(User::as_select())
+            .first(conn)?;
+        lockout::ensure_unlocked(&user)?;
+        let Some(secret) = user
+            .totp_secret
+            .clone()
+            .filter(|_| user.two_factor_enabled())
+        else {
+            return Err(AppError::Unauthorized);
+        };
+
+        let verified = match (&payload.code, &payload.recovery_code) {
+            (Some(code), _) => match two_factor::verify_code(&secret, code, user.totp_last_step)? {
+                Some(step) => two_factor::use_step(conn, user.id, step)?,
+                None => false,
+            },
+            (None, Some(recovery_code)) => {
+                two_factor::consume_recovery_code(conn, user.id, recovery_code)?
+            }
+            (None, None) => {
+                return Err(AppError::BadRequest(
+                    "either code or recovery_code is required".into(),
+                ))
+            }
+        };
+
+        record_login(conn, user.id, &origin, verified)?;
+        if !verified {
+            return Ok((user, None));
+        }
+        let signed_in = complete_login(conn, &mut user, true, &origin, session_ttl)?;
+        Ok((user, Some(signed_in)))
+    })
+    .await?;
+
+    let Some((session, refresh_token)) = signed_in else {
+        return Err(reject(&state, user, AppError::InvalidTwoFactorCode).await);
+    };
+    let jar = auth::remove_challenge_cookie(jar);
+    let jar = sign_in(&state, jar, &session, user.role, refresh_token)?;
+    Ok((jar, Json(UserProfile::from(&user))))
+}
+
+/// Trades the refresh token cookie for a new access token. The refresh token
+/// is rotated on every call.
+async fn refresh(
+    State(state): State<AppState>,
+    origin: Origin,
+    jar: CookieJar,
+) -> AppResult<(CookieJar, StatusCode)> {
+    let refresh_token = jar
+        .get(auth::REFRESH_TOKEN_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .ok_or(AppError::Unauthorized)?;
+
+    let session_ttl = state.session_ttl;
+    let (session, refresh_token, role) = db::run(&state.pool, move |conn| {
+        let (session, refresh_token) = session::rotate(conn, &refresh_token, &origin, session_ttl)?;
+        let role = users::table
+            .find(session.user_id)
+            .select(users::role)
+            .first(conn)?;
+        Ok::<_, AppError>((session, refresh_token, role))
+    })
+    .await?;
+
+    let jar = sign_in(&state, jar, &session, role, refresh_token)?;
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+/// Ends the current session. Works with either token, so a client whose
+/// access token has already expired can still log out.
+async fn logout(
+    State(state): State<AppState>,
+    auth: Option<PartialAuthUser>,
+    jar: CookieJar,
+) -> AppResult<(CookieJar, StatusCode)> {
+    let refresh_token = jar
+        .get(auth::REFRESH_TOKEN_COOKIE)
+        .map(|cookie| cookie.value().to_owned());
+
+    db::run(&state.pool, move |conn| {
+        let session = match (auth, refresh_token) {
+            (Some(PartialAuthUser(auth)), _) => Some((auth.id, auth.session_id)),
+            (None, Some(refresh_token)) => session::find_by_refresh_token(conn, &refresh_token)?
+                .map(|session| (session.user_id, session.id)),
+            (None, None) => None,
+        };
+        if let Some((user_id, session_id)) = session {
+            session::revoke(conn, user_id, session_id)?;
+        }
+        Ok::<_, AppError>(())
+    })
+    .await?;
+
+    Ok((auth::remove_cookies(jar), StatusCode::NO_CONTENT))
+}
+
+pub(super) fn record_login(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    origin: &Origin,
+    success: bool,
+) -> QueryResult<()> {
+    diesel::insert_into(login_events::table)
+        .values(NewLoginEvent {
+            user_id,
+            success,
+            ip_address: origin.ip_address.clone(),
+            user_agent: origin.user_agent.clone(),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Rehashes the password if its hash predates the configured argon2
+/// parameters. Failing to do so does not fail the login.
+fn upgrade_hash(
+    conn: &mut PgConnection,
+    user: &mut User,
+    plain: &str,
+    params: &password::HashParams,
+) {
+    if !password::needs_rehash(&user.password, params) {
+        return;
+    }
+    let upgraded = password::hash(plain, params)
+        .map_err(AppError::internal)
+        .and_then(|hash| {
+            // Only replaces the hash that was just verified, in case the
+            // password changed in the meantime.
+            diesel::update(users::table.find(user.id))
+                .filter(users::password.eq(&user.password))
+                .set(users::password.eq(&hash))
+                .execute(conn)?;
+            Ok(hash)
+        });
+    match upgraded {
+        Ok(hash) => {
+            tracing::info!(user_id = %user.id, "upgraded password hash");
+            user.password = hash;
+        }
+        Err(err) => tracing::warn!(user_id = %user.id, "failed to upgrade password hash: {err}"),
+    }
+}
+
+/// Counts a failed attempt towards the lockout and tells the owner if it
+/// locked the account. Returns `error`, or whatever went wrong on the way.
+async fn reject(state: &AppState, user: User, error: AppError) -> AppError {
+    let policy = state.lockout;
+    let user_id = user.id;
+    let locked_until = db::run(&state.pool, move |conn| {
+        let locked_until = lockout::record_failure(conn, user_id, &policy)?;
+        if let Some(locked_until) = locked_until {
+            lockout::notify(conn, &user, locked_until)?;
+        }
+        Ok::<_, AppError>(locked_until)
+    })
+    .await;
+    match locked_until {
+        Ok(Some(locked_until)) => {
+            tracing::warn!(%user_id, %locked_until, "account locked after failed logins");
+            error
+        }
+        Ok(None) => error,
+        Err(err) => err,
+    }
+}
+
+/// Bookkeeping for a fully authenticated login. Starts the session.
+pub(super) fn complete_login(
+    conn: &mut PgConnection,
+    user: &mut User,
+    mfa: bool,
+    origin: &Origin,
+    session_ttl: chrono::Duration,
+) -> QueryResult<(Session, String)> {
+    lockout::reset(conn, user)?;
+    alert_new_device(conn, user, origin)?;
+    if deletion::cancel(conn, user.id)? {
+        tracing::info!(user_id = %user.id, "account deletion cancelled by login");
+        user.deletion_scheduled_at = None;
+    }
+    session::create(conn, user.id, mfa, origin, session_ttl)
+}
+
+/// Emails the owner about a login from an IP address and browser that
+/// never logged in to the account before. Expects the login to be recorded
+/// already. The first login of an account is not worth an alert.
+fn alert_new_device(conn: &mut PgConnection, user: &User, origin: &Origin) -> QueryResult<()> {
+    let logins = login_events::table
+        .filter(login_events::user_id.eq(user.id))
+        .filter(login_events::success.eq(true));
+    let total: i64 = logins.count().get_result(conn)?;
+    let from_device: i64 = logins
+        .filter(login_events::ip_address.is_not_distinct_from(origin.ip_address.as_deref()))
+        .filter(login_events::user_agent.is_not_distinct_from(origin.user_agent.as_deref()))
+        .count()
+        .get_result(conn)?;
+    if total <= 1 || from_device > 1 {
+        return Ok(());
+    }
+
+    mail::enqueue(
+        conn,
+        &user.email,
+        user.locale,
+        user.tz(),
+        Email::LoginAlert {
+            name: user.name.clone(),
+            time: Utc::now(),
+            ip_address: origin.ip_address.clone(),
+            user_agent: origin.user_agent.clone(),
+        },
+    )?;
+    Ok(())
+}
+
+pub(super) fn sign_in(
+    state: &AppState,
+    jar: CookieJar,
+    session: &Session,
+    role: UserRole,
+    refresh_token: String,
+) -> AppResult<CookieJar> {
+    let jar = jar.add(auth::refresh_cookie(refresh_token, state));
+    reissue_access_token(state, jar, session, role)
+}
+
+pub(super) fn reissue_access_token(
+    state: &AppState,
+    jar: CookieJar,
+    session: &Session,
+    role: UserRole,
+) -> AppResult<CookieJar> {
+    let token = state.jwt.issue(session, role).map_err(AppError::internal)?;
+    Ok(jar.add(auth::access_cookie(token, state)))
+}
@@ -0,0 +1,41 @@
+use axum::extract::State;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+use crate::calendar;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::Path;
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:file", get(feed))
+}
+
+/// `/cal/{token}.ics`. The token is the only credential, since calendar apps
+/// cannot log in.
+async fn feed(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let feed_token = file
+        .strip_suffix(".ics")
+        .ok_or(AppError::NotFound)?
+        .to_string();
+    let public_url = state.public_url.clone();
+    let calendar = db::run(&state.pool, move |conn| {
+        calendar::feed(conn, &feed_token, &public_url)
+    })
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "text/calendar; charset=utf-8"),
+            (CACHE_CONTROL, "private, no-cache"),
+        ],
+        calendar,
+    ))
+}
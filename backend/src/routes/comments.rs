@@ -0,0 +1,233 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, patch, post};
+use axum::Router;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::auth::ApiUser;
+use crate::comments::{self, Draft};
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path, Query};
+use crate::models::api_token::ApiTokenScope;
+use crate::models::comment::NoteComment;
+use crate::models::user::User;
+use crate::schema::users;
+use crate::shares::{self, Access};
+use crate::state::AppState;
+
+/// Nested under `/api/notes` next to the notes themselves.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/comments", get(list).post(create))
+        .route("/:id/comments/:comment_id", patch(update).delete(remove))
+        .route("/:id/comments/:comment_id/resolve", post(resolve))
+        .route("/:id/comments/:comment_id/unresolve", post(unresolve))
+}
+
+#[derive(Debug, Serialize)]
+struct Thread {
+    #[serde(flatten)]
+    comment: NoteComment,
+    replies: Vec<NoteComment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    /// Only resolved or only open threads.
+    resolved: Option<bool>,
+}
+
+/// Threads in the order they were started, each with its replies.
+async fn list(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ListQuery>,
+) -> AppResult<Json<Vec<Thread>>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let comments = db::run(&state.pool, move |conn| {
+        let note = shares::note(conn, user.id, id, Access::Read)?;
+        Ok::<_, AppError>(comments::list(conn, note.id)?)
+    })
+    .await?;
+
+    let (first, replies): (Vec<_>, Vec<_>) = comments
+        .into_iter()
+        .partition(|comment| comment.parent_id.is_none());
+    let mut threads: Vec<Thread> = first
+        .into_iter()
+        .filter(|comment| {
+            query
+                .resolved
+                .is_none_or(|resolved| resolved == comment.resolved_at.is_some())
+        })
+        .map(|comment| Thread {
+            comment,
+            replies: Vec::new(),
+        })
+        .collect();
+    for reply in replies {
+        if let Some(thread) = threads
+            .iter_mut()
+            .find(|thread| Some(thread.comment.id) == reply.parent_id)
+        {
+            thread.replies.push(reply);
+        }
+    }
+
+    Ok(Json(threads))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct CreateCommentRequest {
+    /// Any comment of the thread to reply to.
+    parent_id: Option<Uuid>,
+    #[validate(custom(function = "validate_body"))]
+    body: String,
+    /// Characters of the note's content that a new thread is about.
+    anchor_start: Option<usize>,
+    anchor_end: Option<usize>,
+}
+
+async fn create(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CreateCommentRequest>,
+) -> AppResult<(StatusCode, Json<NoteComment>)> {
+    user.require(ApiTokenScope::NotesWrite)?;
+    payload.validate()?;
+    let anchor = match (payload.anchor_start, payload.anchor_end) {
+        (Some(start), Some(end)) => Some(start..end),
+        (None, None) => None,
+        _ => {
+            return Err(AppError::BadRequest(
+                "give both anchor_start and anchor_end".into(),
+            ))
+        }
+    };
+    let draft = Draft {
+        reply_to: payload.parent_id,
+        body: payload.body,
+        anchor,
+    };
+
+    let public_url = state.public_url.clone();
+    let comment = db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let note = shares::note(conn, user.id, id, Access::Comment)?;
+            let author = find_user(conn, user.id)?;
+            comments::create(conn, &note, &author, draft, &public_url)
+        })
+    })
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct UpdateCommentRequest {
+    #[validate(custom(function = "validate_body"))]
+    body: String,
+}
+
+async fn update(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path((id, comment_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateCommentRequest>,
+) -> AppResult<Json<NoteComment>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+    payload.validate()?;
+
+    let public_url = state.public_url.clone();
+    let comment = db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let note = shares::note(conn, user.id, id, Access::Comment)?;
+            let comment = comments::find(conn, note.id, comment_id)?;
+            let author = find_user(conn, user.id)?;
+            comments::edit(conn, &note, &comment, &author, payload.body, &public_url)
+        })
+    })
+    .await?;
+
+    Ok(Json(comment))
+}
+
+async fn remove(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path((id, comment_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    db::run(&state.pool, move |conn| {
+        let note = shares::note(conn, user.id, id, Access::Comment)?;
+        let comment = comments::find(conn, note.id, comment_id)?;
+        comments::delete(conn, &comment, user.id)
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Resolves the thread started by the comment.
+async fn resolve(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path((id, comment_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<Json<NoteComment>> {
+    set_resolved(state, user, id, comment_id, true).await
+}
+
+async fn unresolve(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path((id, comment_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<Json<NoteComment>> {
+    set_resolved(state, user, id, comment_id, false).await
+}
+
+async fn set_resolved(
+    state: AppState,
+    user: ApiUser,
+    id: Uuid,
+    comment_id: Uuid,
+    resolved: bool,
+) -> AppResult<Json<NoteComment>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let comment = db::run(&state.pool, move |conn| {
+        let note = shares::note(conn, user.id, id, Access::Comment)?;
+        let comment = comments::find(conn, note.id, comment_id)?;
+        comments::set_resolved(conn, &comment, user.id, resolved)
+    })
+    .await?;
+
+    Ok(Json(comment))
+}
+
+fn find_user(conn: &mut PgConnection, id: Uuid) -> QueryResult<User> {
+    users::table.find(id).select(User::as_select()).first(conn)
+}
+
+const MAX_BODY_CHARS: usize = 10_000;
+
+fn validate_body(body: &str) -> Result<(), ValidationError> {
+    if body.trim().is_empty() {
+        return Err(
+            ValidationError::new("comment_blank").with_message("comments cannot be empty".into())
+        );
+    }
+    if body.chars().count() > MAX_BODY_CHARS {
+        return Err(ValidationError::new("comment_length").with_message(
+            format!("comments can be at most {MAX_BODY_CHARS} characters long").into(),
+        ));
+    }
+    Ok(())
+}
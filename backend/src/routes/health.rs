@@ -0,0 +1,133 @@
+//! Probes for orchestrators and load balancers. `/healthz` only says the
+//! process is up; `/readyz` says whether it should get traffic, with the
+//! state of every dependency. Why a check failed is only logged, as the
+//! probes are public and the errors name internals.
+
+use std::future::Future;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::db;
+use crate::error::AppError;
+use crate::migrations;
+use crate::state::AppState;
+
+/// A dependency that takes longer than this to answer counts as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
+
+async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    status: CheckStatus,
+    /// Failing checks that are not critical are reported without making us
+    /// unready.
+    #[serde(skip)]
+    critical: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CheckStatus {
+    Ok,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Checks {
+    database: Check,
+    migrations: Check,
+    storage: Check,
+    mail: Check,
+}
+
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let (database, migrations, storage, mail) = tokio::join!(
+        run_check("database", true, async {
+            db::health_check(&state.pool).await?;
+            Ok(())
+        }),
+        run_check("migrations", true, async {
+            let pending = db::run(&state.pool, |conn| {
+                migrations::pending(conn).map_err(AppError::internal)
+            })
+            .await?;
+            if pending.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("pending migrations: {}", pending.join(", ")).into())
+            }
+        }),
+        run_check("storage", true, async {
+            let store = state.store.clone();
+            tokio::task::spawn_blocking(move || store.check()).await??;
+            Ok(())
+        }),
+        // Mail is sent by background jobs, which retry; an outage should not
+        // take the API down.
+        run_check("mail", false, async {
+            state.mailer.check().await?;
+            Ok(())
+        }),
+    );
+    let checks = Checks {
+        database,
+        migrations,
+        storage,
+        mail,
+    };
+
+    let shutting_down = state.shutdown.is_triggered();
+    let healthy = [
+        &checks.database,
+        &checks.migrations,
+        &checks.storage,
+        &checks.mail,
+    ]
+    .iter()
+    .all(|check| !check.critical || check.status == CheckStatus::Ok);
+    let (status, label) = match (shutting_down, healthy) {
+        (true, _) => (StatusCode::SERVICE_UNAVAILABLE, "shutting_down"),
+        (false, false) => (StatusCode::SERVICE_UNAVAILABLE, "not_ready"),
+        (false, true) => (StatusCode::OK, "ready"),
+    };
+    (status, Json(json!({ "status": label, "checks": checks })))
+}
+
+type CheckError = Box<dyn std::error::Error + Send + Sync>;
+
+async fn run_check<F>(name: &'static str, critical: bool, check: F) -> Check
+where
+    F: Future<Output = Result<(), CheckError>>,
+{
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err(format!(
+            "no answer within {} seconds",
+            CHECK_TIMEOUT.as_secs()
+        )),
+    };
+    let status = match result {
+        Ok(()) => CheckStatus::Ok,
+        Err(error) => {
+            tracing::warn!(check = name, critical, "readiness check failed: {error}");
+            CheckStatus::Failed
+        }
+    };
+    Check { status, critical }
+}
@@ -0,0 +1,126 @@
+//! Inspecting background jobs and dealing with the ones that failed. Admins
+//! only.
+
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::Router;
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::auth::ApiUser;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path, Query};
+use crate::jobs::{self, BackgroundJob, JobFilter};
+use crate::mail::SendEmail;
+use crate::models::api_token::ApiTokenScope;
+use crate::models::job::{Job, JobStatus};
+use crate::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Kinds whose payload is not shown: emails may contain links that sign
+/// their reader in or change the account.
+const REDACTED_KINDS: [&str; 1] = [SendEmail::KIND];
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list))
+        .route("/stats", get(stats))
+        .route("/:id", get(show).delete(remove))
+        .route("/:id/retry", post(retry))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    status: Option<String>,
+    kind: Option<String>,
+    /// ID of the last job of the previous page.
+    before: Option<Uuid>,
+    limit: Option<i64>,
+}
+
+/// Newest first.
+async fn list(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Query(query): Query<ListQuery>,
+) -> AppResult<Json<Vec<Job>>> {
+    user.require(ApiTokenScope::Admin)?;
+    let status = query
+        .status
+        .map(|status| {
+            JobStatus::parse(&status)
+                .ok_or_else(|| AppError::BadRequest(format!("unknown job status {status:?}")))
+        })
+        .transpose()?;
+
+    let filter = JobFilter {
+        status,
+        kind: query.kind,
+        before: query.before,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    };
+    let jobs = jobs::list(&state, filter).await?;
+    Ok(Json(jobs.into_iter().map(redact).collect()))
+}
+
+/// Number of jobs by kind and status, e.g.
+/// `{"account_export": {"completed": 12, "dead": 1}}`.
+async fn stats(
+    State(state): State<AppState>,
+    user: ApiUser,
+) -> AppResult<Json<BTreeMap<String, BTreeMap<&'static str, i64>>>> {
+    user.require(ApiTokenScope::Admin)?;
+    let mut stats: BTreeMap<String, BTreeMap<&'static str, i64>> = BTreeMap::new();
+    for (kind, status, count) in jobs::counts(&state).await? {
+        stats
+            .entry(kind)
+            .or_default()
+            .insert(status.as_str(), count);
+    }
+    Ok(Json(stats))
+}
+
+async fn show(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Job>> {
+    user.require(ApiTokenScope::Admin)?;
+    Ok(Json(redact(jobs::find(&state, id).await?)))
+}
+
+/// Gives a dead job a fresh set of attempts, or runs a pending one now.
+async fn retry(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Job>> {
+    user.require(ApiTokenScope::Admin)?;
+    let job = jobs::retry(&state, id).await?;
+    tracing::info!(job_id = %id, admin_id = %user.id, "job retried");
+    Ok(Json(redact(job)))
+}
+
+async fn remove(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::Admin)?;
+    jobs::delete(&state, id).await?;
+    tracing::info!(job_id = %id, admin_id = %user.id, "job deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn redact(mut job: Job) -> Job {
+    if REDACTED_KINDS.contains(&job.kind.as_str()) {
+        job.payload = Value::String("[redacted]".into());
+    }
+    job
+}
@@ -0,0 +1,47 @@
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+use crate::auth::{self, token};
+use crate::error::{AppError, AppResult};
+use crate::jobs;
+use crate::metrics::Snapshot;
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(metrics))
+}
+
+async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<impl IntoResponse> {
+    if let Some(expected) = &state.metrics_token {
+        // Comparing hashes keeps the comparison from leaking the token.
+        let given = auth::bearer_token(&headers).map(token::hash);
+        if given != Some(token::hash(expected)) {
+            return Err(AppError::Unauthorized);
+        }
+    }
+
+    let pool = state.pool.state();
+    let mut snapshot = Snapshot {
+        pool_connections: pool.connections,
+        pool_idle: pool.idle_connections,
+        pool_max_size: state.pool.max_size(),
+        ..Snapshot::default()
+    };
+    // A scrape should still report everything else while the database is down.
+    match jobs::pending_counts(&state).await {
+        Ok(depths) => snapshot.queue_depths = depths,
+        Err(err) => tracing::warn!("cannot count pending jobs: {err}"),
+    }
+
+    Ok((
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        state.metrics.render(&snapshot),
+    ))
+}
@@ -0,0 +1,84 @@
+mod account;
+mod api_tokens;
+mod auth;
+mod calendar;
+mod comments;
+mod health;
+mod jobs;
+mod metrics;
+mod notes;
+mod notifications;
+mod oidc;
+mod reminders;
+mod shares;
+mod two_factor;
+
+use std::time::Duration;
+
+use axum::extract::DefaultBodyLimit;
+use axum::http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderValue, Method};
+use axum::{middleware, Router};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::telemetry::{self, REQUEST_ID};
+
+pub fn router(state: AppState, cors_config: &CorsConfig) -> Router {
+    let router = Router::new()
+        .merge(health::router())
+        .nest("/api/auth", auth::router(state.clone()))
+        .nest("/api/auth/oidc", oidc::router())
+        .nest("/api/account", account::router())
+        .nest("/api/account/2fa", two_factor::router())
+        .nest("/api/account/tokens", api_tokens::router())
+        .nest(
+            "/api/notes",
+            notes::router()
+                .merge(reminders::router())
+                .merge(comments::router())
+                .merge(shares::router()),
+        )
+        .nest("/api/notifications", notifications::router())
+        .nest("/api/admin/jobs", jobs::router())
+        .nest("/cal", calendar::router())
+        .nest("/metrics", metrics::router())
+        .fallback(|| async { AppError::NotFound })
+        .method_not_allowed_fallback(|| async { AppError::MethodNotAllowed })
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            crate::metrics::track,
+        ))
+        .layer(DefaultBodyLimit::max(state.max_body_bytes))
+        .layer(cors(cors_config))
+        .with_state(state);
+    telemetry::trace_requests(router)
+}
+
+/// Lets browsers on the configured origins call the API. Listed origins may
+/// send cookies, which is what the refresh token travels in.
+fn cors(config: &CorsConfig) -> CorsLayer {
+    let layer = CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE, REQUEST_ID])
+        .expose_headers([CONTENT_DISPOSITION, RETRY_AFTER, REQUEST_ID])
+        .max_age(Duration::from_secs(60 * 60));
+    if config.allowed_origins.iter().any(|origin| origin == "*") {
+        return layer.allow_origin(AllowOrigin::any());
+    }
+    let origins = config
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok());
+    layer
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+}
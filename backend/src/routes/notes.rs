@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::ApiUser;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path, Query};
+use crate::models::api_token::ApiTokenScope;
+use crate::models::note::{NewNote, Note, NoteColor};
+use crate::models::reminder::NoteReminder;
+use crate::models::tag::{self, NoteTag};
+use crate::schema::{attachments, note_reminders, note_tags, notes, tags};
+use crate::shares::{self, Access};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:id", get(show).patch(update).delete(remove))
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteResponse {
+    #[serde(flatten)]
+    pub note: Note,
+    pub tags: Vec<String>,
+    pub reminder: Option<NoteReminder>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    archived: bool,
+    tag: Option<String>,
+}
+
+/// Pinned notes first, then the most recently edited.
+async fn list(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Query(query): Query<ListQuery>,
+) -> AppResult<Json<Vec<NoteResponse>>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let notes = db::run(&state.pool, move |conn| {
+        let mut select = notes::table
+            .filter(notes::user_id.eq(user.id))
+            .filter(notes::archived.eq(query.archived))
+            .order((notes::pinned.desc(), notes::updated_at.desc()))
+            .select(Note::as_select())
+            .into_boxed();
+        if let Some(tag) = &query.tag {
+            select = select.filter(
+                notes::id.eq_any(
+                    note_tags::table
+                        .inner_join(tags::table)
+                        .filter(tags::user_id.eq(user.id))
+                        .filter(tags::name.eq(tag))
+                        .select(note_tags::note_id),
+                ),
+            );
+        }
+        let notes = select.load(conn)?;
+        with_details(conn, notes)
+    })
+    .await?;
+
+    Ok(Json(notes))
+}
+
+/// Also notes shared with the user, without the owner's tags and reminder.
+async fn show(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<NoteResponse>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let note = db::run(&state.pool, move |conn| {
+        let note = shares::note(conn, user.id, id, Access::Read)?;
+        details_for(conn, user.id, note)
+    })
+    .await?;
+
+    Ok(Json(note))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct CreateNoteRequest {
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    title: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    color: NoteColor,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    #[validate(custom(function = "validate_tags"))]
+    tags: Vec<String>,
+}
+
+async fn create(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Json(payload): Json<CreateNoteRequest>,
+) -> AppResult<(StatusCode, Json<NoteResponse>)> {
+    user.require(ApiTokenScope::NotesWrite)?;
+    payload.validate()?;
+
+    let note = db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let now = Utc::now();
+            let note: Note = diesel::insert_into(notes::table)
+                .values(NewNote {
+                    user_id: user.id,
+                    title: payload.title,
+                    content: payload.content,
+                    color: payload.color,
+                    pinned: payload.pinned,
+                    archived: payload.archived,
+                    created_at: now,
+                    updated_at: now,
+                })
+                .returning(Note::as_returning())
+                .get_result(conn)?;
+            set_tags(conn, &note, &payload.tags)?;
+            single_with_details(conn, note)
+        })
+    })
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(note)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct UpdateNoteRequest {
+    #[validate(length(max = 255))]
+    title: Option<String>,
+    content: Option<String>,
+    color: Option<NoteColor>,
+    pinned: Option<bool>,
+    archived: Option<bool>,
+    /// Replaces all tags of the note when present.
+    #[validate(custom(function = "validate_tags"))]
+    tags: Option<Vec<String>>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = notes)]
+struct NoteChanges {
+    title: Option<String>,
+    content: Option<String>,
+    color: Option<NoteColor>,
+    pinned: Option<bool>,
+    archived: Option<bool>,
+}
+
+/// Editors of a shared note change only its title and content.
+async fn update(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateNoteRequest>,
+) -> AppResult<Json<NoteResponse>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+    payload.validate()?;
+
+    let note = db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let note = shares::note(conn, user.id, id, Access::Edit)?;
+            let filing = payload.color.is_some()
+                || payload.pinned.is_some()
+                || payload.archived.is_some()
+                || payload.tags.is_some();
+            if note.user_id != user.id && filing {
+                return Err(AppError::Forbidden(
+                    "only the owner can change the color, pins, archive and tags of a note".into(),
+                ));
+            }
+            let changes = NoteChanges {
+                title: payload.title,
+                content: payload.content,
+                color: payload.color,
+                pinned: payload.pinned,
+                archived: payload.archived,
+            };
+            let has_changes = changes.title.is_some()
+                || changes.content.is_some()
+                || changes.color.is_some()
+                || changes.pinned.is_some()
+                || changes.archived.is_some();
+            let note = if has_changes {
+                diesel::update(notes::table.find(note.id))
+                    .set(&changes)
+                    .returning(Note::as_returning())
+                    .get_result(conn)?
+            } else {
+                note
+            };
+            if let Some(tags) = &payload.tags {
+                set_tags(conn, &note, tags)?;
+            }
+            details_for(conn, user.id, note)
+        })
+    })
+    .await?;
+
+    Ok(Json(note))
+}
+
+async fn remove(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let store = state.store.clone();
+    db::run(&state.pool, move |conn| {
+        let keys = conn.transaction(|conn| {
+            let note = find(conn, user.id, id)?;
+            let keys: Vec<String> = attachments::table
+                .filter(attachments::note_id.eq(note.id))
+                .select(attachments::storage_key)
+                .load(conn)?;
+            diesel::delete(notes::table.find(note.id)).execute(conn)?;
+            Ok::<_, AppError>(keys)
+        })?;
+        // Blobs go only once the rows are gone, so a failed delete never
+        // leaves attachments pointing at missing files.
+        for key in keys {
+            if let Err(err) = store.delete(&key) {
+                tracing::warn!("failed to delete attachment {key}: {err}");
+            }
+        }
+        Ok::<_, AppError>(())
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) fn find(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> QueryResult<Note> {
+    notes::table
+        .find(id)
+        .filter(notes::user_id.eq(user_id))
+        .select(Note::as_select())
+        .first(conn)
+}
+
+/// Replaces the tags of `note` with `names`, creating missing tags.
+fn set_tags(conn: &mut PgConnection, note: &Note, names: &[String]) -> QueryResult<()> {
+    diesel::delete(note_tags::table.filter(note_tags::note_id.eq(note.id))).execute(conn)?;
+
+    let mut names: Vec<&str> = names
+        .iter()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let links = names
+        .into_iter()
+        .map(|name| {
+            Ok(NoteTag {
+                note_id: note.id,
+                tag_id: tag::find_or_create(conn, note.user_id, name)?,
+            })
+        })
+        .collect::<QueryResult<Vec<_>>>()?;
+    if !links.is_empty() {
+        diesel::insert_into(note_tags::table)
+            .values(&links)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Loads the tags and reminders of `notes`.
+fn with_details(conn: &mut PgConnection, notes: Vec<Note>) -> QueryResult<Vec<NoteResponse>> {
+    let ids: Vec<Uuid> = notes.iter().map(|note| note.id).collect();
+    let mut tags_by_note: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (note_id, name) in note_tags::table
+        .inner_join(tags::table)
+        .filter(note_tags::note_id.eq_any(&ids))
+        .order(tags::name.asc())
+        .select((note_tags::note_id, tags::name))
+        .load::<(Uuid, String)>(conn)?
+    {
+        tags_by_note.entry(note_id).or_default().push(name);
+    }
+    let mut reminders: HashMap<Uuid, NoteReminder> = note_reminders::table
+        .filter(note_reminders::note_id.eq_any(&ids))
+        .select(NoteReminder::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|reminder| (reminder.note_id, reminder))
+        .collect();
+
+    Ok(notes
+        .into_iter()
+        .map(|note| NoteResponse {
+            tags: tags_by_note.remove(&note.id).unwrap_or_default(),
+            reminder: reminders.remove(&note.id),
+            note,
+        })
+        .collect())
+}
+
+fn single_with_details(conn: &mut PgConnection, note: Note) -> AppResult<NoteResponse> {
+    with_details(conn, vec![note])?
+        .pop()
+        .ok_or_else(|| AppError::internal("note vanished while loading its details"))
+}
+
+/// Like [`single_with_details`], but only the owner sees their tags and
+/// reminder.
+fn details_for(conn: &mut PgConnection, user_id: Uuid, note: Note) -> AppResult<NoteResponse> {
+    if note.user_id != user_id {
+        return Ok(NoteResponse {
+            note,
+            tags: Vec::new(),
+            reminder: None,
+        });
+    }
+    single_with_details(conn, note)
+}
+
+fn validate_tags(tags: &[String]) -> Result<(), validator::ValidationError> {
+    if tags.iter().any(|tag| tag.trim().chars().count() > 100) {
+        return Err(validator::ValidationError::new("tag_length")
+            .with_message("tags can be at most 100 characters long".into()));
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, post};
+use axum::Router;
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::auth::ApiUser;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path, Query};
+use crate::models::api_token::ApiTokenScope;
+use crate::models::notification::{Delivery, Notification, NotificationKind};
+use crate::notifications::{self, NotificationFilter};
+use crate::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list))
+        .route("/unread-count", get(unread_count))
+        .route("/read", post(mark_all_read))
+        .route("/:id/read", post(mark_read))
+        .route("/preferences", get(preferences).put(set_preferences))
+        .route("/stream", get(stream))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    unread: bool,
+    /// ID of the last notification of the previous page.
+    before: Option<Uuid>,
+    limit: Option<i64>,
+}
+
+/// Newest first.
+async fn list(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Query(query): Query<ListQuery>,
+) -> AppResult<Json<Vec<Notification>>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let filter = NotificationFilter {
+        unread: query.unread,
+        before: query.before,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    };
+    let notifications = db::run(&state.pool, move |conn| {
+        notifications::list(conn, user.id, filter)
+    })
+    .await?;
+
+    Ok(Json(notifications))
+}
+
+#[derive(Debug, Serialize)]
+struct UnreadCount {
+    count: i64,
+}
+
+async fn unread_count(
+    State(state): State<AppState>,
+    user: ApiUser,
+) -> AppResult<Json<UnreadCount>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let count = db::run(&state.pool, move |conn| {
+        notifications::unread_count(conn, user.id)
+    })
+    .await?;
+
+    Ok(Json(UnreadCount { count }))
+}
+
+async fn mark_read(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Notification>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let notification = db::run(&state.pool, move |conn| {
+        notifications::mark_read(conn, user.id, id)
+    })
+    .await?;
+
+    Ok(Json(notification))
+}
+
+async fn mark_all_read(State(state): State<AppState>, user: ApiUser) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    db::run(&state.pool, move |conn| {
+        notifications::mark_all_read(conn, user.id)
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Whether each kind of notification is shown in the app and emailed, e.g.
+/// `{"reminder": {"in_app": true, "email": false}}`.
+async fn preferences(
+    State(state): State<AppState>,
+    user: ApiUser,
+) -> AppResult<Json<BTreeMap<NotificationKind, Delivery>>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let preferences = db::run(&state.pool, move |conn| {
+        notifications::preferences(conn, user.id)
+    })
+    .await?;
+
+    Ok(Json(preferences))
+}
+
+/// Changes the kinds that are given and returns all preferences.
+async fn set_preferences(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Json(payload): Json<BTreeMap<NotificationKind, Delivery>>,
+) -> AppResult<Json<BTreeMap<NotificationKind, Delivery>>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let preferences = db::run(&state.pool, move |conn| {
+        notifications::set_preferences(conn, user.id, &payload)?;
+        notifications::preferences(conn, user.id)
+    })
+    .await?;
+
+    Ok(Json(preferences))
+}
+
+/// Server-sent events with each new in-app notification as a `notification`
+/// event. A `resync` event means some were missed and should be listed
+/// instead. The stream ends when the server starts shutting down, and
+/// browsers reconnect on their own.
+async fn stream(
+    State(state): State<AppState>,
+    user: ApiUser,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let receiver = state.notifications.subscribe();
+    // Dropped with the stream, when the client goes away or it ends.
+    let guard = state.metrics.sse_opened();
+    let events = stream::unfold(
+        (state, receiver, user.id, guard),
+        |(state, mut receiver, user_id, guard)| async move {
+            loop {
+                let id = tokio::select! {
+                    received = receiver.recv() => match received {
+                        Ok(created) if created.user_id == user_id => created.id,
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(_)) => {
+                            let event = Event::default().event("resync").data("");
+                            return Some((Ok(event), (state, receiver, user_id, guard)));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    },
+                    () = state.shutdown.triggered() => return None,
+                };
+                let loaded = db::run(&state.pool, move |conn| {
+                    notifications::find(conn, user_id, id)
+                })
+                .await;
+                let notification = match loaded {
+                    Ok(notification) => notification,
+                    // Deleted together with its note in the meantime.
+                    Err(AppError::NotFound) => continue,
+                    Err(err) => {
+                        tracing::warn!(%id, "cannot load notification: {err}");
+                        continue;
+                    }
+                };
+                let event = Event::default()
+                    .event("notification")
+                    .id(id.to_string())
+                    .json_data(notification);
+                match event {
+                    Ok(event) => return Some((Ok(event), (state, receiver, user_id, guard))),
+                    Err(err) => tracing::warn!(%id, "cannot encode notification: {err}"),
+                }
+            }
+        },
+    );
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,124 @@
+use axum::extract::State;
+use axum::response::Redirect;
+use axum::routing::get;
+use axum::Router;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use chrono::Duration;
+use serde::Deserialize;
+
+use super::auth::{complete_login, record_login, sign_in};
+use crate::auth;
+use crate::auth::oidc::{self, Oidc, PendingLogin};
+use crate::auth::session::Origin;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::Query;
+use crate::state::AppState;
+
+const PENDING_COOKIE: &str = "oidc_login";
+const PENDING_COOKIE_PATH: &str = "/api/auth/oidc";
+const PENDING_PURPOSE: &str = "oidc_login";
+/// How long the user may take at the identity provider.
+const PENDING_TTL_MINUTES: i64 = 10;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/login", get(start))
+        .route("/callback", get(callback))
+}
+
+fn provider(state: &AppState) -> AppResult<&Oidc> {
+    state.oidc.as_ref().ok_or(AppError::NotFound)
+}
+
+/// Sends the browser to the identity provider.
+async fn start(State(state): State<AppState>, jar: CookieJar) -> AppResult<(CookieJar, Redirect)> {
+    let request = provider(&state)?.authorize().await?;
+    let ttl = Duration::minutes(PENDING_TTL_MINUTES);
+    let sealed = state
+        .jwt
+        .seal(PENDING_PURPOSE, &request.pending, ttl)
+        .map_err(AppError::internal)?;
+
+    let cookie = Cookie::build((PENDING_COOKIE, sealed))
+        .path(PENDING_COOKIE_PATH)
+        .http_only(true)
+        .secure(state.secure_cookies)
+        // Lax, because the provider sends the user back with a top-level GET.
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(ttl.num_seconds()))
+        .build();
+    Ok((jar.add(cookie), Redirect::to(&request.url)))
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+async fn callback(
+    State(state): State<AppState>,
+    origin: Origin,
+    jar: CookieJar,
+    Query(query): Query<CallbackQuery>,
+) -> AppResult<(CookieJar, Redirect)> {
+    let provider = provider(&state)?;
+    let pending: PendingLogin = jar
+        .get(PENDING_COOKIE)
+        .and_then(|cookie| state.jwt.unseal(PENDING_PURPOSE, cookie.value()))
+        .ok_or_else(|| AppError::BadRequest("login expired, please try again".into()))?;
+    let jar = jar.remove(Cookie::build(PENDING_COOKIE).path(PENDING_COOKIE_PATH));
+
+    if let Some(error) = query.error {
+        return Err(AppError::BadRequest(format!(
+            "the identity provider refused the login: {}",
+            query.error_description.unwrap_or(error)
+        )));
+    }
+    let (Some(code), Some(csrf_state)) = (query.code, query.state) else {
+        return Err(AppError::BadRequest("code and state are required".into()));
+    };
+    let identity = provider.exchange(&code, &csrf_state, pending).await?;
+
+    let session_ttl = state.session_ttl;
+    let params = state.password_params;
+    let (user, signed_in) = db::run(&state.pool, move |conn| {
+        let mut user = oidc::link_or_create(conn, &identity, &params)?;
+        // Accounts with two-factor authentication still need their code.
+        if user.two_factor_enabled() {
+            return Ok((user, None));
+        }
+        record_login(conn, user.id, &origin, true)?;
+        let signed_in = complete_login(conn, &mut user, false, &origin, session_ttl)?;
+        Ok::<_, AppError>((user, Some(signed_in)))
+    })
+    .await?;
+
+    let post_login_url = &provider.config().post_login_url;
+    match signed_in {
+        Some((session, refresh_token)) => {
+            let jar = sign_in(&state, jar, &session, user.role, refresh_token)?;
+            Ok((jar, Redirect::to(post_login_url)))
+        }
+        None => {
+            let challenge_token = state
+                .jwt
+                .issue_challenge(user.id)
+                .map_err(AppError::internal)?;
+            // Only a marker goes into the URL; the challenge itself would
+            // leak into histories, logs and `Referer` headers from there.
+            let separator = if post_login_url.contains('?') {
+                '&'
+            } else {
+                '?'
+            };
+            Ok((
+                jar.add(auth::challenge_cookie(challenge_token, &state)),
+                Redirect::to(&format!("{post_login_url}{separator}two_factor=required")),
+            ))
+        }
+    }
+}
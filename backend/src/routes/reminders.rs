@@ -0,0 +1,152 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{post, put};
+use axum::Router;
+use chrono::{TimeDelta, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::notes;
+use crate::auth::ApiUser;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::{Json, Path};
+use crate::models::api_token::ApiTokenScope;
+use crate::models::reminder::NoteReminder;
+use crate::reminders::{self, recurrence::Recurrence};
+use crate::state::AppState;
+
+/// Nested under `/api/notes` next to the notes themselves.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/reminder", put(set).delete(remove))
+        .route("/:id/reminder/snooze", post(snooze))
+        .route("/:id/reminder/complete", post(complete))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetReminderRequest {
+    /// RFC 3339, or local time in the user's time zone.
+    remind_at: String,
+    /// `daily`, `weekly`, `monthly`, `yearly` or an RRULE.
+    recurrence: Option<String>,
+}
+
+async fn set(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetReminderRequest>,
+) -> AppResult<Json<NoteReminder>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+    let recurrence = payload
+        .recurrence
+        .as_deref()
+        .map(str::parse::<Recurrence>)
+        .transpose()
+        .map_err(|err| AppError::BadRequest(err.to_string()))?;
+
+    let reminder = db::run(&state.pool, move |conn| {
+        let note = notes::find(conn, user.id, id)?;
+        let tz = reminders::time_zone(conn, user.id)?;
+        let remind_at = reminders::parse_time(&payload.remind_at, tz).ok_or_else(invalid_time)?;
+        let recurrence = recurrence.map(|rule| rule.anchored(remind_at, tz));
+        Ok::<_, AppError>(reminders::set(
+            conn,
+            note.id,
+            remind_at,
+            recurrence.as_ref(),
+        )?)
+    })
+    .await?;
+
+    Ok(Json(reminder))
+}
+
+async fn remove(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let removed = db::run(&state.pool, move |conn| {
+        let note = notes::find(conn, user.id, id)?;
+        reminders::remove(conn, note.id)
+    })
+    .await?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound)
+    }
+}
+
+/// Either a time or a number of minutes from now.
+#[derive(Debug, Deserialize)]
+struct SnoozeRequest {
+    until: Option<String>,
+    minutes: Option<u32>,
+}
+
+/// A week.
+const MAX_SNOOZE_MINUTES: u32 = 7 * 24 * 60;
+
+async fn snooze(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SnoozeRequest>,
+) -> AppResult<Json<NoteReminder>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let reminder = db::run(&state.pool, move |conn| {
+        let note = notes::find(conn, user.id, id)?;
+        let until = match (payload.until, payload.minutes) {
+            (Some(until), None) => {
+                let tz = reminders::time_zone(conn, user.id)?;
+                reminders::parse_time(&until, tz).ok_or_else(invalid_time)?
+            }
+            (None, Some(minutes @ 1..=MAX_SNOOZE_MINUTES)) => {
+                Utc::now() + TimeDelta::minutes(minutes.into())
+            }
+            (None, Some(_)) => {
+                return Err(AppError::BadRequest(format!(
+                    "minutes must be between 1 and {MAX_SNOOZE_MINUTES}"
+                )))
+            }
+            _ => return Err(AppError::BadRequest("give either until or minutes".into())),
+        };
+        conn.transaction(|conn| reminders::snooze(conn, note.id, until))
+    })
+    .await?;
+
+    Ok(Json(reminder))
+}
+
+/// Completes the current occurrence; see [`reminders::complete`].
+async fn complete(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<NoteReminder>> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    let reminder = db::run(&state.pool, move |conn| {
+        let note = notes::find(conn, user.id, id)?;
+        let tz = reminders::time_zone(conn, user.id)?;
+        conn.transaction(|conn| reminders::complete(conn, note.id, tz))
+    })
+    .await?;
+
+    Ok(Json(reminder))
+}
+
+fn invalid_time() -> AppError {
+    AppError::BadRequest(format!(
+        "expected an RFC 3339 time or a local time like 2026-10-19T09:00, at most {} years from now",
+        reminders::MAX_YEARS_AWAY
+    ))
+}
@@ -0,0 +1,128 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::Router;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::notes;
+use crate::auth::ApiUser;
+use crate::db;
+use crate::error::AppResult;
+use crate::extract::{Json, Path};
+use crate::models::api_token::ApiTokenScope;
+use crate::models::share::{NoteShare, SharePermission};
+use crate::models::user::User;
+use crate::schema::users;
+use crate::shares;
+use crate::state::AppState;
+
+/// Nested under `/api/notes` next to the notes themselves. Only the owner
+/// sees and changes who a note is shared with.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/shares", get(list).put(set))
+        .route("/:id/shares/:user_id", delete(remove))
+}
+
+#[derive(Debug, Serialize)]
+struct ShareResponse {
+    user_id: Uuid,
+    name: String,
+    email: String,
+    permission: SharePermission,
+    created_at: DateTime<Utc>,
+}
+
+impl ShareResponse {
+    fn new(share: NoteShare, user: User) -> Self {
+        Self {
+            user_id: user.id,
+            name: user.name,
+            email: user.email,
+            permission: share.permission,
+            created_at: share.created_at,
+        }
+    }
+}
+
+async fn list(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<ShareResponse>>> {
+    user.require(ApiTokenScope::NotesRead)?;
+
+    let shares = db::run(&state.pool, move |conn| {
+        let note = notes::find(conn, user.id, id)?;
+        shares::list(conn, note.id)
+    })
+    .await?;
+
+    Ok(Json(
+        shares
+            .into_iter()
+            .map(|(share, user)| ShareResponse::new(share, user))
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct SetShareRequest {
+    #[validate(email)]
+    email: String,
+    permission: SharePermission,
+}
+
+/// Shares the note with the account at `email`, or changes their
+/// permission. Answers the same whether or not the address has an account.
+async fn set(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetShareRequest>,
+) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::NotesWrite)?;
+    payload.validate()?;
+
+    let public_url = state.public_url.clone();
+    db::run(&state.pool, move |conn| {
+        conn.transaction(|conn| {
+            let note = notes::find(conn, user.id, id)?;
+            let owner: User = users::table
+                .find(user.id)
+                .select(User::as_select())
+                .first(conn)?;
+            shares::share(
+                conn,
+                &note,
+                &owner,
+                &payload.email,
+                payload.permission,
+                &public_url,
+            )
+        })
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    user.require(ApiTokenScope::NotesWrite)?;
+
+    db::run(&state.pool, move |conn| {
+        let note = notes::find(conn, user.id, id)?;
+        shares::unshare(conn, note.id, user_id)
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,202 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{delete, post};
+use axum::Router;
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::auth::reissue_access_token;
+use crate::auth::two_factor::{self, Enrollment};
+use crate::auth::{password, AuthUser, PartialAuthUser};
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::extract::Json;
+use crate::models::session::Session;
+use crate::models::user::{User, UserRole};
+use crate::schema::{recovery_codes, sessions, users};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", delete(disable))
+        .route("/setup", post(setup))
+        .route("/enable", post(enable))
+        .route("/recovery-codes", post(regenerate_recovery_codes))
+}
+
+#[derive(Debug, Deserialize)]
+struct CodeRequest {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RecoveryCodesResponse {
+    recovery_codes: Vec<String>,
+}
+
+/// Starts enrollment by storing a new, not yet active secret.
+async fn setup(
+    State(state): State<AppState>,
+    PartialAuthUser(auth): PartialAuthUser,
+) -> AppResult<Json<Enrollment>> {
+    let issuer = state.totp_issuer.clone();
+    let enrollment = db::run(&state.pool, move |conn| {
+        let user = load_user(conn, &auth)?;
+        if user.two_factor_enabled() {
+            return Err(AppError::BadRequest(
+                "two-factor authentication is already enabled".into(),
+            ));
+        }
+
+        let enrollment = two_factor::enroll(&issuer, &user.email)?;
+        diesel::update(users::table.find(user.id))
+            .set(users::totp_secret.eq(&enrollment.secret))
+            .execute(conn)?;
+        Ok(enrollment)
+    })
+    .await?;
+
+    Ok(Json(enrollment))
+}
+
+/// Activates the pending secret once the user proves their app generates
+/// valid codes. The session is upgraded to a two-factor one.
+async fn enable(
+    State(state): State<AppState>,
+    PartialAuthUser(auth): PartialAuthUser,
+    jar: CookieJar,
+    Json(payload): Json<CodeRequest>,
+) -> AppResult<(CookieJar, Json<RecoveryCodesResponse>)> {
+    let (user, session, codes) = db::run(&state.pool, move |conn| {
+        let user = load_user(conn, &auth)?;
+        let secret = match (&user.totp_secret, user.two_factor_enabled()) {
+            (Some(secret), false) => secret.clone(),
+            (_, true) => {
+                return Err(AppError::BadRequest(
+                    "two-factor authentication is already enabled".into(),
+                ))
+            }
+            (None, false) => {
+                return Err(AppError::BadRequest(
+                    "start the setup before enabling two-factor authentication".into(),
+                ))
+            }
+        };
+        let step = two_factor::verify_code(&secret, &payload.code, None)?
+            .ok_or(AppError::InvalidTwoFactorCode)?;
+
+        let codes = two_factor::generate_recovery_codes();
+        let session = conn.transaction(|conn| {
+            diesel::update(users::table.find(user.id))
+                .set((
+                    users::totp_enabled_at.eq(Utc::now()),
+                    users::totp_last_step.eq(step),
+                ))
+                .execute(conn)?;
+            two_factor::store_recovery_codes(conn, user.id, &codes)?;
+            diesel::update(sessions::table.find(auth.session_id))
+                .set(sessions::mfa.eq(true))
+                .returning(Session::as_returning())
+                .get_result(conn)
+                .map_err(AppError::from)
+        })?;
+        Ok((user, session, codes))
+    })
+    .await?;
+
+    let jar = reissue_access_token(&state, jar, &session, user.role)?;
+    Ok((
+        jar,
+        Json(RecoveryCodesResponse {
+            recovery_codes: codes,
+        }),
+    ))
+}
+
+/// Replaces all recovery codes, invalidating the old ones.
+async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<CodeRequest>,
+) -> AppResult<Json<RecoveryCodesResponse>> {
+    let codes = db::run(&state.pool, move |conn| {
+        let user = load_user(conn, &auth)?;
+        verify_enabled_code(conn, &user, &payload.code)?;
+
+        let codes = two_factor::generate_recovery_codes();
+        two_factor::store_recovery_codes(conn, user.id, &codes)?;
+        Ok::<_, AppError>(codes)
+    })
+    .await?;
+
+    Ok(Json(RecoveryCodesResponse {
+        recovery_codes: codes,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DisableRequest {
+    password: String,
+    code: String,
+}
+
+async fn disable(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<DisableRequest>,
+) -> AppResult<StatusCode> {
+    if state.require_admin_2fa && auth.role == UserRole::Admin {
+        return Err(AppError::Forbidden(
+            "admin accounts cannot disable two-factor authentication".into(),
+        ));
+    }
+
+    db::run(&state.pool, move |conn| {
+        let user = load_user(conn, &auth)?;
+        if !password::verify(&payload.password, &user.password) {
+            return Err(AppError::Forbidden("password is incorrect".into()));
+        }
+        verify_enabled_code(conn, &user, &payload.code)?;
+
+        conn.transaction(|conn| {
+            diesel::update(users::table.find(user.id))
+                .set((
+                    users::totp_secret.eq(None::<String>),
+                    users::totp_enabled_at.eq(None::<chrono::DateTime<Utc>>),
+                    users::totp_last_step.eq(None::<i64>),
+                ))
+                .execute(conn)?;
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+                .execute(conn)
+        })?;
+        Ok(())
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn load_user(conn: &mut PgConnection, auth: &AuthUser) -> QueryResult<User> {
+    users::table
+        .find(auth.id)
+        .select(User::as_select())
+        .first(conn)
+}
+
+/// Checks a TOTP code for an account with two-factor authentication enabled
+/// and remembers its time step.
+fn verify_enabled_code(conn: &mut PgConnection, user: &User, code: &str) -> AppResult<()> {
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .filter(|_| user.two_factor_enabled())
+        .ok_or_else(|| AppError::BadRequest("two-factor authentication is not enabled".into()))?;
+    let step = two_factor::verify_code(secret, code, user.totp_last_step)?
+        .ok_or(AppError::InvalidTwoFactorCode)?;
+    if !two_factor::use_step(conn, user.id, step)? {
+        return Err(AppError::InvalidTwoFactorCode);
+    }
+    Ok(())
+}
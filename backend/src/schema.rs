@@ -1,14 +1,298 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "api_token_scope"))]
+    pub struct ApiTokenScope;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "export_status"))]
+    pub struct ExportStatus;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "job_status"))]
+    pub struct JobStatus;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "locale"))]
+    pub struct Locale;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "note_color"))]
+    pub struct NoteColor;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "notification_kind"))]
+    pub struct NotificationKind;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "share_permission"))]
+    pub struct SharePermission;
+
     #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "user_role"))]
     pub struct UserRole;
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ExportStatus;
+
+    account_exports (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        status -> ExportStatus,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        #[max_length = 255]
+        storage_key -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ApiTokenScope;
+
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 100]
+        name -> Varchar,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        scopes -> Array<ApiTokenScope>,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    attachments (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        #[max_length = 255]
+        file_name -> Varchar,
+        #[max_length = 100]
+        mime_type -> Varchar,
+        size_bytes -> Int8,
+        #[max_length = 255]
+        storage_key -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    email_changes (user_id) {
+        user_id -> Uuid,
+        #[max_length = 255]
+        new_email -> Varchar,
+        #[max_length = 64]
+        confirm_token_hash -> Varchar,
+        #[max_length = 64]
+        cancel_token_hash -> Varchar,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::JobStatus;
+
+    jobs (id) {
+        id -> Uuid,
+        #[max_length = 64]
+        kind -> Varchar,
+        payload -> Jsonb,
+        status -> JobStatus,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        run_at -> Timestamptz,
+        locked_until -> Nullable<Timestamptz>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    login_events (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        success -> Bool,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    note_comments (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        user_id -> Uuid,
+        parent_id -> Nullable<Uuid>,
+        body -> Text,
+        anchor_start -> Nullable<Int4>,
+        anchor_end -> Nullable<Int4>,
+        anchor_text -> Nullable<Text>,
+        resolved_at -> Nullable<Timestamptz>,
+        resolved_by -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    note_reminders (note_id) {
+        note_id -> Uuid,
+        remind_at -> Timestamptz,
+        #[max_length = 255]
+        recurrence -> Nullable<Varchar>,
+        notify_at -> Nullable<Timestamptz>,
+        completed_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::SharePermission;
+
+    note_shares (note_id, user_id) {
+        note_id -> Uuid,
+        user_id -> Uuid,
+        permission -> SharePermission,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    note_tags (note_id, tag_id) {
+        note_id -> Uuid,
+        tag_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::NoteColor;
+
+    notes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 255]
+        title -> Varchar,
+        content -> Text,
+        color -> NoteColor,
+        pinned -> Bool,
+        archived -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::NotificationKind;
+
+    notification_preferences (user_id, kind) {
+        user_id -> Uuid,
+        kind -> NotificationKind,
+        in_app -> Bool,
+        email -> Bool,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::NotificationKind;
+
+    notifications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        kind -> NotificationKind,
+        note_id -> Nullable<Uuid>,
+        data -> Jsonb,
+        read_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    rate_limit_buckets (key) {
+        #[max_length = 320]
+        key -> Varchar,
+        tokens -> Float8,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 255]
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        refresh_token_hash -> Varchar,
+        mfa -> Bool,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        created_at -> Timestamptz,
+        last_seen_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 100]
+        name -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_identities (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 255]
+        issuer -> Varchar,
+        #[max_length = 255]
+        subject -> Varchar,
+        #[max_length = 255]
+        email -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        last_login_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::UserRole;
+    use super::sql_types::Locale;
 
     users (id) {
         id -> Uuid,
@@ -17,7 +301,7 @@ diesel::table! {
         #[max_length = 255]
         email -> Varchar,
         verified -> Bool,
-        #[max_length = 100]
+        #[max_length = 255]
         password -> Varchar,
         #[max_length = 255]
         verification_token -> Nullable<Varchar>,
@@ -25,5 +309,59 @@ diesel::table! {
         role -> UserRole,
         created_at -> Nullable<Timestamptz>,
         updated_at -> Nullable<Timestamptz>,
+        deletion_scheduled_at -> Nullable<Timestamptz>,
+        #[max_length = 64]
+        totp_secret -> Nullable<Varchar>,
+        totp_enabled_at -> Nullable<Timestamptz>,
+        totp_last_step -> Nullable<Int8>,
+        failed_login_attempts -> Int4,
+        locked_until -> Nullable<Timestamptz>,
+        locale -> Locale,
+        #[max_length = 64]
+        time_zone -> Varchar,
+        #[max_length = 64]
+        calendar_token_hash -> Nullable<Varchar>,
     }
 }
+
+diesel::joinable!(account_exports -> users (user_id));
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(attachments -> notes (note_id));
+diesel::joinable!(email_changes -> users (user_id));
+diesel::joinable!(login_events -> users (user_id));
+diesel::joinable!(note_comments -> notes (note_id));
+diesel::joinable!(note_reminders -> notes (note_id));
+diesel::joinable!(note_shares -> notes (note_id));
+diesel::joinable!(note_shares -> users (user_id));
+diesel::joinable!(note_tags -> notes (note_id));
+diesel::joinable!(note_tags -> tags (tag_id));
+diesel::joinable!(notes -> users (user_id));
+diesel::joinable!(notification_preferences -> users (user_id));
+diesel::joinable!(notifications -> notes (note_id));
+diesel::joinable!(notifications -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(tags -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    account_exports,
+    api_tokens,
+    attachments,
+    email_changes,
+    jobs,
+    login_events,
+    note_comments,
+    note_reminders,
+    note_shares,
+    note_tags,
+    notes,
+    notification_preferences,
+    notifications,
+    rate_limit_buckets,
+    recovery_codes,
+    sessions,
+    tags,
+    user_identities,
+    users,
+);
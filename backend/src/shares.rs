@@ -0,0 +1,139 @@
+//! Notes shared by their owner with other people. Viewers read the note and
+//! its comments; commenters also comment, and editors also change its title
+//! and content. Only the owner files the note, deletes it or changes who it
+//! is shared with.
+
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::mail::{self, Email};
+use crate::models::note::Note;
+use crate::models::share::{NewNoteShare, NoteShare, SharePermission};
+use crate::models::user::User;
+use crate::schema::{note_shares, notes, users};
+
+/// What someone wants to do with a note that may not be theirs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Read it and its comments.
+    Read,
+    /// Also add to the comments.
+    Comment,
+    /// Also change its title and content.
+    Edit,
+}
+
+/// The note `note_id` if `user_id` owns it or it is shared with them for
+/// `access`. Notes that are not shared with them at all are not found.
+pub fn note(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    note_id: Uuid,
+    access: Access,
+) -> Result<Note, AppError> {
+    let note: Note = notes::table
+        .find(note_id)
+        .select(Note::as_select())
+        .first(conn)?;
+    if note.user_id == user_id {
+        return Ok(note);
+    }
+    let permission: SharePermission = note_shares::table
+        .find((note_id, user_id))
+        .select(note_shares::permission)
+        .first(conn)?;
+    let allowed = match access {
+        Access::Read => true,
+        Access::Comment => permission != SharePermission::Viewer,
+        Access::Edit => permission == SharePermission::Editor,
+    };
+    if !allowed {
+        return Err(AppError::Forbidden(format!(
+            "this note is shared with you as a {permission}"
+        )));
+    }
+    Ok(note)
+}
+
+/// The owner of `note` and everyone it is shared with.
+pub fn members(conn: &mut PgConnection, note: &Note) -> QueryResult<Vec<User>> {
+    let shared_with = note_shares::table
+        .filter(note_shares::note_id.eq(note.id))
+        .select(note_shares::user_id);
+    users::table
+        .filter(users::id.eq(note.user_id).or(users::id.eq_any(shared_with)))
+        .select(User::as_select())
+        .load(conn)
+}
+
+/// Everyone `note_id` is shared with, in the order it was shared.
+pub fn list(conn: &mut PgConnection, note_id: Uuid) -> QueryResult<Vec<(NoteShare, User)>> {
+    note_shares::table
+        .inner_join(users::table)
+        .filter(note_shares::note_id.eq(note_id))
+        .order(note_shares::created_at.asc())
+        .select((NoteShare::as_select(), User::as_select()))
+        .load(conn)
+}
+
+/// Shares `note` with the account at `email`, or changes their permission
+/// if it already is. They get an email the first time. Addresses without an
+/// account are skipped without telling the owner, who could otherwise find
+/// out who has one.
+pub fn share(
+    conn: &mut PgConnection,
+    note: &Note,
+    owner: &User,
+    email: &str,
+    permission: SharePermission,
+    public_url: &str,
+) -> Result<(), AppError> {
+    let Some(user) = users::table
+        .filter(users::email.eq(email))
+        .select(User::as_select())
+        .first(conn)
+        .optional()?
+    else {
+        return Ok(());
+    };
+    if user.id == note.user_id {
+        return Err(AppError::BadRequest(
+            "notes cannot be shared with their owner".into(),
+        ));
+    }
+
+    let updated = diesel::update(note_shares::table.find((note.id, user.id)))
+        .set(note_shares::permission.eq(permission))
+        .execute(conn)?;
+    if updated == 0 {
+        diesel::insert_into(note_shares::table)
+            .values(NewNoteShare {
+                note_id: note.id,
+                user_id: user.id,
+                permission,
+            })
+            .execute(conn)?;
+        mail::enqueue(
+            conn,
+            &user.email,
+            user.locale,
+            user.tz(),
+            Email::ShareInvitation {
+                inviter: owner.name.clone(),
+                note_title: note.title.clone(),
+                link: format!("{}/notes/{}", public_url.trim_end_matches('/'), note.id),
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Stops sharing `note_id` with `user_id`.
+pub fn unshare(conn: &mut PgConnection, note_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    let deleted = diesel::delete(note_shares::table.find((note_id, user_id))).execute(conn)?;
+    if deleted == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+//! Coordinates shutting down, which goes through three phases:
+//!
+//! 1. Draining: readiness turns false so load balancers stop sending
+//!    traffic, but requests are still served. Periodic tasks stop, and
+//!    long-lived connections should be closed.
+//! 2. Closing: no new connections are accepted; running requests and
+//!    background tasks get until the deadline to finish.
+//! 3. Stopping: the deadline has passed. Tasks that are still running should
+//!    save what they can, since they are dropped shortly after.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// How long tasks get to save their progress once the deadline has passed.
+const CHECKPOINT_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Running,
+    Draining,
+    Closing,
+    Stopping,
+}
+
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    phase: Arc<watch::Sender<Phase>>,
+    /// Number of background tasks still running.
+    tasks: Arc<watch::Sender<usize>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            phase: Arc::new(watch::channel(Phase::Running).0),
+            tasks: Arc::new(watch::channel(0).0),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.advance(Phase::Draining);
+    }
+
+    /// Stops accepting connections.
+    pub fn close(&self) {
+        self.advance(Phase::Closing);
+    }
+
+    /// Tells the tasks that are still running to stop.
+    pub fn stop(&self) {
+        self.advance(Phase::Stopping);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.phase.borrow() >= Phase::Draining
+    }
+
+    /// Completes once shutdown has been triggered, immediately if it already
+    /// has been.
+    pub async fn triggered(&self) {
+        self.reached(Phase::Draining).await;
+    }
+
+    /// Completes once no new connections should be accepted.
+    pub async fn closing(&self) {
+        self.reached(Phase::Closing).await;
+    }
+
+    /// Completes once the deadline has passed and running work should be
+    /// checkpointed.
+    pub async fn stopping(&self) {
+        self.reached(Phase::Stopping).await;
+    }
+
+    /// Runs `future` in the background; shutting down waits for it until the
+    /// deadline.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.send_modify(|count| *count += 1);
+        let guard = TaskGuard {
+            tasks: self.tasks.clone(),
+        };
+        tokio::spawn(async move {
+            let _guard = guard;
+            future.await
+        })
+    }
+
+    /// Waits for the background tasks until `deadline`, then tells the rest
+    /// to stop and gives them a moment to checkpoint. Returns how many were
+    /// still running after that.
+    pub async fn wait_for_tasks(&self, deadline: Instant) -> usize {
+        let mut tasks = self.tasks.subscribe();
+        tokio::select! {
+            _ = tasks.wait_for(|&count| count == 0) => return 0,
+            () = tokio::time::sleep_until(deadline) => {}
+            () = self.stopping() => {}
+        }
+        self.stop();
+        let _ = tokio::time::timeout(CHECKPOINT_GRACE, tasks.wait_for(|&count| count == 0)).await;
+        let remaining = *tasks.borrow();
+        remaining
+    }
+
+    fn advance(&self, phase: Phase) {
+        self.phase.send_if_modified(|current| {
+            let advanced = *current < phase;
+            if advanced {
+                *current = phase;
+            }
+            advanced
+        });
+    }
+
+    async fn reached(&self, phase: Phase) {
+        let mut receiver = self.phase.subscribe();
+        // The sender lives as long as `self`, so this cannot fail.
+        let _ = receiver.wait_for(|&current| current >= phase).await;
+    }
+}
+
+/// Counts a background task as running until dropped.
+struct TaskGuard {
+    tasks: Arc<watch::Sender<usize>>,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.tasks.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Moves through the phases as signals arrive: the first SIGTERM or Ctrl-C
+/// starts draining and closes after `drain_delay`, a second one closes right
+/// away and a third one skips the deadline.
+pub async fn handle_signals(shutdown: Shutdown, drain_delay: Duration) {
+    signal().await;
+    shutdown.trigger();
+    tracing::info!("shutting down in {} seconds", drain_delay.as_secs());
+    tokio::select! {
+        () = tokio::time::sleep(drain_delay) => {}
+        () = signal() => {}
+    }
+    tracing::info!("shutting down");
+    shutdown.close();
+
+    signal().await;
+    tracing::warn!("not waiting for running requests and jobs");
+    shutdown.stop();
+}
+
+/// Completes on SIGTERM or Ctrl-C.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("cannot listen for Ctrl-C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("cannot listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
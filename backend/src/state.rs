@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use chrono::Duration;
+
+use crate::auth::breached::BreachedPasswords;
+use crate::auth::jwt::JwtKeys;
+use crate::auth::lockout::LockoutPolicy;
+use crate::auth::oidc::Oidc;
+use crate::auth::password::HashParams;
+use crate::auth::password_policy::PasswordPolicy;
+use crate::config::{Config, RateLimitBackend, SharedNotesPolicy};
+use crate::db::{self, DbPool};
+use crate::mail::Mailer;
+use crate::metrics::Metrics;
+use crate::notifications::stream::Hub;
+use crate::rate_limit::{LoginLimits, MemoryStore, PgStore};
+use crate::shutdown::Shutdown;
+use crate::storage::BlobStore;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: DbPool,
+    pub store: Arc<dyn BlobStore>,
+    pub mailer: Mailer,
+    pub jwt: JwtKeys,
+    /// How long a session lasts without its refresh token being used.
+    pub session_ttl: Duration,
+    pub secure_cookies: bool,
+    /// Base URL used when building links for emails.
+    pub public_url: String,
+    pub export_ttl: Duration,
+    /// How long the links for confirming a new email address stay valid.
+    pub email_change_ttl: Duration,
+    pub deletion_grace: Duration,
+    pub deletion_shared_notes: SharedNotesPolicy,
+    /// How long completed jobs are kept.
+    pub job_retention: Duration,
+    pub totp_issuer: String,
+    pub require_admin_2fa: bool,
+    /// Largest request body accepted.
+    pub max_body_bytes: usize,
+    pub login_limits: LoginLimits,
+    pub lockout: LockoutPolicy,
+    pub password_policy: PasswordPolicy,
+    /// Argon2 costs for new password hashes; older hashes are upgraded on login.
+    pub password_params: HashParams,
+    /// Single sign-on, if an identity provider is configured.
+    pub oidc: Option<Oidc>,
+    pub metrics: Arc<Metrics>,
+    /// Bearer token required to read `/metrics`.
+    pub metrics_token: Option<String>,
+    /// New notifications, for pushing them to connected clients.
+    pub notifications: Hub,
+    pub shutdown: Shutdown,
+}
+
+impl AppState {
+    /// Connects to the database and sets up everything the handlers share.
+    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let pool = db::build_pool(&config.database.url, &config.database.pool)?;
+        let metrics = Arc::new(Metrics::default());
+
+        let limits = &config.limits;
+        let login_limits = LoginLimits {
+            store: match limits.rate_limit_backend {
+                RateLimitBackend::Memory => Arc::new(MemoryStore::default()),
+                RateLimitBackend::Postgres => Arc::new(PgStore::new(pool.clone())),
+            },
+            per_ip: limits.login_per_ip,
+            per_email: limits.login_per_email,
+        };
+
+        let passwords = &config.passwords;
+        let breached = match &passwords.breached_path {
+            Some(path) => Some(Arc::new(BreachedPasswords::open(path).map_err(|err| {
+                format!("cannot read BREACHED_PASSWORDS_PATH {path:?}: {err}")
+            })?)),
+            None => None,
+        };
+        let oidc = config.oidc.clone().map(Oidc::new).transpose()?;
+
+        Ok(Self {
+            pool,
+            store: config.storage.open(),
+            mailer: Mailer::new(&config.mail, metrics.clone())?,
+            jwt: JwtKeys::new(config.auth.jwt_secret.as_bytes(), config.auth.jwt_ttl),
+            session_ttl: config.auth.session_ttl,
+            secure_cookies: config.cookies.secure,
+            public_url: config.server.public_url.clone(),
+            export_ttl: config.accounts.export_ttl,
+            email_change_ttl: config.accounts.email_change_ttl,
+            deletion_grace: config.accounts.deletion_grace,
+            deletion_shared_notes: config.accounts.deletion_shared_notes,
+            job_retention: config.jobs.completed_retention,
+            totp_issuer: config.auth.totp_issuer.clone(),
+            require_admin_2fa: config.auth.require_admin_2fa,
+            max_body_bytes: limits.max_body_bytes,
+            login_limits,
+            lockout: limits.lockout,
+            password_policy: PasswordPolicy {
+                min_length: passwords.min_length,
+                min_score: passwords.min_score,
+                breached,
+            },
+            password_params: passwords.hash,
+            oidc,
+            metrics,
+            metrics_token: config.metrics_token.clone(),
+            notifications: Hub::default(),
+            shutdown: Shutdown::default(),
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use uuid::Uuid;
+
+/// Storage for attachment contents, addressed by opaque keys.
+pub trait BlobStore: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+    fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    fn delete(&self, key: &str) -> io::Result<()>;
+    /// Checks that blobs can be written, for the readiness probe.
+    fn check(&self) -> io::Result<()>;
+}
+
+/// Keeps blobs as plain files below a root directory.
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(key);
+        let is_plain = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if key.is_empty() || !is_plain {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid blob key: {key}"),
+            ));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path_for(key)?)
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path_for(key)?) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        let key = format!(".probe-{}", Uuid::new_v4());
+        self.put(&key, b"")?;
+        self.delete(&key)
+    }
+}
@@ -0,0 +1,30 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::error::AppResult;
+use crate::state::AppState;
+
+/// Runs `task` every `period` until shutdown is triggered. The task
+/// reports how many items it processed so quiet runs stay out of the log.
+/// A run that has started is finished before shutting down.
+pub fn spawn_periodic<F, Fut>(name: &'static str, period: Duration, state: AppState, task: F)
+where
+    F: Fn(AppState) -> Fut + Send + 'static,
+    Fut: Future<Output = AppResult<usize>> + Send,
+{
+    let shutdown = state.shutdown.clone();
+    shutdown.clone().spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.triggered() => break,
+            }
+            match task(state.clone()).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("{name}: processed {count} items"),
+                Err(err) => tracing::error!("{name} failed: {err}"),
+            }
+        }
+    });
+}
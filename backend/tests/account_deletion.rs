@@ -0,0 +1,213 @@
+mod common;
+
+use backend::account::deletion;
+use backend::models::attachment::NewAttachment;
+use backend::models::note::{NewNote, NoteColor};
+use backend::models::share::{NewNoteShare, SharePermission};
+use backend::models::user::User;
+use backend::schema::{attachments, note_shares, notes, users};
+use chrono::{DateTime, Duration, Utc};
+use common::{TestApp, PASSWORD};
+use diesel::prelude::*;
+use openidconnect::reqwest::{Response, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+fn create_note(app: &TestApp, user_id: Uuid, title: &str) -> Uuid {
+    diesel::insert_into(notes::table)
+        .values(NewNote {
+            user_id,
+            title: title.into(),
+            content: String::new(),
+            color: NoteColor::Default,
+            pinned: false,
+            archived: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .returning(notes::id)
+        .get_result(&mut app.conn())
+        .unwrap()
+}
+
+/// Shares `note_id` with `user`, as if it had been `days_ago`.
+fn share(app: &TestApp, note_id: Uuid, user: &User, permission: SharePermission, days_ago: i64) {
+    diesel::insert_into(note_shares::table)
+        .values(NewNoteShare {
+            note_id,
+            user_id: user.id,
+            permission,
+        })
+        .execute(&mut app.conn())
+        .unwrap();
+    diesel::update(note_shares::table.find((note_id, user.id)))
+        .set(note_shares::created_at.eq(Utc::now() - Duration::days(days_ago)))
+        .execute(&mut app.conn())
+        .unwrap();
+}
+
+/// Removes `user` right away, skipping the grace period.
+async fn purge(app: &TestApp, user: &User) {
+    diesel::update(users::table.find(user.id))
+        .set(users::deletion_scheduled_at.eq(Utc::now() - Duration::minutes(1)))
+        .execute(&mut app.conn())
+        .unwrap();
+    deletion::purge_due(app.state.clone()).await.unwrap();
+    assert_eq!(scheduled_at(app, user.id), None);
+}
+
+fn owner_of(app: &TestApp, note_id: Uuid) -> Option<Uuid> {
+    notes::table
+        .find(note_id)
+        .select(notes::user_id)
+        .first(&mut app.conn())
+        .optional()
+        .unwrap()
+}
+
+fn shared_with(app: &TestApp, note_id: Uuid) -> Vec<Uuid> {
+    note_shares::table
+        .filter(note_shares::note_id.eq(note_id))
+        .order(note_shares::user_id)
+        .select(note_shares::user_id)
+        .load(&mut app.conn())
+        .unwrap()
+}
+
+async fn request_deletion(app: &TestApp, cookie: &str) -> Response {
+    app.delete("/api/account")
+        .header("cookie", cookie)
+        .header("content-type", "application/json")
+        .body(json!({ "password": PASSWORD }).to_string())
+        .send()
+        .await
+        .unwrap()
+}
+
+fn scheduled_at(app: &TestApp, user_id: Uuid) -> Option<Option<DateTime<Utc>>> {
+    users::table
+        .find(user_id)
+        .select(users::deletion_scheduled_at)
+        .first(&mut app.conn())
+        .optional()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn logging_in_cancels_the_deletion() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Leaving");
+    let cookie = app.login(&user.email).await;
+
+    let response = request_deletion(&app, &cookie).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let deletion_at = scheduled_at(&app, user.id).unwrap().unwrap();
+    assert!(deletion_at > Utc::now() + Duration::days(13));
+    // Every session ends, so only a new login can cancel it.
+    let response = app
+        .get("/api/account")
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    app.login(&user.email).await;
+    assert_eq!(scheduled_at(&app, user.id), Some(None));
+    deletion::purge_due(app.state.clone()).await.unwrap();
+    assert_eq!(scheduled_at(&app, user.id), Some(None));
+}
+
+#[tokio::test]
+async fn deletes_the_account_once_the_grace_period_is_over() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Gone");
+    let cookie = app.login(&user.email).await;
+    let note_id = create_note(&app, user.id, "Soon gone");
+    let storage_key = format!("attachments/{}", Uuid::new_v4());
+    app.state.store.put(&storage_key, b"data").unwrap();
+    diesel::insert_into(attachments::table)
+        .values(NewAttachment {
+            note_id,
+            file_name: "data.txt".into(),
+            mime_type: "text/plain".into(),
+            size_bytes: 4,
+            storage_key: storage_key.clone(),
+        })
+        .execute(&mut app.conn())
+        .unwrap();
+
+    let response = request_deletion(&app, &cookie).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let kept = app.user("Kept");
+
+    // Not yet due.
+    deletion::purge_due(app.state.clone()).await.unwrap();
+    assert!(scheduled_at(&app, user.id).is_some());
+
+    diesel::update(users::table.find(user.id))
+        .set(users::deletion_scheduled_at.eq(Utc::now() - Duration::minutes(1)))
+        .execute(&mut app.conn())
+        .unwrap();
+    let purged = deletion::purge_due(app.state.clone()).await.unwrap();
+    assert!(purged >= 1);
+    assert_eq!(scheduled_at(&app, user.id), None);
+    let notes_left: i64 = notes::table
+        .filter(notes::id.eq(note_id))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap();
+    assert_eq!(notes_left, 0);
+    assert!(app.state.store.get(&storage_key).is_err());
+    assert_eq!(scheduled_at(&app, kept.id), Some(None));
+}
+
+#[tokio::test]
+async fn removes_shared_notes_by_default() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let (owner, editor) = (app.user("Owner"), app.user("Editor"));
+    let note_id = create_note(&app, owner.id, "Shared");
+    share(&app, note_id, &editor, SharePermission::Editor, 3);
+
+    purge(&app, &owner).await;
+    assert_eq!(owner_of(&app, note_id), None);
+    assert!(shared_with(&app, note_id).is_empty());
+}
+
+#[tokio::test]
+async fn can_hand_shared_notes_to_the_longest_standing_editor() {
+    let Some(app) = TestApp::with_settings(
+        r#"
+[accounts]
+deletion_shared_notes = "transfer"
+"#,
+    )
+    .await
+    else {
+        return;
+    };
+    let owner = app.user("Owner");
+    let (first, second, commenter) = (app.user("First"), app.user("Second"), app.user("Commenter"));
+    let shared = create_note(&app, owner.id, "Shared");
+    // Longer than any editor, but cannot edit.
+    share(&app, shared, &commenter, SharePermission::Commenter, 30);
+    share(&app, shared, &second, SharePermission::Editor, 2);
+    share(&app, shared, &first, SharePermission::Editor, 5);
+    let read_only = create_note(&app, owner.id, "Read only");
+    share(&app, read_only, &commenter, SharePermission::Commenter, 1);
+    let private = create_note(&app, owner.id, "Private");
+
+    purge(&app, &owner).await;
+    assert_eq!(owner_of(&app, shared), Some(first.id));
+    let mut remaining = vec![second.id, commenter.id];
+    remaining.sort();
+    assert_eq!(shared_with(&app, shared), remaining);
+    assert_eq!(owner_of(&app, read_only), None);
+    assert_eq!(owner_of(&app, private), None);
+}
@@ -0,0 +1,251 @@
+mod common;
+
+use backend::account::export::{self, AssembleExport};
+use backend::jobs::BackgroundJob;
+use backend::models::account_export::{AccountExport, ExportStatus};
+use backend::models::api_token::{ApiTokenScope, NewApiToken};
+use backend::models::attachment::NewAttachment;
+use backend::models::comment::NewNoteComment;
+use backend::models::email_change::EmailChange;
+use backend::models::login_event::NewLoginEvent;
+use backend::models::note::{NewNote, NoteColor};
+use backend::models::share::{NewNoteShare, SharePermission};
+use backend::models::tag::{self, NoteTag};
+use backend::models::user::User;
+use backend::models::user_identity::NewUserIdentity;
+use backend::schema::{
+    account_exports, api_tokens, attachments, email_changes, login_events, note_comments,
+    note_shares, note_tags, notes, user_identities,
+};
+use chrono::{Duration, Utc};
+use common::TestApp;
+use diesel::prelude::*;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// A note with a tag, an attachment, a comment and a login, all marked
+/// with `secret` so they can be told apart from another user's.
+fn seed(conn: &mut PgConnection, user: &User, secret: &str) -> Uuid {
+    let note_id = diesel::insert_into(notes::table)
+        .values(NewNote {
+            user_id: user.id,
+            title: format!("Note {secret}"),
+            content: format!("Content {secret}"),
+            color: NoteColor::Default,
+            pinned: false,
+            archived: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .returning(notes::id)
+        .get_result(conn)
+        .unwrap();
+    let tag_id = tag::find_or_create(conn, user.id, &format!("tag-{secret}")).unwrap();
+    diesel::insert_into(note_tags::table)
+        .values(NoteTag { note_id, tag_id })
+        .execute(conn)
+        .unwrap();
+    diesel::insert_into(attachments::table)
+        .values(NewAttachment {
+            note_id,
+            file_name: format!("{secret}.pdf"),
+            mime_type: "application/pdf".into(),
+            size_bytes: 3,
+            storage_key: format!("attachments/{}", Uuid::new_v4()),
+        })
+        .execute(conn)
+        .unwrap();
+    comment(conn, note_id, user, &format!("Comment {secret}"));
+    diesel::insert_into(login_events::table)
+        .values(NewLoginEvent {
+            user_id: user.id,
+            success: true,
+            ip_address: Some("203.0.113.7".into()),
+            user_agent: Some(format!("Browser {secret}")),
+        })
+        .execute(conn)
+        .unwrap();
+    note_id
+}
+
+fn comment(conn: &mut PgConnection, note_id: Uuid, author: &User, body: &str) {
+    diesel::insert_into(note_comments::table)
+        .values(NewNoteComment {
+            note_id,
+            user_id: author.id,
+            parent_id: None,
+            body: body.into(),
+            anchor_start: None,
+            anchor_end: None,
+            anchor_text: None,
+        })
+        .execute(conn)
+        .unwrap();
+}
+
+fn share(conn: &mut PgConnection, note_id: Uuid, user: &User, permission: SharePermission) {
+    diesel::insert_into(note_shares::table)
+        .values(NewNoteShare {
+            note_id,
+            user_id: user.id,
+            permission,
+        })
+        .execute(conn)
+        .unwrap();
+}
+
+/// A token, a linked identity and a pending email change of `user`, marked
+/// with `secret`. Returns the hashes that must stay out of the archive.
+fn seed_account(conn: &mut PgConnection, user: &User, secret: &str) -> [String; 3] {
+    let hashes = [(); 3].map(|_| format!("hash-{}", Uuid::new_v4()));
+    diesel::insert_into(api_tokens::table)
+        .values(NewApiToken {
+            user_id: user.id,
+            name: format!("Script {secret}"),
+            token_hash: hashes[0].clone(),
+            scopes: vec![ApiTokenScope::NotesRead],
+            expires_at: None,
+        })
+        .execute(conn)
+        .unwrap();
+    diesel::insert_into(user_identities::table)
+        .values(NewUserIdentity {
+            user_id: user.id,
+            issuer: "https://id.example.com".into(),
+            subject: format!("subject-{secret}"),
+            email: None,
+        })
+        .execute(conn)
+        .unwrap();
+    diesel::insert_into(email_changes::table)
+        .values(EmailChange {
+            user_id: user.id,
+            new_email: format!("new-{secret}@example.com"),
+            confirm_token_hash: hashes[1].clone(),
+            cancel_token_hash: hashes[2].clone(),
+            expires_at: Utc::now() + Duration::hours(1),
+            created_at: Utc::now(),
+        })
+        .execute(conn)
+        .unwrap();
+    hashes
+}
+
+#[tokio::test]
+async fn archives_everything_of_the_user_and_nothing_else() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let ann = app.user("Ann");
+    let bob = app.user("Bob");
+    let ann_secret = Uuid::new_v4().to_string();
+    let bob_secret = Uuid::new_v4().to_string();
+    let note_id = seed(&mut app.conn(), &ann, &ann_secret);
+    let bob_note_id = seed(&mut app.conn(), &bob, &bob_secret);
+    let hashes = seed_account(&mut app.conn(), &ann, &ann_secret);
+    seed_account(&mut app.conn(), &bob, &bob_secret);
+    // Each shares a note with the other, and comments on it.
+    share(&mut app.conn(), note_id, &bob, SharePermission::Editor);
+    share(
+        &mut app.conn(),
+        bob_note_id,
+        &ann,
+        SharePermission::Commenter,
+    );
+    comment(
+        &mut app.conn(),
+        note_id,
+        &bob,
+        &format!("Reply {bob_secret}"),
+    );
+    comment(
+        &mut app.conn(),
+        bob_note_id,
+        &ann,
+        &format!("Reply {ann_secret}"),
+    );
+
+    let export = export::request(&app.state, ann.id).await.unwrap();
+    // A second request while the first is pending reuses it.
+    assert_eq!(
+        export::request(&app.state, ann.id).await.unwrap().id,
+        export.id
+    );
+    AssembleExport {
+        export_id: export.id,
+    }
+    .run(&app.state)
+    .await
+    .unwrap();
+
+    let export: AccountExport = account_exports::table
+        .find(export.id)
+        .select(AccountExport::as_select())
+        .first(&mut app.conn())
+        .unwrap();
+    assert_eq!(export.status, ExportStatus::Ready);
+    let raw = app.state.store.get(&export.storage_key.unwrap()).unwrap();
+    let text = String::from_utf8(raw).unwrap();
+    let archive: Value = serde_json::from_str(&text).unwrap();
+
+    assert_eq!(archive["user"]["email"], ann.email.as_str());
+    let notes = archive["notes"].as_array().unwrap();
+    assert_eq!(notes.len(), 1);
+    let note = &notes[0];
+    assert_eq!(note["id"], note_id.to_string());
+    assert_eq!(note["title"], format!("Note {ann_secret}"));
+    assert_eq!(note["content"], format!("Content {ann_secret}"));
+    assert_eq!(
+        note["tags"],
+        serde_json::json!([format!("tag-{ann_secret}")])
+    );
+    assert_eq!(
+        note["attachments"][0]["file_name"],
+        format!("{ann_secret}.pdf")
+    );
+    assert!(note["attachments"][0].get("storage_key").is_none());
+    // Only Ann's own comments.
+    let comments = note["comments"].as_array().unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0]["body"], format!("Comment {ann_secret}"));
+    let elsewhere = archive["comments_on_shared_notes"].as_array().unwrap();
+    assert_eq!(elsewhere.len(), 1);
+    assert_eq!(elsewhere[0]["body"], format!("Reply {ann_secret}"));
+    assert_eq!(elsewhere[0]["note_id"], bob_note_id.to_string());
+
+    let granted = archive["shares_granted"].as_array().unwrap();
+    assert_eq!(granted.len(), 1);
+    assert_eq!(granted[0]["note_id"], note_id.to_string());
+    assert_eq!(granted[0]["user_id"], bob.id.to_string());
+    assert_eq!(granted[0]["permission"], "editor");
+    let received = archive["shares_received"].as_array().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0]["note_id"], bob_note_id.to_string());
+    assert_eq!(received[0]["permission"], "commenter");
+
+    let tokens = archive["api_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["name"], format!("Script {ann_secret}"));
+    assert_eq!(tokens[0]["scopes"], serde_json::json!(["notes:read"]));
+    assert!(tokens[0]["created_at"].is_string());
+    let identities = archive["identities"].as_array().unwrap();
+    assert_eq!(identities.len(), 1);
+    assert_eq!(identities[0]["subject"], format!("subject-{ann_secret}"));
+    assert_eq!(
+        archive["pending_email_change"]["new_email"],
+        format!("new-{ann_secret}@example.com")
+    );
+    for hash in hashes {
+        assert!(!text.contains(&hash), "the archive contains a token hash");
+    }
+    let logins = archive["login_history"].as_array().unwrap();
+    assert_eq!(logins.len(), 1);
+    assert_eq!(logins[0]["user_agent"], format!("Browser {ann_secret}"));
+
+    assert!(!text.contains(&bob_secret), "the archive leaks Bob's data");
+    assert!(!text.contains(&bob.email));
+    assert!(
+        !text.contains(&ann.password),
+        "the archive contains the password hash"
+    );
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{json, TestApp};
+use openidconnect::reqwest::StatusCode;
+use serde_json::json;
+
+/// Creates a token with `scopes` and returns the plain token.
+async fn create_token(app: &TestApp, cookie: &str, scopes: &[&str]) -> String {
+    let response = app
+        .post(
+            "/api/account/tokens",
+            json!({ "name": "script", "scopes": scopes }),
+        )
+        .header("cookie", cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = json(response).await;
+    assert_eq!(body["scopes"], json!(scopes));
+    body["token"].as_str().unwrap().to_string()
+}
+
+async fn create_note(app: &TestApp, token: &str) -> StatusCode {
+    app.post("/api/notes", json!({ "title": "From a script" }))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn list_notes(app: &TestApp, token: &str) -> StatusCode {
+    app.get("/api/notes")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn tokens_only_reach_what_their_scopes_allow() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Scripter");
+    let cookie = app.login(&user.email).await;
+
+    let reader = create_token(&app, &cookie, &["notes:read"]).await;
+    assert_eq!(list_notes(&app, &reader).await, StatusCode::OK);
+    assert_eq!(create_note(&app, &reader).await, StatusCode::FORBIDDEN);
+
+    let writer = create_token(&app, &cookie, &["notes:write"]).await;
+    assert_eq!(create_note(&app, &writer).await, StatusCode::CREATED);
+    assert_eq!(list_notes(&app, &writer).await, StatusCode::FORBIDDEN);
+
+    assert_eq!(
+        list_notes(&app, "nat_not-a-real-token").await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn only_admins_may_create_admin_tokens() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Ordinary");
+    let cookie = app.login(&user.email).await;
+
+    let response = app
+        .post(
+            "/api/account/tokens",
+            json!({ "name": "root", "scopes": ["admin"] }),
+        )
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
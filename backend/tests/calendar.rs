@@ -0,0 +1,174 @@
+mod common;
+
+use backend::calendar::{self, ical};
+use backend::models::note::{Note, NoteColor};
+use backend::models::reminder::NoteReminder;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use common::{json, TestApp};
+use openidconnect::reqwest::StatusCode;
+use openidconnect::url::Url;
+use serde_json::json;
+use uuid::Uuid;
+
+fn time(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+}
+
+fn entry(id: u128, title: &str, content: &str, reminder: NoteReminder) -> (Note, NoteReminder) {
+    let note = Note {
+        id: Uuid::from_u128(id),
+        user_id: Uuid::from_u128(1),
+        title: title.into(),
+        content: content.into(),
+        color: NoteColor::Default,
+        pinned: false,
+        archived: false,
+        created_at: time(2026, 10, 1, 8, 0),
+        updated_at: time(2026, 10, 2, 8, 0),
+    };
+    let reminder = NoteReminder {
+        note_id: note.id,
+        ..reminder
+    };
+    (note, reminder)
+}
+
+fn reminder(remind_at: DateTime<Utc>, recurrence: Option<&str>) -> NoteReminder {
+    NoteReminder {
+        note_id: Uuid::nil(),
+        remind_at,
+        recurrence: recurrence.map(str::to_string),
+        notify_at: Some(remind_at),
+        completed_at: None,
+        created_at: time(2026, 10, 1, 9, 0),
+        updated_at: time(2026, 10, 3, 9, 0),
+    }
+}
+
+#[test]
+fn renders_reminders_as_events() {
+    let entries = [
+        entry(
+            2,
+            "Water plants",
+            "Ferns, cacti; the big one, too",
+            reminder(
+                time(2026, 10, 19, 17, 40),
+                Some("FREQ=WEEKLY;BYDAY=MO,TH;BYHOUR=19;BYMINUTE=40;UNTIL=20261231"),
+            ),
+        ),
+        entry(
+            3,
+            "",
+            "",
+            NoteReminder {
+                completed_at: Some(time(2026, 10, 5, 10, 0)),
+                ..reminder(time(2026, 10, 5, 6, 0), None)
+            },
+        ),
+    ];
+    let feed = calendar::render(
+        Tz::Europe__Berlin,
+        &entries,
+        "https://notes.example.com/",
+        time(2026, 10, 18, 12, 0),
+    );
+    assert!(feed.lines().all(|line| line.len() <= 75));
+    insta::assert_snapshot!(feed.replace("\r\n", "\n"));
+}
+
+#[test]
+fn lists_no_changes_for_zones_without_daylight_saving() {
+    let feed = calendar::render(
+        Tz::UTC,
+        &[],
+        "https://notes.example.com",
+        time(2026, 1, 1, 0, 0),
+    );
+    assert_eq!(feed.matches("BEGIN:STANDARD").count(), 1);
+    assert!(!feed.contains("BEGIN:DAYLIGHT"));
+    assert!(feed.contains("TZOFFSETFROM:+0000\r\nTZOFFSETTO:+0000\r\n"));
+}
+
+#[test]
+fn escapes_and_folds_text() {
+    assert_eq!(ical::escape("a,b;c\\d\r\ne"), "a\\,b\\;c\\\\d\\ne");
+
+    let mut writer = ical::Writer::new();
+    writer.text("SUMMARY", &"ä".repeat(50));
+    let text = writer.finish();
+    let lines: Vec<&str> = text.split("\r\n").collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines.iter().all(|line| line.len() <= 75));
+    assert!(lines[1].starts_with(' '));
+    assert_eq!(lines[2], "");
+    let unfolded = text.replace("\r\n ", "");
+    assert_eq!(unfolded, format!("SUMMARY:{}\r\n", "ä".repeat(50)));
+}
+
+/// Creates a feed URL and returns its path.
+async fn create_feed(app: &TestApp, cookie: &str) -> String {
+    let response = app
+        .post("/api/account/calendar", json!({}))
+        .header("cookie", cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let url = json(response).await["url"].as_str().unwrap().to_owned();
+    Url::parse(&url).unwrap().path().to_owned()
+}
+
+#[tokio::test]
+async fn feed_urls_work_until_replaced_or_turned_off() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Planner");
+    let cookie = app.login(&user.email).await;
+    let response = app
+        .post("/api/notes", json!({ "title": "Dentist" }))
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    let note_id = json(response).await["id"].as_str().unwrap().to_owned();
+    let response = app
+        .client
+        .put(app.url(&format!("/api/notes/{note_id}/reminder")))
+        .header("cookie", &cookie)
+        .header("content-type", "application/json")
+        .body(json!({ "remind_at": "2026-11-02T09:30" }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let first = create_feed(&app, &cookie).await;
+    let response = app.get(&first).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/calendar; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.contains("SUMMARY:Dentist"));
+
+    let second = create_feed(&app, &cookie).await;
+    assert_ne!(first, second);
+    let response = app.get(&first).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = app.get(&second).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .delete("/api/account/calendar")
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app.get(&second).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
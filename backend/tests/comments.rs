@@ -0,0 +1,186 @@
+mod common;
+
+use backend::comments::mentions;
+use common::{json, TestApp};
+use openidconnect::reqwest::StatusCode;
+use serde_json::{json, Value};
+
+#[test]
+fn finds_mentions_by_email_address() {
+    assert_eq!(
+        mentions::parse("@ann@example.com, can you ask @Bob.Smith@Example.org?"),
+        ["ann@example.com", "bob.smith@example.org"]
+    );
+    assert_eq!(
+        mentions::parse("(@ann@example.com) and @ann@EXAMPLE.com."),
+        ["ann@example.com"]
+    );
+    assert_eq!(
+        mentions::parse("@ann@mail-1.example.co.uk-"),
+        ["ann@mail-1.example.co.uk"]
+    );
+}
+
+#[test]
+fn ignores_what_is_not_a_mention() {
+    assert!(mentions::parse("write to ann@example.com").is_empty());
+    assert!(mentions::parse("@ann, @ann@localhost, @@, @ann@.com, @ann@example.").is_empty());
+    assert!(mentions::parse("").is_empty());
+}
+
+async fn share(app: &TestApp, cookie: &str, note: &str, email: &str, permission: &str) {
+    let response = app
+        .client
+        .put(app.url(&format!("/api/notes/{note}/shares")))
+        .header("cookie", cookie)
+        .header("content-type", "application/json")
+        .body(json!({ "email": email, "permission": permission }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+async fn comment(app: &TestApp, cookie: &str, note: &str, body: &str) -> StatusCode {
+    app.post(
+        &format!("/api/notes/{note}/comments"),
+        json!({ "body": body }),
+    )
+    .header("cookie", cookie)
+    .send()
+    .await
+    .unwrap()
+    .status()
+}
+
+async fn notifications(app: &TestApp, cookie: &str) -> Vec<Value> {
+    let response = app
+        .get("/api/notifications")
+        .header("cookie", cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    json(response).await.as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn shared_notes_are_open_to_comments_and_mentions() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let (owner, commenter, viewer, stranger) = (
+        app.user("Ann"),
+        app.user("Bob"),
+        app.user("Cat"),
+        app.user("Dan"),
+    );
+    let owner_cookie = app.login(&owner.email).await;
+    let commenter_cookie = app.login(&commenter.email).await;
+    let viewer_cookie = app.login(&viewer.email).await;
+    let stranger_cookie = app.login(&stranger.email).await;
+
+    let response = app
+        .post(
+            "/api/notes",
+            json!({ "title": "Offsite", "content": "Thursday" }),
+        )
+        .header("cookie", &owner_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let note = json(response).await["id"].as_str().unwrap().to_string();
+
+    // Nobody but the owner can be mentioned before the note is shared.
+    let body = format!("Does Thursday work, @{}?", commenter.email);
+    assert_eq!(
+        comment(&app, &owner_cookie, &note, &body).await,
+        StatusCode::CREATED
+    );
+    assert!(notifications(&app, &commenter_cookie).await.is_empty());
+    assert_eq!(
+        comment(&app, &commenter_cookie, &note, "Let me see").await,
+        StatusCode::NOT_FOUND
+    );
+
+    share(&app, &owner_cookie, &note, &commenter.email, "commenter").await;
+    share(&app, &owner_cookie, &note, &viewer.email, "viewer").await;
+
+    assert_eq!(
+        comment(&app, &owner_cookie, &note, &body).await,
+        StatusCode::CREATED
+    );
+    let received = notifications(&app, &commenter_cookie).await;
+    assert_eq!(received.len(), 1, "{received:?}");
+    assert_eq!(received[0]["kind"], "mention");
+    assert_eq!(received[0]["note_id"], note.as_str());
+    assert_eq!(received[0]["data"]["author_name"], "Ann");
+
+    // The commenter comments and reads along, but does not edit the note.
+    assert_eq!(
+        comment(&app, &commenter_cookie, &note, "It does").await,
+        StatusCode::CREATED
+    );
+    let response = app
+        .get(&format!("/api/notes/{note}/comments"))
+        .header("cookie", &commenter_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await.as_array().unwrap().len(), 3);
+    let response = app
+        .client
+        .patch(app.url(&format!("/api/notes/{note}")))
+        .header("cookie", &commenter_cookie)
+        .header("content-type", "application/json")
+        .body(json!({ "content": "Friday" }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // The viewer reads the note and its comments, but cannot comment.
+    let response = app
+        .get(&format!("/api/notes/{note}"))
+        .header("cookie", &viewer_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["content"], "Thursday");
+    assert_eq!(
+        comment(&app, &viewer_cookie, &note, "Looks good").await,
+        StatusCode::FORBIDDEN
+    );
+
+    // Everyone else still cannot find it.
+    let response = app
+        .get(&format!("/api/notes/{note}/comments"))
+        .header("cookie", &stranger_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Once it is no longer shared, mentions stop too.
+    let response = app
+        .delete(&format!("/api/notes/{note}/shares/{}", commenter.id))
+        .header("cookie", &owner_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        comment(
+            &app,
+            &owner_cookie,
+            &note,
+            &format!("@{}?", commenter.email)
+        )
+        .await,
+        StatusCode::CREATED
+    );
+    assert_eq!(notifications(&app, &commenter_cookie).await.len(), 1);
+}
@@ -0,0 +1,244 @@
+//! Setup shared by the tests that use the Postgres database from
+//! `DATABASE_URL`. They are skipped when it is not set.
+#![allow(dead_code)]
+
+use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use backend::auth::password::{self, HashParams};
+use backend::config::Config;
+use backend::migrations;
+use backend::models::user::{Locale, NewUser, User};
+use backend::routes;
+use backend::schema::users;
+use backend::state::AppState;
+use diesel::prelude::*;
+use openidconnect::reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub const PASSWORD: &str = "correct horse battery staple 42";
+
+/// Cheap enough that tests do not wait for hashing.
+pub const FAST_HASH: HashParams = HashParams {
+    memory_kib: 1024,
+    iterations: 1,
+    parallelism: 1,
+};
+
+pub fn database_url() -> Option<String> {
+    let url = env::var("DATABASE_URL").ok();
+    if url.is_none() {
+        eprintln!("skipped: DATABASE_URL is not set");
+    }
+    url
+}
+
+/// Connects to the database and brings its schema up to date.
+pub fn connect() -> Option<PgConnection> {
+    let url = database_url()?;
+    let mut conn = PgConnection::establish(&url).expect("cannot connect to DATABASE_URL");
+    migrations::run_pending(&mut conn).expect("cannot apply migrations");
+    Some(conn)
+}
+
+/// A fresh, empty directory.
+pub fn temp_dir(purpose: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("notes-{purpose}-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A verified user with a unique address and [`PASSWORD`].
+pub fn create_user(conn: &mut PgConnection, name: &str) -> User {
+    let email = format!("{}-{}@example.com", name.to_lowercase(), Uuid::new_v4());
+    create_user_with(conn, name, &email, &FAST_HASH)
+}
+
+pub fn create_user_with(
+    conn: &mut PgConnection,
+    name: &str,
+    email: &str,
+    params: &HashParams,
+) -> User {
+    diesel::insert_into(users::table)
+        .values(NewUser {
+            name: name.into(),
+            email: email.into(),
+            verified: true,
+            password: password::hash(PASSWORD, params).unwrap(),
+            verification_token: None,
+            token_expires_at: None,
+            locale: Locale::En,
+            time_zone: "UTC".into(),
+        })
+        .returning(User::as_returning())
+        .get_result(conn)
+        .unwrap()
+}
+
+/// The whole API, served on a random port, with emails written to
+/// `mail_dir`.
+pub struct TestApp {
+    pub addr: SocketAddr,
+    pub state: AppState,
+    pub config: Config,
+    pub client: Client,
+    pub mail_dir: PathBuf,
+}
+
+impl TestApp {
+    pub async fn start() -> Option<Self> {
+        Self::with_settings("").await
+    }
+
+    /// Starts the API with extra settings, given as TOML, on top of the
+    /// test defaults.
+    pub async fn with_settings(settings: &str) -> Option<Self> {
+        // Only for the migrations; the app has its own pool.
+        connect()?;
+        let dir = temp_dir("app");
+        let mail_dir = dir.join("mail");
+        let config = load_config(&dir, settings);
+        let state = AppState::new(&config).unwrap();
+        let app = routes::router(state.clone(), &config.cors);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap()
+        });
+        Some(Self {
+            addr,
+            state,
+            config,
+            client: Client::new(),
+            mail_dir,
+        })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    pub fn conn(
+        &self,
+    ) -> diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>> {
+        self.state.pool.get().unwrap()
+    }
+
+    pub fn user(&self, name: &str) -> User {
+        create_user(&mut self.conn(), name)
+    }
+
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.client.get(self.url(path))
+    }
+
+    pub fn post(&self, path: &str, body: Value) -> RequestBuilder {
+        self.client
+            .post(self.url(path))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+    }
+
+    pub fn delete(&self, path: &str) -> RequestBuilder {
+        self.client.delete(self.url(path))
+    }
+
+    /// Logs in with [`PASSWORD`] and returns the auth cookies, ready for a
+    /// `Cookie` header.
+    pub async fn login(&self, email: &str) -> String {
+        let response = self
+            .post(
+                "/api/auth/login",
+                json!({ "email": email, "password": PASSWORD }),
+            )
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        cookies(&response)
+    }
+
+    /// The emails written so far, as raw messages.
+    pub fn emails(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.mail_dir) else {
+            return Vec::new();
+        };
+        entries
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect()
+    }
+}
+
+/// The test defaults plus `settings`, from a file in `dir`. The database
+/// comes from `DATABASE_URL`.
+pub fn load_config(dir: &Path, settings: &str) -> Config {
+    let file = dir.join("config.toml");
+    let defaults = format!(
+        r#"
+[auth]
+jwt_secret = "test-secret-that-is-long-enough-to-sign"
+
+[mail]
+transport = "file"
+dir = {mail:?}
+
+[storage]
+dir = {storage:?}
+
+[database]
+pool_max_size = 4
+
+[passwords]
+argon2_memory_kib = {memory}
+argon2_iterations = {iterations}
+argon2_parallelism = {parallelism}
+"#,
+        mail = dir.join("mail"),
+        storage = dir.join("storage"),
+        memory = FAST_HASH.memory_kib,
+        iterations = FAST_HASH.iterations,
+        parallelism = FAST_HASH.parallelism,
+    );
+    std::fs::write(&file, merge(&defaults, settings)).unwrap();
+    Config::load(Some(&file)).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Overrides the tables of `base` with those in `extra`.
+fn merge(base: &str, extra: &str) -> String {
+    let mut base: toml::Table = toml::from_str(base).unwrap();
+    let extra: toml::Table = toml::from_str(extra).unwrap();
+    for (key, value) in extra {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(extra)) => table.extend(extra),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+    base.to_string()
+}
+
+/// The cookies a response sets, as a `Cookie` header value.
+pub fn cookies(response: &Response) -> String {
+    response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|cookie| cookie.split(';').next())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub async fn json(response: Response) -> Value {
+    serde_json::from_str(&response.text().await.unwrap()).unwrap()
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use backend::config::{Config, ConfigError, LogFormat, StorageBackend};
+
+/// The environment is shared by all tests in this binary.
+static ENV: Mutex<()> = Mutex::new(());
+
+/// Variables the tests set, and the required ones the surrounding
+/// environment might provide.
+const VARS: [&str; 9] = [
+    "DATABASE_URL",
+    "JWT_SECRET",
+    "JWT_TTL_MINUTES",
+    "SESSION_TTL_DAYS",
+    "JOB_WORKERS",
+    "LOG_FORMAT",
+    "PASSWORD_MIN_SCORE",
+    "LOGIN_EMAIL_BURST",
+    "STORAGE_DIR",
+];
+
+/// Runs `f` with a config file holding `toml` and only `vars` set among
+/// [`VARS`].
+fn with_env<T>(toml: &str, vars: &[(&str, &str)], f: impl FnOnce(PathBuf) -> T) -> T {
+    let _guard = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let saved: Vec<_> = VARS.iter().map(|var| (var, env::var_os(var))).collect();
+    for var in VARS {
+        env::remove_var(var);
+    }
+    for (var, value) in vars {
+        env::set_var(var, value);
+    }
+    let file = common::temp_dir("config").join("config.toml");
+    std::fs::write(&file, toml).unwrap();
+
+    let result = f(file);
+    for (var, value) in saved {
+        match value {
+            Some(value) => env::set_var(var, value),
+            None => env::remove_var(var),
+        }
+    }
+    result
+}
+
+fn load(toml: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+    with_env(toml, vars, |file| Config::load(Some(&file)))
+}
+
+fn problems(toml: &str, vars: &[(&str, &str)]) -> Vec<String> {
+    match load(toml, vars) {
+        Ok(_) => panic!("expected the configuration to be rejected"),
+        Err(err) => err.problems,
+    }
+}
+
+const MINIMAL: &str = r#"
+[database]
+url = "postgres://localhost/notes"
+
+[auth]
+jwt_secret = "a-secret-of-at-least-thirty-two-bytes"
+"#;
+
+#[test]
+fn falls_back_to_defaults() {
+    let config = load(MINIMAL, &[]).unwrap();
+    assert_eq!(config.database.url, "postgres://localhost/notes");
+    assert_eq!(config.server.bind_addr, ([0, 0, 0, 0], 3000).into());
+    assert_eq!(config.server.public_url, "http://localhost:3000");
+    assert_eq!(config.log.format, LogFormat::Pretty);
+    assert_eq!(config.jobs.workers, 4);
+    assert_eq!(config.limits.login_per_email.burst, 5);
+    assert_eq!(config.passwords.min_score, 3);
+    assert!(config.oidc.is_none());
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let toml = format!(
+        "{MINIMAL}
+[jobs]
+workers = 2
+
+[log]
+format = \"json\"
+
+[limits]
+login_email_burst = 8
+"
+    );
+    let config = load(&toml, &[]).unwrap();
+    assert_eq!(config.jobs.workers, 2);
+    assert_eq!(config.log.format, LogFormat::Json);
+    assert_eq!(config.limits.login_per_email.burst, 8);
+
+    let config = load(
+        &toml,
+        &[
+            ("JOB_WORKERS", "7"),
+            ("LOG_FORMAT", "pretty"),
+            ("JWT_SECRET", "from-env-and-at-least-thirty-two-bytes"),
+            // Empty variables count as unset.
+            ("LOGIN_EMAIL_BURST", ""),
+        ],
+    )
+    .unwrap();
+    assert_eq!(config.jobs.workers, 7);
+    assert_eq!(config.log.format, LogFormat::Pretty);
+    assert_eq!(
+        config.auth.jwt_secret,
+        "from-env-and-at-least-thirty-two-bytes"
+    );
+    assert_eq!(config.limits.login_per_email.burst, 8);
+}
+
+#[test]
+fn rejects_unknown_settings() {
+    let toml = format!(
+        "{MINIMAL}
+[server]
+bind_adress = \"127.0.0.1:8080\"
+
+[caching]
+enabled = true
+"
+    );
+    let problems = problems(&toml, &[]);
+    assert_eq!(problems.len(), 2, "{problems:?}");
+    assert!(
+        problems[0].starts_with("`caching.enabled` in "),
+        "{problems:?}"
+    );
+    assert!(problems[0].ends_with(": unknown setting"), "{problems:?}");
+    assert!(
+        problems[1].starts_with("`server.bind_adress` in "),
+        "{problems:?}"
+    );
+}
+
+#[test]
+fn reports_every_problem_at_once() {
+    let toml = r#"
+[jobs]
+workers = "many"
+"#;
+    let problems = problems(toml, &[("LOG_FORMAT", "xml"), ("PASSWORD_MIN_SCORE", "9")]);
+    let expected = [
+        "LOG_FORMAT = \"xml\": expected `pretty` or `json`",
+        "DATABASE_URL (or `database.url` in ",
+        "JWT_SECRET (or `auth.jwt_secret` in ",
+        "`jobs.workers` in ",
+        "PASSWORD_MIN_SCORE must be between 0 and 4",
+    ];
+    assert_eq!(problems.len(), expected.len(), "{problems:?}");
+    for start in expected {
+        assert!(
+            problems.iter().any(|problem| problem.starts_with(start)),
+            "{start:?} missing from {problems:?}"
+        );
+    }
+}
+
+#[test]
+fn rejects_short_secrets_and_negative_lifetimes() {
+    let toml = format!(
+        "{MINIMAL}
+[accounts]
+export_ttl_hours = 0
+deletion_grace_days = -1
+"
+    );
+    let problems = problems(
+        &toml,
+        &[
+            ("JWT_SECRET", "a"),
+            ("JWT_TTL_MINUTES", "-15"),
+            ("SESSION_TTL_DAYS", "-30"),
+        ],
+    );
+    assert_eq!(
+        problems,
+        [
+            "JWT_SECRET must be at least 32 bytes long",
+            "JWT_TTL_MINUTES must be at least 1",
+            "SESSION_TTL_DAYS must be at least 1",
+            "EXPORT_TTL_HOURS must be at least 1",
+            "ACCOUNT_DELETION_GRACE_DAYS must be at least 0",
+        ]
+    );
+}
+
+#[test]
+fn command_line_tools_need_only_the_database() {
+    let toml = r#"
+[database]
+url = "postgres://localhost/notes"
+
+[mail]
+transport = "carrier-pigeon"
+
+[caching]
+enabled = true
+"#;
+    let config = with_env(toml, &[("STORAGE_DIR", "/srv/blobs")], |file| {
+        Config::load_cli(Some(&file))
+    })
+    .unwrap();
+    assert_eq!(config.database.url, "postgres://localhost/notes");
+    let StorageBackend::Fs { dir } = config.storage;
+    assert_eq!(dir, PathBuf::from("/srv/blobs"));
+
+    let problems = with_env("", &[], |file| Config::load_cli(Some(&file)))
+        .err()
+        .unwrap()
+        .problems;
+    assert_eq!(problems.len(), 1, "{problems:?}");
+    assert!(problems[0].starts_with("DATABASE_URL"), "{problems:?}");
+}
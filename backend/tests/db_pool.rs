@@ -0,0 +1,210 @@
+//! Checks that database work never blocks the async runtime, using the
+//! Postgres database from `DATABASE_URL`. Skipped when it is not set.
+
+use std::env;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use backend::db::{self, DbPool, PoolConfig};
+use backend::error::{AppError, AppResult};
+use diesel::{sql_query, RunQueryDsl};
+
+fn pool(config: PoolConfig) -> Option<DbPool> {
+    let Ok(url) = env::var("DATABASE_URL") else {
+        eprintln!("skipped: DATABASE_URL is not set");
+        return None;
+    };
+    Some(db::build_pool(&url, &config).expect("cannot connect to DATABASE_URL"))
+}
+
+async fn sleep_in_db(pool: &DbPool, seconds: f64) -> AppResult<()> {
+    db::run(pool, move |conn| {
+        sql_query(format!("SELECT pg_sleep({seconds})")).execute(conn)
+    })
+    .await?;
+    Ok(())
+}
+
+async fn slow(State(pool): State<DbPool>) -> AppResult<StatusCode> {
+    sleep_in_db(&pool, 0.5).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn ping() -> impl IntoResponse {
+    "pong"
+}
+
+async fn serve(pool: DbPool) -> SocketAddr {
+    let app = Router::new()
+        .route("/slow", get(slow))
+        .route("/ping", get(ping))
+        .with_state(pool);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// A single runtime thread serves everything: if queries ran on it, the
+/// ping requests would wait for the slow ones.
+#[tokio::test(flavor = "current_thread")]
+async fn handlers_stay_responsive_while_queries_block() {
+    let Some(pool) = pool(PoolConfig {
+        max_size: 4,
+        connection_timeout: Duration::from_secs(10),
+        ..PoolConfig::default()
+    }) else {
+        return;
+    };
+    let addr = serve(pool).await;
+    let client = reqwest_client();
+
+    // Far more slow requests than connections, so most of them queue for one.
+    let slow_requests: Vec<_> = (0..16)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client
+                    .get(format!("http://{addr}/slow"))
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+            })
+        })
+        .collect();
+    let begin = Instant::now();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(
+        begin.elapsed() < Duration::from_millis(200),
+        "the runtime stalled for {:?} while queries were starting",
+        begin.elapsed()
+    );
+
+    for _ in 0..10 {
+        let start = Instant::now();
+        let response = client
+            .get(format!("http://{addr}/ping"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            start.elapsed() < Duration::from_millis(200),
+            "ping took {:?} while queries were running",
+            start.elapsed()
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    for request in slow_requests {
+        assert_eq!(request.await.unwrap(), StatusCode::NO_CONTENT);
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn timers_keep_firing_under_concurrent_queries() {
+    let Some(pool) = pool(PoolConfig {
+        max_size: 2,
+        connection_timeout: Duration::from_secs(10),
+        ..PoolConfig::default()
+    }) else {
+        return;
+    };
+
+    // The schedule starts before the queries do, so time spent running them
+    // on this thread shows up as lateness, however early that happens.
+    let begin = tokio::time::Instant::now();
+    let queries: Vec<_> = (0..8)
+        .map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { sleep_in_db(&pool, 0.25).await })
+        })
+        .collect();
+
+    // The queries take a second in total; a blocked runtime would delay the
+    // ticks by that much.
+    let period = Duration::from_millis(20);
+    let mut worst = Duration::ZERO;
+    for i in 1..=40 {
+        let due = begin + period * i;
+        tokio::time::sleep_until(due).await;
+        worst = worst.max(due.elapsed());
+    }
+    assert!(
+        worst < Duration::from_millis(100),
+        "a tick was {worst:?} late"
+    );
+
+    for query in queries {
+        query.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn exhausted_pool_reports_unavailable() {
+    let Some(pool) = pool(PoolConfig {
+        max_size: 1,
+        min_idle: Some(1),
+        connection_timeout: Duration::from_millis(300),
+        ..PoolConfig::default()
+    }) else {
+        return;
+    };
+
+    let busy = {
+        let pool = pool.clone();
+        tokio::spawn(async move { sleep_in_db(&pool, 1.0).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let start = Instant::now();
+    let err = sleep_in_db(&pool, 0.0).await.unwrap_err();
+    assert!(matches!(err, AppError::Unavailable(_)), "got {err:?}");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(
+        err.into_response().status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    busy.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn statement_timeout_cancels_slow_queries() {
+    let Some(pool) = pool(PoolConfig {
+        max_size: 1,
+        statement_timeout: Some(Duration::from_millis(100)),
+        ..PoolConfig::default()
+    }) else {
+        return;
+    };
+
+    let err = sleep_in_db(&pool, 2.0).await.unwrap_err();
+    assert!(matches!(err, AppError::Internal(_)), "got {err:?}");
+    sleep_in_db(&pool, 0.0).await.unwrap();
+}
+
+#[tokio::test]
+async fn health_check_reports_pool_state() {
+    let Some(pool) = pool(PoolConfig {
+        max_size: 3,
+        ..PoolConfig::default()
+    }) else {
+        return;
+    };
+
+    let status = db::health_check(&pool).await.unwrap();
+    assert_eq!(status.max_size, 3);
+    assert!(status.connections >= 1);
+    assert!(status.idle <= status.connections);
+}
+
+fn reqwest_client() -> openidconnect::reqwest::Client {
+    openidconnect::reqwest::Client::new()
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use backend::mail::{Email, SendEmail};
+use backend::schema::{jobs, users};
+use common::{json, TestApp, PASSWORD};
+use diesel::prelude::*;
+use openidconnect::reqwest::{Response, StatusCode};
+use serde_json::{json, Value};
+
+/// The page linked from the last queued email to `to`, and its token.
+fn queued_link(app: &TestApp, to: &str) -> (String, String) {
+    let payloads: Vec<Value> = jobs::table
+        .filter(jobs::kind.eq("email"))
+        .order(jobs::created_at.desc())
+        .select(jobs::payload)
+        .load(&mut app.conn())
+        .unwrap();
+    let email = payloads
+        .into_iter()
+        .map(|payload| serde_json::from_value::<SendEmail>(payload).unwrap())
+        .find(|email| email.to == to)
+        .unwrap_or_else(|| panic!("no email queued for {to}"));
+    let link = match email.email {
+        Email::EmailChangeNotice { cancel_link, .. } => cancel_link,
+        Email::EmailChangeConfirmation { link, .. } => link,
+        other => panic!("unexpected email {other:?}"),
+    };
+    let path = link.strip_prefix(&app.config.server.public_url).unwrap();
+    let (page, token) = path.split_once("?token=").unwrap();
+    (page.to_string(), token.to_string())
+}
+
+/// What the page at a link does with its token.
+async fn submit(app: &TestApp, action: &str, token: &str) -> Response {
+    app.post(
+        &format!("/api/account/email/{action}"),
+        json!({ "token": token }),
+    )
+    .send()
+    .await
+    .unwrap()
+}
+
+fn stored_email(app: &TestApp, user_id: uuid::Uuid) -> String {
+    users::table
+        .find(user_id)
+        .select(users::email)
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+async fn request_change(app: &TestApp, cookie: &str, new_email: &str) {
+    let response = app
+        .post(
+            "/api/account/email",
+            json!({ "new_email": new_email, "password": PASSWORD }),
+        )
+        .header("cookie", cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn switches_only_once_the_new_address_is_confirmed() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Moving");
+    let cookie = app.login(&user.email).await;
+    let new_email = format!("moved-{}@example.com", uuid::Uuid::new_v4());
+
+    request_change(&app, &cookie, &new_email).await;
+    assert_eq!(stored_email(&app, user.id), user.email);
+    // The old address is told, and can still be used to log in.
+    queued_link(&app, &user.email);
+    app.login(&user.email).await;
+
+    let response = submit(&app, "confirm", "not-the-token").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(stored_email(&app, user.id), user.email);
+
+    let (page, token) = queued_link(&app, &new_email);
+    assert_eq!(page, "/confirm-email");
+    // Only the page's request counts; opening an API URL changes nothing.
+    let response = app
+        .get(&format!("/api/account/email/confirm?token={token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(stored_email(&app, user.id), user.email);
+
+    let response = submit(&app, "confirm", &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["email"], new_email);
+    assert_eq!(stored_email(&app, user.id), new_email);
+    app.login(&new_email).await;
+
+    // The link works once.
+    let response = submit(&app, "confirm", &token).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn the_old_address_can_cancel_the_change() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Hijacked");
+    let cookie = app.login(&user.email).await;
+    let new_email = format!("thief-{}@example.com", uuid::Uuid::new_v4());
+
+    request_change(&app, &cookie, &new_email).await;
+    let (page, cancel) = queued_link(&app, &user.email);
+    assert_eq!(page, "/cancel-email-change");
+    let (_, confirm) = queued_link(&app, &new_email);
+
+    let response = submit(&app, "cancel", &cancel).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = submit(&app, "confirm", &confirm).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(stored_email(&app, user.id), user.email);
+}
@@ -0,0 +1,186 @@
+//! Sends every email in every locale through the file transport and
+//! compares the written messages with the snapshots in `tests/snapshots`.
+//! After changing a template, review the differences with `cargo insta
+//! review`.
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use backend::config::{MailConfig, MailTransport};
+use backend::mail::{Email, Mailer};
+use backend::metrics::Metrics;
+use backend::models::user::Locale;
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+use uuid::Uuid;
+
+const LINK: &str = "https://notes.example.com/link?token=abc123";
+
+fn emails() -> Vec<Email> {
+    let time = Utc.with_ymd_and_hms(2026, 10, 18, 21, 30, 0).unwrap();
+    vec![
+        Email::Verification {
+            name: "Ann".into(),
+            link: LINK.into(),
+            valid_hours: 48,
+        },
+        Email::PasswordReset {
+            name: "Ann".into(),
+            link: LINK.into(),
+            valid_minutes: 60,
+        },
+        Email::EmailChangeNotice {
+            name: "Ann".into(),
+            new_email: "ann@new.example.com".into(),
+            cancel_link: LINK.into(),
+        },
+        Email::EmailChangeConfirmation {
+            name: "Ann".into(),
+            link: LINK.into(),
+            valid_hours: 24,
+        },
+        Email::LoginAlert {
+            name: "Ann".into(),
+            time,
+            ip_address: Some("203.0.113.7".into()),
+            user_agent: None,
+        },
+        Email::AccountLocked {
+            name: "Ann".into(),
+            until: time,
+        },
+        Email::DeletionScheduled {
+            name: "Ann".into(),
+            deletion_at: time,
+        },
+        Email::ExportReady {
+            name: "Ann".into(),
+            link: LINK.into(),
+            valid_hours: 72,
+        },
+        Email::ShareInvitation {
+            inviter: "Bob <script>".into(),
+            note_title: "Groceries & more".into(),
+            link: LINK.into(),
+        },
+        Email::Reminder {
+            name: "Ann".into(),
+            note_title: String::new(),
+            link: LINK.into(),
+            remind_at: time,
+        },
+        Email::Mention {
+            name: "Ann".into(),
+            author: "Bob".into(),
+            note_title: "Trip".into(),
+            comment: "@ann@example.com can you book\nthe <train>?".into(),
+            link: LINK.into(),
+        },
+    ]
+}
+
+fn mailer(dir: PathBuf) -> Mailer {
+    let config = MailConfig {
+        transport: MailTransport::File { dir },
+        from: "NotesApp <noreply@notes.example.com>".into(),
+    };
+    Mailer::new(&config, Arc::new(Metrics::default())).unwrap()
+}
+
+/// Replaces what differs between runs: the date and the MIME boundaries.
+fn normalize(message: &str) -> String {
+    let mut message = message.replace("\r\n", "\n");
+    let boundaries: Vec<String> = message
+        .split("boundary=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .map(str::to_string)
+        .collect();
+    for (i, boundary) in boundaries.iter().enumerate() {
+        message = message.replace(boundary, &format!("BOUNDARY-{i}"));
+    }
+    message
+        .lines()
+        .map(|line| {
+            if line.starts_with("Date: ") {
+                "Date: [date]"
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[tokio::test]
+async fn renders_every_email_in_every_locale() {
+    for locale in [Locale::En, Locale::De] {
+        for email in emails() {
+            let dir = env::temp_dir().join(format!("notes-emails-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            mailer(dir.clone())
+                .send("ann@example.com", locale, Tz::UTC, &email)
+                .await
+                .unwrap();
+
+            let files: Vec<_> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            assert_eq!(files.len(), 1, "expected one email in {dir:?}");
+            let message = std::fs::read_to_string(&files[0]).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            insta::assert_snapshot!(
+                format!("{}_{locale}", email.template()),
+                normalize(&message)
+            );
+        }
+    }
+}
+
+#[test]
+fn escapes_html_but_not_text() {
+    let email = Email::ShareInvitation {
+        inviter: "Bob <script>".into(),
+        note_title: "Groceries & more".into(),
+        link: LINK.into(),
+    };
+    let rendered = backend::mail::render(Locale::En, Tz::UTC, &email).unwrap();
+    assert_eq!(rendered.subject, "Bob <script> shared a note with you");
+    assert!(rendered
+        .text
+        .contains("Bob <script> shared the note \"Groceries & more\""));
+    assert!(rendered.html.contains("Bob &lt;script&gt;"));
+    assert!(rendered.html.contains("Groceries &amp; more"));
+    assert!(!rendered.html.contains("<script>"));
+}
+
+#[test]
+fn formats_dates_for_the_locale() {
+    let email = Email::AccountLocked {
+        name: "Ann".into(),
+        until: Utc.with_ymd_and_hms(2026, 3, 5, 8, 0, 0).unwrap(),
+    };
+    let en = backend::mail::render(Locale::En, Tz::UTC, &email).unwrap();
+    assert!(en.text.contains("until March 5, 2026 at 08:00 UTC."));
+    let de = backend::mail::render(Locale::De, Tz::UTC, &email).unwrap();
+    assert!(de
+        .text
+        .contains("bis zum 05.03.2026 um 08:00 UTC gesperrt."));
+}
+
+#[test]
+fn formats_dates_in_the_time_zone() {
+    let email = Email::AccountLocked {
+        name: "Ann".into(),
+        until: Utc.with_ymd_and_hms(2026, 7, 1, 22, 30, 0).unwrap(),
+    };
+    let berlin = backend::mail::render(Locale::De, Tz::Europe__Berlin, &email).unwrap();
+    assert!(berlin
+        .text
+        .contains("bis zum 02.07.2026 um 00:30 CEST gesperrt."));
+    let new_york = backend::mail::render(Locale::En, Tz::America__New_York, &email).unwrap();
+    assert!(new_york.text.contains("until July 1, 2026 at 18:30 EDT."));
+}
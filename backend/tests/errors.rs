@@ -0,0 +1,202 @@
+//! Checks that errors, including malformed requests rejected before a
+//! handler runs, reach clients as problem details.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::DefaultBodyLimit;
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use backend::error::AppError;
+use backend::extract::{Json, Path, Query};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors};
+
+async fn problem(response: Response) -> (StatusCode, Value) {
+    let status = response.status();
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "application/problem+json",
+        "for {status}"
+    );
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn describes_errors_as_problems() {
+    let (status, body) = problem(AppError::NotFound.into_response()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        body,
+        json!({
+            "type": "urn:notesapp:problem:not-found",
+            "title": "Not found",
+            "status": 404,
+            "detail": "not found",
+        })
+    );
+
+    let (status, body) = problem(AppError::Conflict("the tag exists".into()).into_response()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["type"], "urn:notesapp:problem:conflict");
+    assert_eq!(body["detail"], "the tag exists");
+
+    let (status, body) = problem(AppError::InvalidCredentials.into_response()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["type"], "urn:notesapp:problem:invalid-credentials");
+}
+
+#[tokio::test]
+async fn hides_internal_details() {
+    let (status, body) =
+        problem(AppError::Internal("connection to 10.0.0.5 refused".into()).into_response()).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["type"], "urn:notesapp:problem:internal");
+    assert_eq!(body["detail"], "internal server error");
+}
+
+#[tokio::test]
+async fn tells_when_to_retry() {
+    let response = AppError::TooManyRequests {
+        message: "too many login attempts".into(),
+        retry_after: Duration::from_millis(2500),
+    }
+    .into_response();
+    assert_eq!(response.headers()[RETRY_AFTER], "3");
+    let (status, body) = problem(response).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["detail"], "too many login attempts");
+}
+
+#[tokio::test]
+async fn lists_validation_errors_by_field() {
+    let mut errors = ValidationErrors::new();
+    let mut too_short = ValidationError::new("password_too_short");
+    too_short.message = Some(Cow::from("use at least 10 characters"));
+    errors.add("password", too_short);
+    let mut length = ValidationError::new("length");
+    length.add_param(Cow::from("max"), &200);
+    errors.add("title", length);
+
+    let (status, body) = problem(AppError::Validation(errors).into_response()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body,
+        json!({
+            "type": "urn:notesapp:problem:validation-failed",
+            "title": "The request is invalid",
+            "status": 400,
+            "detail": "password: use at least 10 characters; title: must have a length of at most 200",
+            "errors": {
+                "password": [{ "code": "password_too_short", "message": "use at least 10 characters" }],
+                "title": [{ "code": "length", "message": "must have a length of at most 200" }],
+            },
+        })
+    );
+}
+
+#[derive(Deserialize)]
+struct Rename {
+    #[allow(dead_code)]
+    title: String,
+}
+
+async fn rename(
+    Path(_id): Path<Uuid>,
+    Query(_query): Query<HashMap<String, u32>>,
+    Json(_payload): Json<Rename>,
+) -> Json<Value> {
+    Json(json!({ "ok": true }))
+}
+
+async fn serve() -> SocketAddr {
+    let app = Router::new()
+        .route("/notes/:id", post(rename))
+        .method_not_allowed_fallback(|| async { AppError::MethodNotAllowed })
+        .layer(DefaultBodyLimit::max(64));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// Sends a request and returns the status and the problem in the response.
+async fn send(request: openidconnect::reqwest::RequestBuilder) -> (StatusCode, Value) {
+    let response = request.send().await.unwrap();
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json",
+        "for {status}"
+    );
+    (
+        status,
+        serde_json::from_str(&response.text().await.unwrap()).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn rejects_malformed_requests_with_problems() {
+    let addr = serve().await;
+    let client = openidconnect::reqwest::Client::new();
+    let url = format!("http://{addr}/notes/{}", Uuid::new_v4());
+    let post_json = |url: &str, body: &str| {
+        client
+            .post(url)
+            .header("content-type", "application/json")
+            .body(body.to_string())
+    };
+
+    let response = post_json(&url, r#"{"title":"Trip"}"#).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (status, body) = send(post_json(&url, "{")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["type"], "urn:notesapp:problem:bad-request");
+    assert!(body["detail"].as_str().unwrap().contains("JSON"));
+
+    let (status, body) = send(post_json(&url, r#"{"name":"Trip"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["detail"]
+        .as_str()
+        .unwrap()
+        .contains("missing field `title`"));
+
+    let (status, body) = send(client.post(&url).body(r#"{"title":"Trip"}"#)).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(body["type"], "urn:notesapp:problem:unsupported-media-type");
+
+    let long = format!(r#"{{"title":"{}"}}"#, "x".repeat(100));
+    let (status, body) = send(post_json(&url, &long)).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["type"], "urn:notesapp:problem:payload-too-large");
+
+    let (status, body) = send(post_json(
+        &format!("http://{addr}/notes/not-a-uuid"),
+        r#"{"title":"Trip"}"#,
+    ))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["detail"].as_str().unwrap().contains("UUID"));
+
+    let (status, _) = send(post_json(
+        &format!("{url}?limit=many"),
+        r#"{"title":"Trip"}"#,
+    ))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = send(client.get(&url)).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(body["type"], "urn:notesapp:problem:method-not-allowed");
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{json, TestApp};
+use openidconnect::reqwest::StatusCode;
+use serde_json::{json, Value};
+
+async fn readyz(app: &TestApp) -> (StatusCode, Value) {
+    let response = app.get("/readyz").send().await.unwrap();
+    (response.status(), json(response).await)
+}
+
+#[tokio::test]
+async fn ready_when_every_dependency_is() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let (status, body) = readyz(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    for check in ["database", "migrations", "storage"] {
+        assert_eq!(body["checks"][check], json!({ "status": "ok" }), "{check}");
+    }
+}
+
+#[tokio::test]
+async fn not_ready_when_a_critical_check_fails() {
+    // Blobs cannot be written below a plain file.
+    let blocker = common::temp_dir("readyz").join("not-a-directory");
+    std::fs::write(&blocker, "").unwrap();
+    let settings = format!(
+        "[storage]\ndir = {:?}\n",
+        blocker.join("storage").to_str().unwrap()
+    );
+    let Some(app) = TestApp::with_settings(&settings).await else {
+        return;
+    };
+
+    let (status, body) = readyz(&app).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "not_ready");
+    // Why it failed stays in the log.
+    assert_eq!(body["checks"]["storage"], json!({ "status": "failed" }));
+    assert_eq!(body["checks"]["database"], json!({ "status": "ok" }));
+}
+
+#[tokio::test]
+async fn stays_ready_without_mail() {
+    let Some(app) = TestApp::with_settings(
+        r#"
+[mail]
+transport = "smtp"
+smtp_url = "smtp://127.0.0.1:1"
+"#,
+    )
+    .await
+    else {
+        return;
+    };
+
+    let (status, body) = readyz(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["mail"], json!({ "status": "failed" }));
+}
+
+#[tokio::test]
+async fn not_ready_once_shutting_down() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    app.state.shutdown.trigger();
+
+    let (status, body) = readyz(&app).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "shutting_down");
+    assert_eq!(body["checks"]["database"], json!({ "status": "ok" }));
+    let response = app.get("/healthz").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use backend::config::JobsConfig;
+use backend::error::{AppError, AppResult};
+use backend::jobs::{self, worker, BackgroundJob, Registry};
+use backend::mail::{self, Email};
+use backend::models::job::{Job, JobStatus};
+use backend::models::user::{Locale, UserRole};
+use backend::schema::{jobs as jobs_table, users};
+use backend::state::AppState;
+use chrono::Utc;
+use common::{json, TestApp};
+use diesel::prelude::*;
+use openidconnect::reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Jobs the dead handlers were called for.
+static DEAD: Mutex<Vec<Uuid>> = Mutex::new(Vec::new());
+
+/// Takes its time, so a job marked as dead before its handler finished
+/// would be caught.
+async fn record_dead(id: Uuid) {
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    DEAD.lock().unwrap().push(id);
+}
+
+/// Fails on every attempt.
+#[derive(Serialize, Deserialize)]
+struct Failing {
+    id: Uuid,
+}
+
+#[async_trait]
+impl BackgroundJob for Failing {
+    const KIND: &'static str = "test_failing";
+    const MAX_ATTEMPTS: i32 = 3;
+
+    async fn run(&self, _state: &AppState) -> AppResult<()> {
+        Err(AppError::Internal("out of luck".into()))
+    }
+
+    async fn dead(&self, _state: &AppState) {
+        record_dead(self.id).await;
+    }
+}
+
+/// Succeeds, but is found with its lease run out as if its worker died.
+#[derive(Serialize, Deserialize)]
+struct Abandoned {
+    id: Uuid,
+}
+
+#[async_trait]
+impl BackgroundJob for Abandoned {
+    const KIND: &'static str = "test_abandoned";
+    const MAX_ATTEMPTS: i32 = 2;
+
+    async fn run(&self, _state: &AppState) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn dead(&self, _state: &AppState) {
+        record_dead(self.id).await;
+    }
+}
+
+fn start_worker<J: BackgroundJob>(app: &TestApp) {
+    let config = JobsConfig {
+        workers: 1,
+        poll_interval: Duration::from_millis(10),
+        completed_retention: chrono::Duration::days(1),
+    };
+    worker::spawn(&app.state, Registry::default().register::<J>(), &config);
+}
+
+fn load(app: &TestApp, id: Uuid) -> Job {
+    jobs_table::table
+        .find(id)
+        .select(Job::as_select())
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+/// Waits until the job is no longer running and has made `attempts`.
+async fn settled(app: &TestApp, id: Uuid, attempts: i32) -> Job {
+    for _ in 0..500 {
+        let job = load(app, id);
+        if job.attempts == attempts && job.status != JobStatus::Running {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job {id} did not settle: {:?}", load(app, id));
+}
+
+#[tokio::test]
+async fn failing_jobs_end_up_dead() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let marker = Uuid::new_v4();
+    let id = jobs::enqueue(&mut app.conn(), &Failing { id: marker }).unwrap();
+    start_worker::<Failing>(&app);
+
+    for attempt in 1..Failing::MAX_ATTEMPTS {
+        let job = settled(&app, id, attempt).await;
+        assert_eq!(job.status, JobStatus::Pending);
+        assert_eq!(job.last_error.as_deref(), Some("out of luck"));
+        // Backed off; skip the wait.
+        assert!(job.run_at > Utc::now() + chrono::Duration::seconds(9));
+        jobs::retry(&app.state, id).await.unwrap();
+    }
+
+    let job = settled(&app, id, Failing::MAX_ATTEMPTS).await;
+    assert_eq!(job.status, JobStatus::Dead);
+    assert!(job.finished_at.is_some());
+    assert!(DEAD.lock().unwrap().contains(&marker));
+    app.state.shutdown.trigger();
+}
+
+#[tokio::test]
+async fn recovers_jobs_whose_lease_ran_out() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let abandon = |marker: Uuid, attempts: i32| {
+        let id = jobs::enqueue(&mut app.conn(), &Abandoned { id: marker }).unwrap();
+        diesel::update(jobs_table::table.find(id))
+            .set((
+                jobs_table::status.eq(JobStatus::Running),
+                jobs_table::attempts.eq(attempts),
+                jobs_table::locked_until.eq(Utc::now() - chrono::Duration::seconds(1)),
+            ))
+            .execute(&mut app.conn())
+            .unwrap();
+        id
+    };
+    let (retried, last) = (Uuid::new_v4(), Uuid::new_v4());
+    let retried_id = abandon(retried, 1);
+    let last_id = abandon(last, Abandoned::MAX_ATTEMPTS);
+    start_worker::<Abandoned>(&app);
+
+    // Another attempt is left, so it runs again.
+    let job = settled(&app, retried_id, 2).await;
+    assert_eq!(job.status, JobStatus::Completed);
+
+    // The last attempt is used up, so it is not run a third time.
+    let job = settled(&app, last_id, Abandoned::MAX_ATTEMPTS).await;
+    assert_eq!(job.status, JobStatus::Dead);
+    assert!(job.last_error.unwrap().contains("lease ran out"));
+    assert!(DEAD.lock().unwrap().contains(&last));
+    assert!(!DEAD.lock().unwrap().contains(&retried));
+    app.state.shutdown.trigger();
+}
+
+#[tokio::test]
+async fn hides_email_payloads_from_admins() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let admin = app.user("Admin");
+    diesel::update(users::table.find(admin.id))
+        .set(users::role.eq(UserRole::Admin))
+        .execute(&mut app.conn())
+        .unwrap();
+    let cookie = app.login(&admin.email).await;
+    let id = mail::enqueue(
+        &mut app.conn(),
+        "someone@example.com",
+        Locale::En,
+        chrono_tz::UTC,
+        Email::PasswordReset {
+            name: "Someone".into(),
+            link: "http://localhost/reset?token=secret".into(),
+            valid_minutes: 30,
+        },
+    )
+    .unwrap();
+
+    let response = app
+        .get(&format!("/api/admin/jobs/{id}"))
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let job = json(response).await;
+    assert_eq!(job["kind"], "email");
+    assert_eq!(job["payload"], "[redacted]");
+
+    let response = app
+        .get("/api/admin/jobs?kind=email&limit=200")
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("token=secret"));
+    assert!(body.contains(&id.to_string()));
+}
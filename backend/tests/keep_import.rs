@@ -0,0 +1,183 @@
+mod common;
+
+use std::path::Path;
+
+use backend::import::keep::{import_takeout, read_takeout, KeepAttachment, KeepEntry};
+use backend::models::note::NoteColor;
+use backend::schema::{attachments, note_tags, notes, tags};
+use backend::storage::{BlobStore, FsBlobStore};
+use chrono::{TimeZone, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+fn fixtures() -> Vec<KeepEntry> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/keep");
+    read_takeout(&dir).expect("fixtures should parse")
+}
+
+fn entry(title: &str) -> KeepEntry {
+    fixtures()
+        .into_iter()
+        .find(|entry| entry.note.title == title)
+        .unwrap_or_else(|| panic!("no fixture titled {title}"))
+}
+
+#[test]
+fn reads_every_note_json() {
+    let mut titles = fixtures()
+        .into_iter()
+        .map(|entry| entry.note.title)
+        .collect::<Vec<_>>();
+    titles.sort();
+    assert_eq!(titles, ["", "Groceries", "Trip ideas"]);
+}
+
+#[test]
+fn maps_checklists_labels_and_flags() {
+    let user_id = Uuid::new_v4();
+    let entry = entry("Groceries");
+    let note = entry.note.to_new_note(user_id);
+
+    assert_eq!(note.user_id, user_id);
+    assert_eq!(
+        note.content,
+        "- [x] Milk\n- [ ] Eggs\n- [ ] Bread wholegrain"
+    );
+    assert_eq!(note.color, NoteColor::Yellow);
+    assert!(note.pinned);
+    assert!(!note.archived);
+    assert_eq!(entry.note.tags(), ["Home", "Shopping"]);
+    assert_eq!(note.created_at, Utc.timestamp_opt(1702900000, 0).unwrap());
+    assert_eq!(note.updated_at, Utc.timestamp_opt(1703072400, 0).unwrap());
+}
+
+#[test]
+fn maps_text_links_and_missing_created_timestamp() {
+    let entry = entry("Trip ideas");
+    let note = entry.note.to_new_note(Uuid::new_v4());
+
+    assert_eq!(
+        note.content,
+        "Lisbon in spring\nPorto afterwards?\n\n- [Visit Lisboa](https://www.visitlisboa.com/)"
+    );
+    assert_eq!(note.color, NoteColor::Cerulean);
+    assert!(note.archived);
+    assert_eq!(note.created_at, note.updated_at);
+    assert_eq!(note.updated_at.timestamp_subsec_micros(), 123456);
+}
+
+#[test]
+fn resolves_attachments_with_mismatched_extensions() {
+    let entry = entry("Trip ideas");
+    let [image, recording] = &entry.note.attachments[..] else {
+        panic!("expected two attachments");
+    };
+
+    let path = entry
+        .resolve_attachment(image)
+        .expect("image should resolve");
+    assert_eq!(path.file_name().unwrap(), "1a2b3c4d5e.jpg");
+    assert!(entry.resolve_attachment(recording).is_none());
+}
+
+#[test]
+fn only_resolves_attachments_next_to_the_note() {
+    let dir = common::temp_dir("keep");
+    let keep = dir.join("Keep");
+    std::fs::create_dir_all(&keep).unwrap();
+    std::fs::write(dir.join("secret.txt"), "not part of the export").unwrap();
+    std::fs::write(keep.join("photo.jpg"), "jpeg").unwrap();
+    let entry = KeepEntry {
+        path: keep.join("Note.json"),
+        note: fixtures().remove(0).note,
+    };
+    let attachment = |file_path: String| KeepAttachment {
+        file_path,
+        mimetype: "text/plain".into(),
+    };
+
+    assert!(entry
+        .resolve_attachment(&attachment("photo.jpg".into()))
+        .is_some());
+    for path in [
+        "../secret.txt".to_string(),
+        "./../secret.txt".to_string(),
+        "Keep/../../secret.txt".to_string(),
+        dir.join("secret.txt").display().to_string(),
+        "..".to_string(),
+        String::new(),
+    ] {
+        assert_eq!(
+            entry.resolve_attachment(&attachment(path.clone())),
+            None,
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn imports_notes_tags_and_attachments() {
+    let Some(mut conn) = common::connect() else {
+        return;
+    };
+    let user = common::create_user(&mut conn, "Keeper");
+    let store = FsBlobStore::new(common::temp_dir("keep-storage"));
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/keep");
+
+    let summary = import_takeout(&mut conn, &store, user.id, &dir).unwrap();
+    assert_eq!(summary.imported, 2);
+    assert_eq!(summary.skipped_trashed, 1);
+    assert_eq!(summary.attachments, 1);
+    assert_eq!(summary.missing_attachments, ["missing-recording.3gp"]);
+
+    let mut imported: Vec<(String, bool, bool)> = notes::table
+        .filter(notes::user_id.eq(user.id))
+        .select((notes::title, notes::pinned, notes::archived))
+        .load(&mut conn)
+        .unwrap();
+    imported.sort();
+    assert_eq!(
+        imported,
+        [
+            ("Groceries".to_string(), true, false),
+            ("Trip ideas".to_string(), false, true),
+        ]
+    );
+
+    let mut labels: Vec<(String, String)> = notes::table
+        .inner_join(note_tags::table.inner_join(tags::table))
+        .filter(notes::user_id.eq(user.id))
+        .select((notes::title, tags::name))
+        .load(&mut conn)
+        .unwrap();
+    labels.sort();
+    assert_eq!(
+        labels,
+        [
+            ("Groceries".to_string(), "Home".to_string()),
+            ("Groceries".to_string(), "Shopping".to_string()),
+            ("Trip ideas".to_string(), "Travel".to_string()),
+        ]
+    );
+
+    let (file_name, mime_type, size_bytes, storage_key): (String, String, i64, String) =
+        attachments::table
+            .inner_join(notes::table)
+            .filter(notes::user_id.eq(user.id))
+            .select((
+                attachments::file_name,
+                attachments::mime_type,
+                attachments::size_bytes,
+                attachments::storage_key,
+            ))
+            .first(&mut conn)
+            .unwrap();
+    assert_eq!(file_name, "1a2b3c4d5e.jpg");
+    assert_eq!(mime_type, "image/jpeg");
+    let data = store.get(&storage_key).unwrap();
+    assert_eq!(data.len() as i64, size_bytes);
+    assert_eq!(
+        data,
+        std::fs::read(dir.join("Takeout/Keep/1a2b3c4d5e.jpg")).unwrap()
+    );
+}
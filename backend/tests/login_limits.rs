@@ -0,0 +1,96 @@
+mod common;
+
+use backend::schema::users;
+use chrono::Utc;
+use common::{json, TestApp, PASSWORD};
+use diesel::prelude::*;
+use openidconnect::reqwest::StatusCode;
+use serde_json::json;
+
+async fn limited_app() -> Option<TestApp> {
+    TestApp::with_settings(
+        r#"
+[limits]
+login_email_burst = 2
+login_email_per_minute = 1
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn password_resets_do_not_use_up_logins() {
+    let Some(app) = limited_app().await else {
+        return;
+    };
+    let user = app.user("Targeted");
+
+    for expected in [
+        StatusCode::ACCEPTED,
+        StatusCode::ACCEPTED,
+        StatusCode::TOO_MANY_REQUESTS,
+    ] {
+        let response = app
+            .post(
+                "/api/auth/password/forgot",
+                json!({ "email": user.email.to_uppercase() }),
+            )
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected);
+    }
+
+    app.login(&user.email).await;
+}
+
+#[tokio::test]
+async fn limits_guessing_two_factor_codes() {
+    let Some(app) = limited_app().await else {
+        return;
+    };
+    let user = app.user("Guarded");
+    diesel::update(users::table.find(user.id))
+        .set((
+            users::totp_secret.eq("JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP"),
+            users::totp_enabled_at.eq(Utc::now()),
+        ))
+        .execute(&mut app.conn())
+        .unwrap();
+    let response = app
+        .post(
+            "/api/auth/login",
+            json!({ "email": user.email, "password": PASSWORD }),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let challenge = json(response).await["challenge_token"].clone();
+
+    for expected in [
+        StatusCode::UNAUTHORIZED,
+        StatusCode::UNAUTHORIZED,
+        StatusCode::TOO_MANY_REQUESTS,
+    ] {
+        let response = app
+            .post(
+                "/api/auth/login/2fa",
+                json!({ "challenge_token": challenge, "recovery_code": "aaaaa-bbbbb" }),
+            )
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected);
+    }
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge, "code": "123456" }),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("retry-after"));
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use backend::metrics::{Metrics, Snapshot};
+
+fn snapshot() -> Snapshot {
+    Snapshot {
+        pool_connections: 3,
+        pool_idle: 1,
+        pool_max_size: 10,
+        queue_depths: vec![("email".into(), 4), ("export".into(), 0)],
+    }
+}
+
+#[test]
+fn renders_the_text_exposition_format() {
+    let metrics = Arc::new(Metrics::default());
+    metrics.record_request("GET", "/api/notes", 200, 0.003);
+    metrics.record_request("GET", "/api/notes", 200, 0.2);
+    metrics.record_request("GET", "/api/notes", 500, 12.0);
+    metrics.record_request("POST", "/api/notes/:id", 404, 0.05);
+    metrics.record_email(true);
+    metrics.record_email(true);
+    metrics.record_email(false);
+    let _stream = metrics.sse_opened();
+
+    insta::assert_snapshot!(metrics.render(&snapshot()));
+}
+
+#[test]
+fn counts_open_streams() {
+    let metrics = Arc::new(Metrics::default());
+    let gauge = |metrics: &Metrics| {
+        metrics
+            .render(&Snapshot::default())
+            .lines()
+            .find_map(|line| line.strip_prefix("notesapp_sse_connections "))
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(gauge(&metrics), "0");
+    let first = metrics.sse_opened();
+    let second = metrics.sse_opened();
+    assert_eq!(gauge(&metrics), "2");
+    drop(first);
+    assert_eq!(gauge(&metrics), "1");
+    drop(second);
+    assert_eq!(gauge(&metrics), "0");
+}
+
+#[test]
+fn escapes_label_values() {
+    let metrics = Metrics::default();
+    metrics.record_request("GET", "/odd\"route\\\n", 200, 0.001);
+    let rendered = metrics.render(&Snapshot::default());
+    assert!(
+        rendered.contains(r#"route="/odd\"route\\\n""#),
+        "{rendered}"
+    );
+}
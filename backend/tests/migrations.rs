@@ -0,0 +1,109 @@
+mod common;
+
+use std::thread;
+
+use backend::migrations::{self, MigrationStatus};
+use diesel::{sql_query, Connection, PgConnection, RunQueryDsl};
+use uuid::Uuid;
+
+/// A database of its own, so reverting migrations does not pull the schema
+/// from under the other tests. Dropped again afterwards.
+struct ScratchDatabase {
+    admin: PgConnection,
+    name: String,
+    url: String,
+}
+
+impl ScratchDatabase {
+    fn create() -> Option<Self> {
+        let base = common::database_url()?;
+        let mut admin = PgConnection::establish(&base).unwrap();
+        let name = format!("notes_migrate_{}", Uuid::new_v4().simple());
+        sql_query(format!("CREATE DATABASE {name}"))
+            .execute(&mut admin)
+            .unwrap();
+        let (server, _) = base.split('?').next().unwrap().rsplit_once('/').unwrap();
+        Some(Self {
+            admin,
+            url: format!("{server}/{name}"),
+            name,
+        })
+    }
+
+    fn connect(&self) -> PgConnection {
+        PgConnection::establish(&self.url).unwrap()
+    }
+}
+
+impl Drop for ScratchDatabase {
+    fn drop(&mut self) {
+        let _ = sql_query(format!(
+            "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+            self.name
+        ))
+        .execute(&mut self.admin);
+    }
+}
+
+fn applied(status: &[MigrationStatus]) -> Vec<&str> {
+    status
+        .iter()
+        .filter(|migration| migration.applied)
+        .map(|migration| migration.name.as_str())
+        .collect()
+}
+
+#[test]
+fn migrates_up_and_down_again() {
+    let Some(db) = ScratchDatabase::create() else {
+        return;
+    };
+    let mut conn = db.connect();
+
+    let status = migrations::status(&mut conn).unwrap();
+    let all: Vec<String> = status.iter().map(|m| m.name.clone()).collect();
+    assert!(all.len() > 2);
+    assert!(applied(&status).is_empty());
+    assert_eq!(migrations::pending(&mut conn).unwrap(), all);
+
+    assert_eq!(migrations::run_pending(&mut conn).unwrap(), all);
+    assert_eq!(applied(&migrations::status(&mut conn).unwrap()), all);
+    assert!(migrations::pending(&mut conn).unwrap().is_empty());
+    assert!(migrations::run_pending(&mut conn).unwrap().is_empty());
+
+    // Newest first.
+    let last_two = &all[all.len() - 2..];
+    let reverted = migrations::revert(&mut conn, 2).unwrap();
+    assert_eq!(reverted, [last_two[1].clone(), last_two[0].clone()]);
+    assert_eq!(migrations::pending(&mut conn).unwrap(), last_two);
+    assert_eq!(
+        applied(&migrations::status(&mut conn).unwrap()),
+        all[..all.len() - 2]
+    );
+
+    // Reverting more than is applied stops at the first migration.
+    let reverted = migrations::revert(&mut conn, all.len() + 5).unwrap();
+    assert_eq!(reverted.len(), all.len() - 2);
+    assert_eq!(reverted.last(), all.first());
+    assert!(applied(&migrations::status(&mut conn).unwrap()).is_empty());
+
+    assert_eq!(migrations::run_pending(&mut conn).unwrap(), all);
+}
+
+#[test]
+fn concurrent_runs_apply_each_migration_once() {
+    let Some(db) = ScratchDatabase::create() else {
+        return;
+    };
+    let total = migrations::status(&mut db.connect()).unwrap().len();
+
+    let runs: Vec<_> = (0..3)
+        .map(|_| {
+            let mut conn = db.connect();
+            thread::spawn(move || migrations::run_pending(&mut conn).unwrap())
+        })
+        .collect();
+    let applied: usize = runs.into_iter().map(|run| run.join().unwrap().len()).sum();
+    assert_eq!(applied, total);
+    assert!(migrations::pending(&mut db.connect()).unwrap().is_empty());
+}
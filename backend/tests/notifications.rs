@@ -0,0 +1,61 @@
+//! Checks that notifications committed by any instance reach the streams of
+//! this one, using the Postgres database from `DATABASE_URL`. Skipped when it
+//! is not set.
+
+use std::env;
+use std::time::Duration;
+
+use backend::notifications::stream::{self, Created, Hub};
+use backend::shutdown::Shutdown;
+use diesel::{sql_query, Connection, PgConnection, RunQueryDsl};
+use tokio::time::{timeout, Instant};
+use uuid::Uuid;
+
+fn database_url() -> Option<String> {
+    let url = env::var("DATABASE_URL").ok();
+    if url.is_none() {
+        eprintln!("skipped: DATABASE_URL is not set");
+    }
+    url
+}
+
+fn notify(conn: &mut PgConnection, payload: &str) {
+    sql_query(format!("SELECT pg_notify('notifications', '{payload}')"))
+        .execute(conn)
+        .unwrap();
+}
+
+#[tokio::test]
+async fn passes_on_notifications_from_postgres() {
+    let Some(url) = database_url() else {
+        return;
+    };
+    let hub = Hub::default();
+    let mut receiver = hub.subscribe();
+    let shutdown = Shutdown::default();
+    stream::spawn_listener(hub, url.clone(), shutdown.clone());
+
+    let created = Created {
+        user_id: Uuid::new_v4(),
+        id: Uuid::new_v4(),
+    };
+    let mut conn = PgConnection::establish(&url).unwrap();
+    // The listener may not have subscribed yet, so keep notifying until it
+    // hears something; malformed payloads must be skipped on the way.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let received = loop {
+        assert!(Instant::now() < deadline, "nothing was passed on");
+        notify(&mut conn, "not a notification");
+        notify(&mut conn, &format!("{} {}", created.user_id, created.id));
+        if let Ok(received) = timeout(Duration::from_millis(500), receiver.recv()).await {
+            break received.unwrap();
+        }
+    };
+    assert_eq!(received, created);
+
+    shutdown.trigger();
+    let finished = shutdown
+        .wait_for_tasks(Instant::now() + Duration::from_secs(5))
+        .await;
+    assert_eq!(finished, 0);
+}
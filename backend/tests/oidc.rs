@@ -0,0 +1,398 @@
+//! Runs the OpenID Connect flow against a mock identity provider served from
+//! the test process.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use backend::auth::oidc::{ExternalIdentity, Oidc, OidcConfig, OidcError, PendingLogin};
+use backend::auth::password;
+use backend::schema::users;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use common::{create_user_with, TestApp, FAST_HASH};
+use diesel::prelude::*;
+use jsonwebtoken::{EncodingKey, Header};
+use openidconnect::reqwest::redirect::Policy;
+use openidconnect::reqwest::{Client, Response};
+use openidconnect::url::Url;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+const CLIENT_ID: &str = "notes";
+const CLIENT_SECRET: &str = "s3cret";
+const REDIRECT_URL: &str = "http://localhost:3000/api/auth/oidc/callback";
+const TOTP_SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+/// What the provider remembers about a code between authorization and the
+/// token request.
+#[derive(Clone)]
+struct Grant {
+    nonce: String,
+    code_challenge: String,
+    email_verified: bool,
+}
+
+#[derive(Clone)]
+struct MockProvider {
+    issuer: String,
+    /// Who logs in at the provider.
+    subject: String,
+    email: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl MockProvider {
+    async fn start() -> Self {
+        Self::start_for("employee-42", "ann@corp.example").await
+    }
+
+    async fn start_for(subject: &str, email: &str) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider = MockProvider {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            subject: subject.into(),
+            email: email.into(),
+            grants: Arc::default(),
+        };
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(provider.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        provider
+    }
+
+    fn client(&self) -> Oidc {
+        Oidc::new(OidcConfig {
+            issuer_url: self.issuer.clone(),
+            client_id: CLIENT_ID.into(),
+            client_secret: Some(CLIENT_SECRET.into()),
+            redirect_url: REDIRECT_URL.into(),
+            post_login_url: "http://localhost:3000/".into(),
+        })
+        .unwrap()
+    }
+
+    /// Plays the user logging in at the provider: checks the authorization
+    /// URL and hands out a code for it.
+    fn authorize(&self, url: &str, email_verified: bool) -> String {
+        let url = Url::parse(url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["redirect_uri"], REDIRECT_URL);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert!(params["scope"].split(' ').any(|scope| scope == "openid"));
+
+        let code = format!("code-{}", self.grants.lock().unwrap().len());
+        self.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                nonce: params["nonce"].clone(),
+                code_challenge: params["code_challenge"].clone(),
+                email_verified,
+            },
+        );
+        code
+    }
+}
+
+async fn discovery(State(provider): State<MockProvider>) -> Json<Value> {
+    let issuer = &provider.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+        "jwks_uri": format!("{issuer}/jwks"),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+    }))
+}
+
+async fn jwks() -> Json<Value> {
+    Json(serde_json::from_str(include_str!("fixtures/oidc/jwks.json")).unwrap())
+}
+
+async fn token(
+    State(provider): State<MockProvider>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let invalid_grant = || {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "invalid_grant" })),
+        )
+    };
+    let expected_auth = format!(
+        "Basic {}",
+        STANDARD.encode(format!("{CLIENT_ID}:{CLIENT_SECRET}"))
+    );
+    if headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        != Some(expected_auth.as_str())
+    {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "invalid_client" })),
+        ));
+    }
+
+    let grant = provider
+        .grants
+        .lock()
+        .unwrap()
+        .remove(&form["code"])
+        .ok_or_else(invalid_grant)?;
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+    if challenge != grant.code_challenge || form["redirect_uri"] != REDIRECT_URL {
+        return Err(invalid_grant());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": provider.issuer,
+        "sub": provider.subject,
+        "aud": CLIENT_ID,
+        "iat": now,
+        "exp": now + 300,
+        "nonce": grant.nonce,
+        "email": provider.email,
+        "email_verified": grant.email_verified,
+        "name": "Ann Example",
+    });
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some("test-key".into());
+    let key = EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc/signing_key.pem")).unwrap();
+    let id_token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+
+    Ok(Json(json!({
+        "access_token": "access",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    })))
+}
+
+#[tokio::test]
+async fn completes_code_flow_with_pkce() {
+    let provider = MockProvider::start().await;
+    let oidc = provider.client();
+
+    let request = oidc.authorize().await.unwrap();
+    let code = provider.authorize(&request.url, true);
+    let state = request.pending.csrf_state.clone();
+    let identity = oidc.exchange(&code, &state, request.pending).await.unwrap();
+
+    assert_eq!(
+        identity,
+        ExternalIdentity {
+            issuer: provider.issuer.clone(),
+            subject: "employee-42".into(),
+            email: Some("ann@corp.example".into()),
+            email_verified: true,
+            name: Some("Ann Example".into()),
+            locale: None,
+            time_zone: None,
+        }
+    );
+}
+
+#[tokio::test]
+async fn reports_unverified_email() {
+    let provider = MockProvider::start().await;
+    let oidc = provider.client();
+
+    let request = oidc.authorize().await.unwrap();
+    let code = provider.authorize(&request.url, false);
+    let state = request.pending.csrf_state.clone();
+    let identity = oidc.exchange(&code, &state, request.pending).await.unwrap();
+
+    assert!(!identity.email_verified);
+}
+
+#[tokio::test]
+async fn rejects_mismatched_state() {
+    let provider = MockProvider::start().await;
+    let oidc = provider.client();
+
+    let request = oidc.authorize().await.unwrap();
+    let code = provider.authorize(&request.url, true);
+    let result = oidc.exchange(&code, "forged", request.pending).await;
+
+    assert!(matches!(result, Err(OidcError::StateMismatch)));
+}
+
+#[tokio::test]
+async fn rejects_wrong_pkce_verifier() {
+    let provider = MockProvider::start().await;
+    let oidc = provider.client();
+
+    let request = oidc.authorize().await.unwrap();
+    let code = provider.authorize(&request.url, true);
+    let pending = PendingLogin {
+        pkce_verifier: "x".repeat(43),
+        ..request.pending
+    };
+    let state = pending.csrf_state.clone();
+    let result = oidc.exchange(&code, &state, pending).await;
+
+    assert!(matches!(result, Err(OidcError::Exchange(_))));
+}
+
+#[tokio::test]
+async fn rejects_id_token_for_another_nonce() {
+    let provider = MockProvider::start().await;
+    let oidc = provider.client();
+
+    let request = oidc.authorize().await.unwrap();
+    let code = provider.authorize(&request.url, true);
+    let pending = PendingLogin {
+        nonce: "replayed".into(),
+        ..request.pending
+    };
+    let state = pending.csrf_state.clone();
+    let result = oidc.exchange(&code, &state, pending).await;
+
+    assert!(matches!(result, Err(OidcError::InvalidIdToken(_))));
+}
+
+/// Starts an app that logs in through `provider`.
+async fn app_for(provider: &MockProvider) -> Option<TestApp> {
+    TestApp::with_settings(&format!(
+        r#"
+[oidc]
+issuer_url = {issuer:?}
+client_id = {CLIENT_ID:?}
+client_secret = {CLIENT_SECRET:?}
+redirect_url = {REDIRECT_URL:?}
+post_login_url = "http://localhost:3000/"
+"#,
+        issuer = provider.issuer,
+    ))
+    .await
+}
+
+/// The `name=value` part of the cookie `name` set by `response`.
+fn set_cookie(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|value| value.starts_with(&format!("{name}=")))
+        .map(str::to_owned)
+}
+
+/// Goes through the login in a browser that does not follow redirects, and
+/// returns the callback's response.
+async fn log_in(app: &TestApp, provider: &MockProvider) -> Response {
+    let client = Client::builder().redirect(Policy::none()).build().unwrap();
+    let response = client
+        .get(app.url("/api/auth/oidc/login"))
+        .send()
+        .await
+        .unwrap();
+    let pending = set_cookie(&response, "oidc_login").unwrap();
+    let location = response.headers()["location"].to_str().unwrap().to_owned();
+
+    let code = provider.authorize(&location, true);
+    let state = Url::parse(&location)
+        .unwrap()
+        .query_pairs()
+        .find(|(key, _)| key == "state")
+        .unwrap()
+        .1
+        .into_owned();
+    client
+        .get(app.url(&format!(
+            "/api/auth/oidc/callback?code={code}&state={state}"
+        )))
+        .header("cookie", pending.split(';').next().unwrap())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn hashes_passwords_of_new_accounts_with_the_configured_params() {
+    let email = format!("new-{}@corp.example", Uuid::new_v4());
+    let provider = MockProvider::start_for(&email, &email).await;
+    let Some(app) = app_for(&provider).await else {
+        return;
+    };
+
+    let response = log_in(&app, &provider).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["location"], "http://localhost:3000/");
+
+    let hash: String = users::table
+        .filter(users::email.eq(&email))
+        .select(users::password)
+        .first(&mut app.conn())
+        .unwrap();
+    assert!(!password::needs_rehash(&hash, &FAST_HASH));
+}
+
+#[tokio::test]
+async fn hands_the_two_factor_challenge_over_in_a_cookie() {
+    let email = format!("guarded-{}@corp.example", Uuid::new_v4());
+    let provider = MockProvider::start_for(&email, &email).await;
+    let Some(app) = app_for(&provider).await else {
+        return;
+    };
+    let user = create_user_with(&mut app.conn(), "Guarded", &email, &FAST_HASH);
+    diesel::update(users::table.find(user.id))
+        .set((
+            users::totp_secret.eq(TOTP_SECRET),
+            users::totp_enabled_at.eq(chrono::Utc::now()),
+        ))
+        .execute(&mut app.conn())
+        .unwrap();
+
+    let response = log_in(&app, &provider).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        response.headers()["location"],
+        "http://localhost:3000/?two_factor=required"
+    );
+    assert!(set_cookie(&response, "access_token").is_none());
+    let challenge = set_cookie(&response, "two_factor_challenge").unwrap();
+    assert!(challenge.contains("HttpOnly"));
+    assert!(challenge.contains("Path=/api/auth/login/2fa"));
+
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        Secret::Encoded(TOTP_SECRET.into()).to_bytes().unwrap(),
+        None,
+        String::new(),
+    )
+    .unwrap();
+    let code = totp.generate(chrono::Utc::now().timestamp() as u64);
+    let response = app
+        .post("/api/auth/login/2fa", json!({ "code": code }))
+        .header("cookie", challenge.split(';').next().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(set_cookie(&response, "access_token").is_some());
+    assert!(set_cookie(&response, "two_factor_challenge")
+        .unwrap()
+        .starts_with("two_factor_challenge=;"));
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use backend::auth::password::{self, HashParams};
+use backend::schema::users;
+use common::{TestApp, FAST_HASH, PASSWORD};
+use diesel::prelude::*;
+use openidconnect::reqwest::StatusCode;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Cheaper than [`FAST_HASH`], as if hashed before the settings were raised.
+const OLD_HASH: HashParams = HashParams {
+    memory_kib: 512,
+    iterations: 2,
+    parallelism: 1,
+};
+
+fn stored_hash(app: &TestApp, user_id: Uuid) -> String {
+    users::table
+        .find(user_id)
+        .select(users::password)
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rehashes_with_the_current_parameters_on_login() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let email = format!("outdated-{}@example.com", Uuid::new_v4());
+    let user = common::create_user_with(&mut app.conn(), "Outdated", &email, &OLD_HASH);
+    assert!(password::needs_rehash(&user.password, &FAST_HASH));
+
+    // A wrong password leaves the hash alone.
+    let response = app
+        .post(
+            "/api/auth/login",
+            json!({ "email": email, "password": "not the password" }),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(stored_hash(&app, user.id), user.password);
+
+    app.login(&email).await;
+    let upgraded = stored_hash(&app, user.id);
+    assert_ne!(upgraded, user.password);
+    assert!(!password::needs_rehash(&upgraded, &FAST_HASH));
+    assert!(password::verify(PASSWORD, &upgraded));
+
+    // Once upgraded, it is kept.
+    app.login(&email).await;
+    assert_eq!(stored_hash(&app, user.id), upgraded);
+}
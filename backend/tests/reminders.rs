@@ -0,0 +1,192 @@
+use backend::reminders::parse_time;
+use backend::reminders::recurrence::Recurrence;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+const BERLIN: Tz = Tz::Europe__Berlin;
+
+fn berlin(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+    BERLIN
+        .with_ymd_and_hms(y, m, d, h, min, 0)
+        .earliest()
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// The first `n` occurrences after `start`.
+fn occurrences(rule: &str, start: DateTime<Utc>, n: usize) -> Vec<DateTime<Utc>> {
+    let mut rule = rule.parse::<Recurrence>().unwrap().anchored(start, BERLIN);
+    let mut current = start;
+    let mut found = Vec::new();
+    while found.len() < n {
+        let Some((next, rest)) = rule.next(current, BERLIN) else {
+            break;
+        };
+        found.push(next);
+        current = next;
+        rule = rest;
+    }
+    found
+}
+
+#[test]
+fn keeps_the_local_time_across_daylight_saving_changes() {
+    let start = berlin(2026, 3, 28, 9, 0);
+    assert_eq!(
+        occurrences("daily", start, 2),
+        [berlin(2026, 3, 29, 9, 0), berlin(2026, 3, 30, 9, 0)]
+    );
+    // 08:00 UTC before the change, 07:00 UTC after it.
+    assert_eq!(start, Utc.with_ymd_and_hms(2026, 3, 28, 8, 0, 0).unwrap());
+    assert_eq!(
+        occurrences("daily", start, 1)[0],
+        Utc.with_ymd_and_hms(2026, 3, 29, 7, 0, 0).unwrap()
+    );
+}
+
+#[test]
+fn moves_times_in_a_gap_without_drifting() {
+    let start = berlin(2026, 3, 28, 2, 30);
+    assert_eq!(
+        occurrences("daily", start, 2),
+        [
+            // 02:30 does not exist that night.
+            berlin(2026, 3, 29, 3, 30),
+            berlin(2026, 3, 30, 2, 30),
+        ]
+    );
+}
+
+#[test]
+fn repeats_weekly_on_the_given_days() {
+    // A Monday.
+    let start = berlin(2026, 10, 19, 18, 0);
+    assert_eq!(
+        occurrences("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH", start, 4),
+        [
+            berlin(2026, 10, 22, 18, 0),
+            berlin(2026, 11, 2, 18, 0),
+            berlin(2026, 11, 5, 18, 0),
+            berlin(2026, 11, 16, 18, 0),
+        ]
+    );
+}
+
+#[test]
+fn filters_daily_rules_by_weekday() {
+    // A Friday.
+    let start = berlin(2026, 10, 23, 8, 0);
+    assert_eq!(
+        occurrences("RRULE:FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR", start, 2),
+        [berlin(2026, 10, 26, 8, 0), berlin(2026, 10, 27, 8, 0)]
+    );
+}
+
+#[test]
+fn skips_months_without_the_day() {
+    let start = berlin(2026, 1, 31, 12, 0);
+    assert_eq!(
+        occurrences("monthly", start, 3),
+        [
+            berlin(2026, 3, 31, 12, 0),
+            berlin(2026, 5, 31, 12, 0),
+            berlin(2026, 7, 31, 12, 0),
+        ]
+    );
+    let leap_day = berlin(2028, 2, 29, 12, 0);
+    assert_eq!(
+        occurrences("yearly", leap_day, 1),
+        [berlin(2032, 2, 29, 12, 0)]
+    );
+}
+
+#[test]
+fn stops_after_count_and_until() {
+    let start = berlin(2026, 10, 19, 9, 0);
+    assert_eq!(occurrences("FREQ=DAILY;COUNT=3", start, 10).len(), 2);
+    assert_eq!(
+        occurrences("FREQ=WEEKLY;UNTIL=20261102", start, 10),
+        [berlin(2026, 10, 26, 9, 0), berlin(2026, 11, 2, 9, 0)]
+    );
+    assert_eq!(
+        occurrences("FREQ=WEEKLY;UNTIL=20261102T075959Z", start, 10),
+        [berlin(2026, 10, 26, 9, 0)]
+    );
+}
+
+#[test]
+fn skips_missed_occurrences_and_counts_them() {
+    let rule = "FREQ=DAILY;COUNT=5"
+        .parse::<Recurrence>()
+        .unwrap()
+        .anchored(berlin(2026, 10, 19, 9, 0), BERLIN);
+    let (next, rest) = rule
+        .next_after(
+            berlin(2026, 10, 19, 9, 0),
+            berlin(2026, 10, 21, 12, 0),
+            BERLIN,
+        )
+        .unwrap();
+    assert_eq!(next, berlin(2026, 10, 22, 9, 0));
+    assert_eq!(rest.to_string(), "FREQ=DAILY;BYHOUR=9;BYMINUTE=0;COUNT=2");
+
+    let ended = rule.next_after(
+        berlin(2026, 10, 19, 9, 0),
+        berlin(2026, 11, 1, 0, 0),
+        BERLIN,
+    );
+    assert!(ended.is_none());
+}
+
+#[test]
+fn writes_canonical_rules() {
+    let rule: Recurrence = "rrule:freq=weekly;byday=fr,mo;interval=3;until=20271231"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        rule.to_string(),
+        "FREQ=WEEKLY;INTERVAL=3;BYDAY=MO,FR;UNTIL=20271231"
+    );
+    assert_eq!(rule.to_string().parse::<Recurrence>().unwrap(), rule);
+}
+
+#[test]
+fn rejects_unsupported_rules() {
+    for rule in [
+        "",
+        "hourly",
+        "FREQ=HOURLY",
+        "INTERVAL=2",
+        "FREQ=DAILY;INTERVAL=0",
+        "FREQ=DAILY;COUNT=2;UNTIL=20261231",
+        "FREQ=MONTHLY;BYDAY=1MO",
+        "FREQ=WEEKLY;BYDAY=XX",
+        "FREQ=DAILY;FREQ=WEEKLY",
+        "FREQ=DAILY;BYHOUR=9",
+        "FREQ=DAILY;BYSETPOS=1",
+        "FREQ=WEEKLY;WKST=SU",
+        "FREQ=DAILY;UNTIL=tomorrow",
+    ] {
+        assert!(rule.parse::<Recurrence>().is_err(), "{rule:?} was accepted");
+    }
+}
+
+#[test]
+fn reads_local_times_in_the_time_zone() {
+    assert_eq!(
+        parse_time("2026-10-19T09:00", BERLIN),
+        Some(berlin(2026, 10, 19, 9, 0))
+    );
+    assert_eq!(
+        parse_time("2026-10-19T09:00:00+02:00", Tz::UTC),
+        Some(Utc.with_ymd_and_hms(2026, 10, 19, 7, 0, 0).unwrap())
+    );
+    assert_eq!(parse_time("next week", BERLIN), None);
+}
+
+#[test]
+fn rejects_times_centuries_away() {
+    assert_eq!(parse_time("0001-01-01T00:00", BERLIN), None);
+    assert_eq!(parse_time("9999-12-31T23:59:59Z", Tz::UTC), None);
+    assert!(parse_time("2100-01-01T00:00", BERLIN).is_some());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::TestApp;
+use openidconnect::reqwest::{Response, StatusCode};
+use uuid::Uuid;
+
+fn request_id(response: &Response) -> &str {
+    response
+        .headers()
+        .get("x-request-id")
+        .expect("response without a request ID")
+        .to_str()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn assigns_an_id_to_every_request() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let first = app.get("/healthz").send().await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    let second = app.get("/api/account").send().await.unwrap();
+    assert_eq!(second.status(), StatusCode::UNAUTHORIZED);
+
+    let first = Uuid::parse_str(request_id(&first)).unwrap();
+    let second = Uuid::parse_str(request_id(&second)).unwrap();
+    assert_ne!(first, second);
+}
+
+#[tokio::test]
+async fn keeps_the_id_sent_by_the_client() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    for path in ["/healthz", "/api/no-such-route"] {
+        let response = app
+            .get(path)
+            .header("x-request-id", "from-the-proxy-42")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(request_id(&response), "from-the-proxy-42", "{path}");
+    }
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{json, TestApp, PASSWORD};
+use openidconnect::reqwest::StatusCode;
+use serde_json::Value;
+
+/// Logs in from a browser called `device`.
+async fn login_from(app: &TestApp, email: &str, device: &str) -> String {
+    let response = app
+        .post(
+            "/api/auth/login",
+            serde_json::json!({ "email": email, "password": PASSWORD }),
+        )
+        .header("user-agent", device)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    common::cookies(&response)
+}
+
+async fn account_status(app: &TestApp, cookie: &str) -> StatusCode {
+    app.get("/api/account")
+        .header("cookie", cookie)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn revoking_a_session_locks_it_out_at_once() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Traveller");
+    let laptop = login_from(&app, &user.email, "Laptop").await;
+    let phone = login_from(&app, &user.email, "Phone").await;
+    assert_eq!(account_status(&app, &phone).await, StatusCode::OK);
+
+    let response = app
+        .get("/api/account/sessions")
+        .header("cookie", &laptop)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let sessions: Vec<Value> = serde_json::from_value(json(response).await).unwrap();
+    assert_eq!(sessions.len(), 2);
+    let current = sessions.iter().find(|s| s["current"] == true).unwrap();
+    assert_eq!(current["user_agent"], "Laptop");
+    let phone_session = sessions.iter().find(|s| s["current"] == false).unwrap();
+    assert_eq!(phone_session["user_agent"], "Phone");
+
+    let response = app
+        .delete(&format!(
+            "/api/account/sessions/{}",
+            phone_session["id"].as_str().unwrap()
+        ))
+        .header("cookie", &laptop)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // The phone's access token has not expired, but it is refused anyway.
+    assert_eq!(account_status(&app, &phone).await, StatusCode::UNAUTHORIZED);
+    let response = app
+        .client
+        .post(app.url("/api/auth/refresh"))
+        .header("cookie", &phone)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(account_status(&app, &laptop).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn logs_out_everywhere_else() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let user = app.user("Careful");
+    let first = login_from(&app, &user.email, "First").await;
+    let second = login_from(&app, &user.email, "Second").await;
+    let third = login_from(&app, &user.email, "Third").await;
+
+    let response = app
+        .delete("/api/account/sessions")
+        .header("cookie", &third)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["revoked"], 2);
+
+    assert_eq!(account_status(&app, &first).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        account_status(&app, &second).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(account_status(&app, &third).await, StatusCode::OK);
+}
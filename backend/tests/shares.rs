@@ -0,0 +1,138 @@
+mod common;
+
+use common::{json, TestApp};
+use openidconnect::reqwest::{RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+
+fn share(app: &TestApp, note: &str, email: &str, permission: &str) -> RequestBuilder {
+    app.client
+        .put(app.url(&format!("/api/notes/{note}/shares")))
+        .header("content-type", "application/json")
+        .body(json!({ "email": email, "permission": permission }).to_string())
+}
+
+fn patch(app: &TestApp, note: &str, body: Value) -> RequestBuilder {
+    app.client
+        .patch(app.url(&format!("/api/notes/{note}")))
+        .header("content-type", "application/json")
+        .body(body.to_string())
+}
+
+async fn create_note(app: &TestApp, cookie: &str) -> String {
+    let response = app
+        .post(
+            "/api/notes",
+            json!({ "title": "Offsite", "tags": ["work"] }),
+        )
+        .header("cookie", cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    json(response).await["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn editors_change_only_the_title_and_content() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let (owner, editor) = (app.user("Ann"), app.user("Bob"));
+    let owner_cookie = app.login(&owner.email).await;
+    let editor_cookie = app.login(&editor.email).await;
+    let note = create_note(&app, &owner_cookie).await;
+
+    let response = share(&app, &note, &editor.email, "editor")
+        .header("cookie", &owner_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = patch(&app, &note, json!({ "content": "Thursday at ten" }))
+        .header("cookie", &editor_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = json(response).await;
+    assert_eq!(body["content"], "Thursday at ten");
+    assert_eq!(body["tags"], json!([]));
+
+    for filing in [
+        json!({ "pinned": true }),
+        json!({ "archived": true }),
+        json!({ "color": "red" }),
+        json!({ "tags": [] }),
+    ] {
+        let response = patch(&app, &note, filing)
+            .header("cookie", &editor_cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let response = app
+        .delete(&format!("/api/notes/{note}"))
+        .header("cookie", &editor_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // The owner still has their tags.
+    let response = app
+        .get(&format!("/api/notes/{note}"))
+        .header("cookie", &owner_cookie)
+        .send()
+        .await
+        .unwrap();
+    let body = json(response).await;
+    assert_eq!(body["content"], "Thursday at ten");
+    assert_eq!(body["tags"], json!(["work"]));
+
+    // Commenters cannot edit.
+    let response = share(&app, &note, &editor.email, "commenter")
+        .header("cookie", &owner_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = patch(&app, &note, json!({ "content": "Friday" }))
+        .header("cookie", &editor_cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn sharing_does_not_reveal_who_has_an_account() {
+    let Some(app) = TestApp::start().await else {
+        return;
+    };
+    let (owner, friend) = (app.user("Ann"), app.user("Bob"));
+    let cookie = app.login(&owner.email).await;
+    let note = create_note(&app, &cookie).await;
+
+    for email in [friend.email.as_str(), "nobody-here@example.com"] {
+        let response = share(&app, &note, email, "viewer")
+            .header("cookie", &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.text().await.unwrap(), "");
+    }
+
+    let response = app
+        .get(&format!("/api/notes/{note}/shares"))
+        .header("cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    let shares = json(response).await;
+    assert_eq!(shares.as_array().unwrap().len(), 1, "{shares}");
+    assert_eq!(shares[0]["email"], friend.email.as_str());
+    assert_eq!(shares[0]["permission"], "viewer");
+}
@@ -0,0 +1,255 @@
+//! Starts the server binary against the Postgres database from
+//! `DATABASE_URL` and shuts it down while requests are running. Skipped when
+//! `DATABASE_URL` is not set.
+#![cfg(unix)]
+
+use std::env;
+use std::net::SocketAddr;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use openidconnect::reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use uuid::Uuid;
+
+const PASSWORD: &str = "correct horse battery staple 42";
+
+struct Server {
+    process: Child,
+    addr: SocketAddr,
+    client: Client,
+}
+
+impl Server {
+    /// Starts the server with the given settings and waits until it answers.
+    async fn start(settings: &[(&str, &str)]) -> Option<Self> {
+        let Ok(database_url) = env::var("DATABASE_URL") else {
+            eprintln!("skipped: DATABASE_URL is not set");
+            return None;
+        };
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let dir = env::temp_dir().join(format!("notes-shutdown-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_backend"))
+            // Keeps a config.toml or .env of the checkout out of the test.
+            .current_dir(&dir)
+            .env_clear()
+            .env("DATABASE_URL", database_url)
+            .env("JWT_SECRET", "shutdown-test-secret-long-enough-to-sign")
+            .env("BIND_ADDR", addr.to_string())
+            .env("STORAGE_DIR", dir.join("storage"))
+            // Nothing listens there; sending mail fails without failing requests.
+            .env("SMTP_URL", "smtp://127.0.0.1:9")
+            .envs(settings.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let server = Server {
+            process,
+            addr,
+            client: Client::new(),
+        };
+
+        for _ in 0..100 {
+            if server
+                .client
+                .get(server.url("/healthz"))
+                .send()
+                .await
+                .is_ok()
+            {
+                return Some(server);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the server did not start");
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    fn terminate(&self) {
+        let pid = self.process.id().unwrap();
+        let status = std::process::Command::new("kill")
+            .args(["-TERM", &pid.to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    fn is_running(&mut self) -> bool {
+        self.process.try_wait().unwrap().is_none()
+    }
+
+    async fn exit_status(&mut self, within: Duration) -> ExitStatus {
+        tokio::time::timeout(within, self.process.wait())
+            .await
+            .expect("the server did not exit in time")
+            .unwrap()
+    }
+
+    async fn post_json(&self, path: &str, body: Value) -> Response {
+        self.client
+            .post(self.url(path))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// Signs up a new user and returns the access token cookie.
+    async fn sign_up(&self) -> String {
+        let email = format!("shutdown-{}@example.com", Uuid::new_v4());
+        let response = self
+            .post_json(
+                "/api/auth/register",
+                json!({ "name": "Shutdown", "email": email, "password": PASSWORD }),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = self
+            .post_json(
+                "/api/auth/login",
+                json!({ "email": email, "password": PASSWORD }),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find(|cookie| cookie.starts_with("access_token="))
+            .and_then(|cookie| cookie.split(';').next())
+            .expect("no access token cookie")
+            .to_string()
+    }
+
+    /// Sends the headers and the first half of a request that creates a
+    /// note, and returns the connection and the rest of the body. Waits a
+    /// moment so the server has accepted the connection before the test
+    /// signals it.
+    async fn start_note_save(&self, cookie: &str, title: &str) -> (TcpStream, Vec<u8>) {
+        let body = json!({ "title": title, "content": "Saved while shutting down" }).to_string();
+        let mut stream = TcpStream::connect(self.addr).await.unwrap();
+        let head = format!(
+            "POST /api/notes HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Cookie: {cookie}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            self.addr,
+            body.len()
+        );
+        let (first, rest) = body.as_bytes().split_at(body.len() / 2);
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(first).await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        (stream, rest.to_vec())
+    }
+}
+
+async fn read_response(stream: &mut TcpStream) -> String {
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn note_saves_in_flight_complete() {
+    let Some(mut server) = Server::start(&[("SHUTDOWN_DRAIN_DELAY_SECS", "0")]).await else {
+        return;
+    };
+    let cookie = server.sign_up().await;
+    let title = format!("In flight {}", Uuid::new_v4());
+    let (mut stream, rest) = server.start_note_save(&cookie, &title).await;
+
+    server.terminate();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(server.is_running(), "exited with a request in flight");
+    assert!(
+        TcpStream::connect(server.addr).await.is_err(),
+        "still accepts connections"
+    );
+
+    stream.write_all(&rest).await.unwrap();
+    let response = read_response(&mut stream).await;
+    assert!(
+        response.starts_with("HTTP/1.1 201"),
+        "unexpected response: {response}"
+    );
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    let note: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(note["title"], title);
+
+    assert!(server.exit_status(Duration::from_secs(5)).await.success());
+}
+
+#[tokio::test]
+async fn requests_are_cut_off_at_the_deadline() {
+    let Some(mut server) = Server::start(&[
+        ("SHUTDOWN_DRAIN_DELAY_SECS", "0"),
+        ("SHUTDOWN_TIMEOUT_SECS", "1"),
+    ])
+    .await
+    else {
+        return;
+    };
+    let cookie = server.sign_up().await;
+    // The rest of the body never comes.
+    let (mut stream, _) = server.start_note_save(&cookie, "Never finished").await;
+
+    server.terminate();
+    assert!(server.exit_status(Duration::from_secs(5)).await.success());
+    assert_eq!(read_response(&mut stream).await, "");
+}
+
+#[tokio::test]
+async fn keeps_serving_while_draining() {
+    let Some(mut server) = Server::start(&[("SHUTDOWN_DRAIN_DELAY_SECS", "2")]).await else {
+        return;
+    };
+    let cookie = server.sign_up().await;
+    let (mut stream, rest) = server.start_note_save(&cookie, "While draining").await;
+
+    server.terminate();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let response = server
+        .client
+        .get(server.url("/readyz"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["status"], "shutting_down");
+    let response = server
+        .client
+        .get(server.url("/healthz"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    stream.write_all(&rest).await.unwrap();
+    let response = read_response(&mut stream).await;
+    assert!(
+        response.starts_with("HTTP/1.1 201"),
+        "unexpected response: {response}"
+    );
+    assert!(server.exit_status(Duration::from_secs(5)).await.success());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use backend::auth::two_factor::{self, verify_code_at};
+use chrono::{DateTime, TimeZone, Utc};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+fn now() -> DateTime<Utc> {
+    // 15 seconds into step 59_246_580.
+    Utc.with_ymd_and_hms(2026, 4, 20, 12, 0, 15).unwrap()
+}
+
+/// The code an authenticator app shows `offset` steps away from `now()`.
+fn code(offset: i64) -> String {
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        Secret::Encoded(SECRET.into()).to_bytes().unwrap(),
+        None,
+        String::new(),
+    )
+    .unwrap();
+    totp.generate((now().timestamp() + offset * 30) as u64)
+}
+
+fn step(offset: i64) -> i64 {
+    now().timestamp() / 30 + offset
+}
+
+#[test]
+fn accepts_the_current_and_neighbouring_steps() {
+    for offset in [-1, 0, 1] {
+        assert_eq!(
+            verify_code_at(SECRET, &code(offset), None, now()).unwrap(),
+            Some(step(offset)),
+            "offset {offset}"
+        );
+    }
+    assert_eq!(
+        verify_code_at(SECRET, &format!(" {} ", code(0)), None, now()).unwrap(),
+        Some(step(0))
+    );
+}
+
+#[test]
+fn rejects_codes_further_out() {
+    for offset in [-3, -2, 2, 3] {
+        assert_eq!(
+            verify_code_at(SECRET, &code(offset), None, now()).unwrap(),
+            None,
+            "offset {offset}"
+        );
+    }
+    assert_eq!(
+        verify_code_at(SECRET, "000000x", None, now()).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn rejects_replayed_steps() {
+    let used = verify_code_at(SECRET, &code(0), None, now())
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        verify_code_at(SECRET, &code(0), Some(used), now()).unwrap(),
+        None
+    );
+    // Nor may an older code follow a newer one.
+    assert_eq!(
+        verify_code_at(SECRET, &code(-1), Some(used), now()).unwrap(),
+        None
+    );
+    assert_eq!(
+        verify_code_at(SECRET, &code(1), Some(used), now()).unwrap(),
+        Some(step(1))
+    );
+}
+
+#[test]
+fn steps_are_used_once_even_when_checked_concurrently() {
+    let Some(mut conn) = common::connect() else {
+        return;
+    };
+    let user = common::create_user(&mut conn, "Racing");
+    // Both requests verified the code against the same, older last step;
+    // only the first to record it wins.
+    assert!(two_factor::use_step(&mut conn, user.id, step(0)).unwrap());
+    assert!(!two_factor::use_step(&mut conn, user.id, step(0)).unwrap());
+    assert!(!two_factor::use_step(&mut conn, user.id, step(-1)).unwrap());
+    assert!(two_factor::use_step(&mut conn, user.id, step(1)).unwrap());
+}
+
+#[test]
+fn recovery_codes_work_once() {
+    let Some(mut conn) = common::connect() else {
+        return;
+    };
+    let user = common::create_user(&mut conn, "Recovering");
+    let codes = two_factor::generate_recovery_codes();
+    assert_eq!(codes.len(), two_factor::RECOVERY_CODE_COUNT);
+    two_factor::store_recovery_codes(&mut conn, user.id, &codes).unwrap();
+
+    // Case and spacing do not matter.
+    let typed = format!(" {} ", codes[3].to_uppercase());
+    assert!(two_factor::consume_recovery_code(&mut conn, user.id, &typed).unwrap());
+    assert!(!two_factor::consume_recovery_code(&mut conn, user.id, &codes[3]).unwrap());
+    assert!(!two_factor::consume_recovery_code(&mut conn, user.id, "aaaaa-bbbbb").unwrap());
+    assert!(two_factor::consume_recovery_code(&mut conn, user.id, &codes[4]).unwrap());
+
+    // New codes replace the old ones.
+    let fresh = two_factor::generate_recovery_codes();
+    two_factor::store_recovery_codes(&mut conn, user.id, &fresh).unwrap();
+    assert!(!two_factor::consume_recovery_code(&mut conn, user.id, &codes[5]).unwrap());
+    assert!(two_factor::consume_recovery_code(&mut conn, user.id, &fresh[0]).unwrap());
+}